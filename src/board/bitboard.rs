@@ -3,11 +3,12 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 //! Contains a number of utility functions and constants for bitboard operations.
-#[cfg(all(target_arch="x86", target_feature="bmi2"))]
-use std::arch::x86::{ _pext_u32, _pdep_u32 };
+//!
+//! `get_flip` and `all_moves` have faster versions using BMI2 and AVX2, which are chosen at
+//! runtime by `cpu::CpuFeatures`. The portable versions are always available, and every version
+//! gives the same results.
 
-#[cfg(all(target_arch="x86_64", target_feature="bmi2"))]
-use std::arch::x86_64::{ _pext_u64, _pdep_u64 };
+use super::cpu;
 
 /// The 'A' file of the board.
 pub const FILE_A: u64 = 0x80_80_80_80_80_80_80_80;
@@ -521,7 +522,21 @@ pub fn directional_moves(player: u64, mask: u64, dir: i8) -> u64 {
 /// # Arguments:
 /// * `player`: The bitboard representing the player's disks.
 /// * `opponent`: The bitboard representing the opponent's disks.
+#[inline]
 pub fn all_moves(player: u64, opponent: u64) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if cpu::has_avx2() {
+            // Only reached when the CPU supports AVX2.
+            return unsafe { all_moves_avx2(player, opponent) };
+        }
+    }
+
+    all_moves_generic(player, opponent)
+}
+
+/// The portable version of `all_moves`, which handles one direction at a time.
+pub fn all_moves_generic(player: u64, opponent: u64) -> u64 {
     let mut all_moves: u64 = 0;
 
     let masked = opponent & !(FILE_A | FILE_H);
@@ -533,7 +548,145 @@ pub fn all_moves(player: u64, opponent: u64) -> u64 {
     all_moves & !(player | opponent)
 }
 
-#[cfg(all(target_arch="x86_64", target_feature="bmi2"))]
+/// The AVX2 version of `all_moves`. Each 64-bit lane handles one of the four lines through a
+/// square, in both directions at once, using the same doubling steps as `directional_moves`.
+/// # Safety
+/// * The CPU must support AVX2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub unsafe fn all_moves_avx2(player: u64, opponent: u64) -> u64 {
+    use std::arch::x86_64::*;
+
+    // The lanes shift by 1 (horizontal), 8 (vertical), 7 and 9 (diagonals). All but the vertical
+    // lane must not wrap around between the A and H files.
+    let shift = _mm256_set_epi64x(1, 8, 7, 9);
+    let shift_2 = _mm256_add_epi64(shift, shift);
+    let edges = _mm256_set_epi64x(!(FILE_A | FILE_H) as i64, -1, !(FILE_A | FILE_H) as i64, !(FILE_A | FILE_H) as i64);
+
+    let pp = _mm256_set1_epi64x(player as i64);
+    let mo = _mm256_and_si256(_mm256_set1_epi64x(opponent as i64), edges);
+
+    // Runs of the opponent's disks next to the player's, growing to lengths of 2, 4 and 6.
+    let mut flip_l = _mm256_and_si256(mo, _mm256_sllv_epi64(pp, shift));
+    let mut flip_r = _mm256_and_si256(mo, _mm256_srlv_epi64(pp, shift));
+    flip_l = _mm256_or_si256(flip_l, _mm256_and_si256(mo, _mm256_sllv_epi64(flip_l, shift)));
+    flip_r = _mm256_or_si256(flip_r, _mm256_and_si256(mo, _mm256_srlv_epi64(flip_r, shift)));
+
+    let pre_l = _mm256_and_si256(mo, _mm256_sllv_epi64(mo, shift));
+    let pre_r = _mm256_srlv_epi64(pre_l, shift);
+    for _ in 0..2 {
+        flip_l = _mm256_or_si256(flip_l, _mm256_and_si256(pre_l, _mm256_sllv_epi64(flip_l, shift_2)));
+        flip_r = _mm256_or_si256(flip_r, _mm256_and_si256(pre_r, _mm256_srlv_epi64(flip_r, shift_2)));
+    }
+
+    let moves = _mm256_or_si256(_mm256_sllv_epi64(flip_l, shift), _mm256_srlv_epi64(flip_r, shift));
+    let moves = _mm_or_si128(_mm256_castsi256_si128(moves), _mm256_extracti128_si256(moves, 1));
+    let moves = _mm_or_si128(moves, _mm_unpackhi_epi64(moves, moves));
+
+    _mm_cvtsi128_si64(moves) as u64 & !(player | opponent)
+}
+
+/// The four corners of the board.
+pub const CORNERS: u64 = 0x81_00_00_00_00_00_00_81;
+/// The edges of the board, including the corners.
+pub const EDGES: u64 = RANK_1 | RANK_8 | FILE_A | FILE_H;
+
+/// A function which finds every square adjacent to at least one of the given squares.
+/// # Arguments:
+/// * `x`: The squares to find the neighbours of.
+/// # Returns:
+/// * A mask of the squares adjacent to `x`, which may include squares in `x`.
+pub fn neighbours(x: u64) -> u64 {
+    let mut result = 0;
+    for i in 0..SHIFT_DIRS.len() {
+        result |= directional_shift(x & SHIFT_MASKS[i], SHIFT_DIRS[i]);
+    }
+    result
+}
+
+/// A function which finds the disks that are stable because they are connected to an occupied
+/// corner by an unbroken line of the same player's disks along an edge.
+/// # Arguments:
+/// * `player`: The bitboard representing the player's disks.
+/// # Returns:
+/// * A mask of the player's disks which are stable along the edges.
+pub fn edge_stable(player: u64) -> u64 {
+    let horizontal = player & (RANK_1 | RANK_8);
+    let vertical = player & (FILE_A | FILE_H);
+
+    let mut stable = player & CORNERS;
+    for _ in 0..6 {
+        stable |= horizontal & ((stable & !FILE_A) << 1 | (stable & !FILE_H) >> 1);
+        stable |= vertical & ((stable & !RANK_1) << 8 | (stable & !RANK_8) >> 8);
+    }
+
+    stable
+}
+
+/// The pairs of opposite directions (indices into SHIFT_DIRS) making up the four lines through a
+/// square: vertical, horizontal, diagonal and anti-diagonal.
+pub const LINE_DIRS: [(usize, usize); 4] = [ (0, 1), (2, 3), (4, 7), (5, 6) ];
+
+/// The squares where each of the lines in LINE_DIRS runs off the board on at least one side.
+const LINE_BORDERS: [u64; 4] = [ RANK_1 | RANK_8, FILE_A | FILE_H, EDGES, EDGES ];
+
+/// A function which finds the squares whose lines are completely filled with disks, for each of
+/// the four lines through a square. A disk on a full line can never be flipped along that line.
+/// # Arguments:
+/// * `occupied`: The bitboard representing all disks on the board.
+/// # Returns:
+/// * A mask of the squares on full lines, for each line in the order of LINE_DIRS.
+pub fn full_lines(occupied: u64) -> [u64; 4] {
+    let mut full = [0; 4];
+    let empty = !occupied;
+
+    for (line, &(a, b)) in LINE_DIRS.iter().enumerate() {
+        // Spread each empty square along the line in both directions, to find the lines that have
+        // an empty square somewhere on them.
+        let mut open = empty;
+        for _ in 0..7 {
+            open |= directional_shift(open & SHIFT_MASKS[a], SHIFT_DIRS[a]);
+            open |= directional_shift(open & SHIFT_MASKS[b], SHIFT_DIRS[b]);
+        }
+        full[line] = !open;
+    }
+
+    full
+}
+
+/// A function which finds all of the disks which can never be flipped for the rest of the game.
+/// A disk is stable if, along each of the four lines through it, it is on a full line, on the edge
+/// of the board, or next to a stable disk of the same color. Disks anchored to a corner along an
+/// edge are found first, and stability is then propagated inward until nothing changes.
+/// # Arguments:
+/// * `player`: The bitboard representing the player's disks.
+/// * `opponent`: The bitboard representing the opponent's disks.
+/// # Returns:
+/// * A mask of the player's stable disks.
+pub fn stable_disks(player: u64, opponent: u64) -> u64 {
+    let full = full_lines(player | opponent);
+
+    let mut fixed = [0; 4];
+    for line in 0..4 {
+        fixed[line] = full[line] | LINE_BORDERS[line];
+    }
+
+    let mut stable = edge_stable(player);
+    loop {
+        let mut candidates = player & !stable;
+        for (line, &(a, b)) in LINE_DIRS.iter().enumerate() {
+            let beside_stable = directional_shift(stable & SHIFT_MASKS[a], SHIFT_DIRS[a])
+                | directional_shift(stable & SHIFT_MASKS[b], SHIFT_DIRS[b]);
+            candidates &= fixed[line] | beside_stable;
+        }
+
+        if candidates == 0 {
+            return stable;
+        }
+        stable |= candidates;
+    }
+}
+
 /// A function which generates a mask representing all of the disks that will be flipped when the
 /// given move is made. Panics if pos > 63.
 /// # Arguments:
@@ -542,20 +695,32 @@ pub fn all_moves(player: u64, opponent: u64) -> u64 {
 /// * `opponent`: The bitboard representing the opponent's disks.
 /// # Returns:
 /// * A mask of the disks flipped when the given move is made.
+#[inline]
 pub fn get_flip(pos: usize, player: u64, opponent: u64) -> u64 {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if cpu::has_bmi2() {
+            // Only reached when the CPU supports BMI2.
+            return unsafe { get_flip_bmi2(pos, player, opponent) };
+        }
+    }
+
+    get_flip_generic(pos, player, opponent)
+}
+
+/// The BMI2 version of `get_flip`, which extracts the four lines through the move with PEXT and
+/// looks up the flips in tables.
+/// # Safety
+/// * The CPU must support BMI2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+pub unsafe fn get_flip_bmi2(pos: usize, player: u64, opponent: u64) -> u64 {
     let masks = EXTRACT_RAYS[pos];
     const NOT_EDGES_H : u64 = !0x81_81_81_81_81_81_81_81;
     const NOT_EDGES_V : u64 = !0xFF_00_00_00_00_00_00_FF;
     const NOT_EDGES_D : u64 = !0xFF_81_81_81_81_81_81_FF;
 
-    // PEXT and PDEP will not cause UB on systems which have them
-    let pext = | a, mask | unsafe {
-        _pext_u64(a, mask)
-    };
-
-    let pdep = | a, mask | unsafe {
-        _pdep_u64(a, mask)
-    };
+    use std::arch::x86_64::{ _pext_u64 as pext, _pdep_u64 as pdep };
 
     let ph: u64 = pext(player, masks[0]);
     let pv: u64 = pext(player, masks[1]);
@@ -579,32 +744,22 @@ pub fn get_flip(pos: usize, player: u64, opponent: u64) -> u64 {
     let md = pdep(fd as u64, masks[2]);
     let ma = pdep(fa as u64, masks[3]);
 
-    (mh | mv | md | ma) as u64
+    // The tables leave out the move itself when nothing is flipped.
+    mh | mv | md | ma | (0x80_00_00_00_00_00_00_00 >> pos)
 }
 
-#[cfg(all(target_arch="x86", target_feature="bmi2"))]
-/// A function which generates a mask representing all of the disks that will be flipped when the
-/// given move is made. Panics if pos > 63.
-/// # Arguments:
-/// * `pos`: The position of the move being made.
-/// * `player`: The bitboard representing the player's disks.
-/// * `opponent`: The bitboard representing the opponent's disks.
-/// # Returns:
-/// * A mask of the disks flipped when the given move is made.
-pub fn get_flip(pos: usize, player: u64, opponent: u64) -> u64 {
+/// The BMI2 version of `get_flip` for 32-bit x86, which splits each line into two halves.
+/// # Safety
+/// * The CPU must support BMI2.
+#[cfg(target_arch = "x86")]
+#[target_feature(enable = "bmi2")]
+pub unsafe fn get_flip_bmi2(pos: usize, player: u64, opponent: u64) -> u64 {
     let masks = EXTRACT_RAYS[pos];
     const NOT_EDGES_H: u64 = !0x81_81_81_81_81_81_81_81;
     const NOT_EDGES_V: u64 = !0xFF_00_00_00_00_00_00_FF;
     const NOT_EDGES_D: u64 = !0xFF_81_81_81_81_81_81_FF;
 
-    // PEXT and PDEP will not cause UB on systems which have them
-    let pext = | a, mask | unsafe {
-        _pext_u32(a, mask)
-    };
-
-    let pdep = | a, mask | unsafe {
-        _pdep_u32(a, mask)
-    };
+    use std::arch::x86::{ _pext_u32 as pext, _pdep_u32 as pdep };
 
     let ph0 = pext(player as u32, masks[0] as u32);
     let pv0 = pext(player as u32, masks[1] as u32);
@@ -656,19 +811,13 @@ pub fn get_flip(pos: usize, player: u64, opponent: u64) -> u64 {
     let md1 = pdep((fd as u64 >> SHIFTS_P[7 - x + y]) as u32, (masks[2] >> 32) as u32) as u64;
     let ma1 = pdep((fa as u64 >> SHIFTS_P[x + y]) as u32,     (masks[3] >> 32) as u32) as u64;
 
+    // The tables leave out the move itself when nothing is flipped.
     (mh0 | mv0 | md0 | ma0  |  (mh1 << 32) | (mv1 << 32) | (md1 << 32) | (ma1 << 32)) as u64
+        | (0x80_00_00_00_00_00_00_00 >> pos)
 }
 
-#[cfg(not(target_feature="bmi2"))]
-/// A function which generates a mask representing all of the disks that will be flipped when the
-/// given move is made. Panics if pos > 63.
-/// # Arguments:
-/// * `pos`: The position of the move being made.
-/// * `player`: The bitboard representing the player's disks.
-/// * `opponent`: The bitboard representing the opponent's disks.
-/// # Returns:
-/// * A mask of the disks flipped when the given move is made.
-pub fn get_flip(pos: usize, player: u64, opponent: u64) -> u64 {
+/// The portable version of `get_flip`, which floods out from the move in each direction.
+pub fn get_flip_generic(pos: usize, player: u64, opponent: u64) -> u64 {
     let disk = 0x80_00_00_00_00_00_00_00 >> pos;
     let mut flood = 0;
     let player = player | disk;
@@ -693,6 +842,44 @@ pub fn get_flip(pos: usize, player: u64, opponent: u64) -> u64 {
     flood | disk
 }
 
+/// A function which mirrors a bitboard from top to bottom, swapping rank 1 with rank 8.
+pub fn flip_vertical(x: u64) -> u64 {
+    x.swap_bytes()
+}
+
+/// A function which mirrors a bitboard from left to right, swapping file A with file H.
+pub fn flip_horizontal(x: u64) -> u64 {
+    const K1: u64 = 0x5555555555555555;
+    const K2: u64 = 0x3333333333333333;
+    const K4: u64 = 0x0f0f0f0f0f0f0f0f;
+
+    let mut x = x;
+    x = ((x >> 1) & K1) | ((x & K1) << 1);
+    x = ((x >> 2) & K2) | ((x & K2) << 2);
+    x = ((x >> 4) & K4) | ((x & K4) << 4);
+
+    x
+}
+
+/// A function which mirrors a bitboard along the a1-h8 diagonal, swapping a2 with b1.
+pub fn flip_diag(x: u64) -> u64 {
+    const K1: u64 = 0x5500550055005500;
+    const K2: u64 = 0x3333000033330000;
+    const K4: u64 = 0x0f0f0f0f00000000;
+
+    let mut t;
+    let mut x = x;
+
+    t  = K4 & (x ^ (x << 28));
+    x ^=       t ^ (t >> 28) ;
+    t  = K2 & (x ^ (x << 14));
+    x ^=       t ^ (t >> 14) ;
+    t  = K1 & (x ^ (x <<  7));
+    x ^=       t ^ (t >>  7) ;
+
+    x
+}
+
 #[cfg(test)]
 mod test {
     #[test]
@@ -723,6 +910,50 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_edge_stable() {
+        // a1-d1 and a1-a3 are anchored by a1, but f1-g1 and h8 are not connected to a corner.
+        let player = 0xF6_80_80_00_00_00_00_00;
+        assert_eq!(super::edge_stable(player), 0xF0_80_80_00_00_00_00_00);
+        assert_eq!(super::edge_stable(player | 0x01), 0xF0_80_80_00_00_00_00_01);
+    }
+
+    #[test]
+    fn test_full_lines() {
+        // Rank 1 and the a1-h8 diagonal are full, as are the one-square diagonals through the
+        // occupied corners.
+        let occupied = 0xFF_40_20_10_08_04_02_01;
+        let full = super::full_lines(occupied);
+
+        assert_eq!(full[0], 0);
+        assert_eq!(full[1], 0xFF_00_00_00_00_00_00_00);
+        assert_eq!(full[2], 0x81_40_20_10_08_04_02_01);
+        assert_eq!(full[3], 0x80_00_00_00_00_00_00_01);
+    }
+
+    #[test]
+    fn test_stable_disks() {
+        // Anchored edges are stable, but the disk on b2 is not, since c3 is empty.
+        let player = 0xF0_C0_80_00_00_00_00_00;
+        assert_eq!(super::stable_disks(player, 0), 0xF0_C0_80_00_00_00_00_00);
+
+        // A disk on h1 alone is stable, but the opponent's run next to it is not.
+        assert_eq!(super::stable_disks(0x01_00_00_00_00_00_00_00, 0x0E_00_00_00_00_00_00_00), 0x01_00_00_00_00_00_00_00);
+        assert_eq!(super::stable_disks(0x0E_00_00_00_00_00_00_00, 0x01_00_00_00_00_00_00_00), 0);
+
+        // On a full board every disk is stable.
+        assert_eq!(super::stable_disks(0xAA_55_AA_55_AA_55_AA_55, 0x55_AA_55_AA_55_AA_55_AA), 0xAA_55_AA_55_AA_55_AA_55);
+
+        // In the opening nothing is stable.
+        assert_eq!(super::stable_disks(0x00_00_00_08_10_00_00_00, 0x00_00_00_10_08_00_00_00), 0);
+    }
+
+    #[test]
+    fn test_neighbours() {
+        assert_eq!(super::neighbours(0x80_00_00_00_00_00_00_00), 0x40_C0_00_00_00_00_00_00);
+        assert_eq!(super::neighbours(0x00_00_00_10_00_00_00_00), 0x00_00_38_28_38_00_00_00);
+    }
+
     #[test]
     fn test_all_moves() {
         let player = 0x00_0C_04_04_86_04_02_00;
@@ -730,4 +961,56 @@ mod test {
 
         assert_eq!(super::all_moves(player, opponent), 0x00_72_00_80_40_60_20_00);
     }
+
+    fn check_dispatch(player: u64, opponent: u64) {
+        let moves = super::all_moves_generic(player, opponent);
+        assert_eq!(super::all_moves(player, opponent), moves);
+        #[cfg(target_arch = "x86_64")]
+        {
+            if super::cpu::has_avx2() {
+                assert_eq!(unsafe { super::all_moves_avx2(player, opponent) }, moves);
+            }
+        }
+
+        for pos in 0..64 {
+            if (player | opponent) & (0x80_00_00_00_00_00_00_00 >> pos) != 0 {
+                continue;
+            }
+
+            let flip = super::get_flip_generic(pos, player, opponent);
+            assert_eq!(super::get_flip(pos, player, opponent), flip);
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            {
+                if super::cpu::has_bmi2() {
+                    assert_eq!(unsafe { super::get_flip_bmi2(pos, player, opponent) }, flip);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_dispatch_matches_generic() {
+        use rand::Rng;
+        use crate::board::Board;
+        use crate::board::compact::CompactBoard;
+
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..1000 {
+            let player = rng.gen::<u64>();
+            let opponent = rng.gen::<u64>() & !player;
+            check_dispatch(player, opponent);
+            check_dispatch(player & rng.gen::<u64>(), opponent & rng.gen::<u64>());
+        }
+
+        for _ in 0..20 {
+            let mut board = CompactBoard::from(&Board::new());
+            while !board.is_game_over() {
+                check_dispatch(board.player, board.opponent);
+
+                let moves = board.get_moves();
+                board = board.play(moves[rng.gen_range(0, moves.len())]);
+            }
+        }
+    }
 }
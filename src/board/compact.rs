@@ -0,0 +1,203 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A compact position for searching, which holds the disks of the player to move and of their
+//! opponent. It is small and `Copy`, so moves are made by returning a new position instead of
+//! being made and undone, and nothing needs to branch on which color is to move. The color and
+//! the Zobrist key are carried along, so that the position can be hashed and converted back.
+//!
+//! Scores of compact positions are always relative to the player to move. Convert to and from a
+//! `Board` at the edges of a search, where the colors matter.
+
+use super::{ bitboard, zobrist, Board, Move };
+use super::movelist::MoveList;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CompactBoard {
+    /// The disks of the player to move.
+    pub player: u64,
+    /// The disks of the player who just moved.
+    pub opponent: u64,
+    /// Whether the player to move is black.
+    pub black_move: bool,
+    hash: u64
+}
+
+impl CompactBoard {
+    pub fn new(player: u64, opponent: u64, black_move: bool) -> CompactBoard {
+        let hash = if black_move {
+            zobrist::hash(player, opponent, true)
+        } else {
+            zobrist::hash(opponent, player, false)
+        };
+
+        CompactBoard { player, opponent, black_move, hash }
+    }
+
+    /// Converts back to a board.
+    pub fn to_board(self) -> Board {
+        if self.black_move {
+            Board::from_pos(self.player, self.opponent, true)
+        } else {
+            Board::from_pos(self.opponent, self.player, false)
+        }
+    }
+
+    /// A function which generates a mask of the squares where the player to move can play.
+    #[inline]
+    pub fn moves(&self) -> u64 {
+        bitboard::all_moves(self.player, self.opponent)
+    }
+
+    /// A function which generates all of the moves that the player to move can make.
+    /// # Returns:
+    /// * A list of the moves, or just `Move::Pass` if the player has no other move.
+    pub fn get_moves(&self) -> MoveList {
+        let mut all_moves = self.moves();
+        let mut moves = MoveList::new();
+
+        if all_moves == 0 {
+            moves.push(Move::Pass);
+        }
+        while all_moves != 0 {
+            let index = all_moves.leading_zeros();
+            moves.push(Move::Play(index as u8));
+            all_moves ^= 0x80_00_00_00_00_00_00_00 >> index;
+        }
+
+        moves
+    }
+
+    #[inline]
+    pub fn move_count(&self) -> u32 {
+        self.moves().count_ones()
+    }
+
+    /// Counts the moves the opponent would have after the given move.
+    #[inline]
+    pub fn move_count_after(&self, m: Move) -> u32 {
+        self.play(m).move_count()
+    }
+
+    /// Makes a move for the player to move. Does not check if the move is legal.
+    /// # Returns:
+    /// * The position after the move, from the opponent's point of view.
+    #[inline]
+    pub fn play(&self, m: Move) -> CompactBoard {
+        match m {
+            Move::Play(square) => {
+                let flipped = bitboard::get_flip(square as usize, self.player, self.opponent);
+                let disk = 0x80_00_00_00_00_00_00_00 >> square;
+                let keys = if self.black_move { &zobrist::BLACK_KEYS } else { &zobrist::WHITE_KEYS };
+                CompactBoard {
+                    player: self.opponent & !flipped,
+                    opponent: self.player | flipped,
+                    black_move: !self.black_move,
+                    hash: self.hash ^ keys[63 - square as usize] ^ zobrist::hash_flips(flipped ^ disk) ^ zobrist::SIDE_KEY
+                }
+            }
+            Move::Pass => self.pass()
+        }
+    }
+
+    /// Passes the turn to the opponent.
+    #[inline]
+    pub fn pass(&self) -> CompactBoard {
+        CompactBoard {
+            player: self.opponent,
+            opponent: self.player,
+            black_move: !self.black_move,
+            hash: self.hash ^ zobrist::SIDE_KEY
+        }
+    }
+
+    #[inline]
+    pub fn all_disks(&self) -> u64 {
+        self.player | self.opponent
+    }
+
+    #[inline]
+    pub fn empties(&self) -> u32 {
+        self.all_disks().count_zeros()
+    }
+
+    /// Checks whether neither player can move.
+    pub fn is_game_over(&self) -> bool {
+        self.moves() == 0 && self.pass().moves() == 0
+    }
+
+    /// The player's disks minus the opponent's, not counting the empty squares.
+    #[inline]
+    pub fn disk_difference(&self) -> i32 {
+        self.player.count_ones() as i32 - self.opponent.count_ones() as i32
+    }
+
+    /// A mask of the opponent's disks which can never be flipped.
+    pub fn opponent_stable(&self) -> u64 {
+        bitboard::stable_disks(self.opponent, self.player)
+    }
+
+    /// The Zobrist key of the position, which is the same as the key of the equivalent `Board`
+    /// and is kept up to date as moves are played.
+    #[inline]
+    pub fn hash_key(&self) -> u64 {
+        self.hash
+    }
+}
+
+impl From<&Board> for CompactBoard {
+    fn from(board: &Board) -> CompactBoard {
+        let (player, opponent) = if board.black_move {
+            (board.black_disks, board.white_disks)
+        } else {
+            (board.white_disks, board.black_disks)
+        };
+
+        CompactBoard { player, opponent, black_move: board.black_move, hash: board.hash_key() }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+
+    use crate::board::Board;
+    use super::CompactBoard;
+
+    #[test]
+    fn test_matches_board() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..50 {
+            let mut board = Board::new();
+            let mut compact = CompactBoard::from(&board);
+
+            while !board.is_game_over() {
+                assert_eq!(compact.to_board().get_position(), board.get_position());
+                assert_eq!(compact.hash_key(), board.hash_key());
+                assert!(!compact.is_game_over());
+
+                let moves = board.get_moves();
+                assert_eq!(compact.get_moves().len(), moves.len());
+                for m in &moves {
+                    assert!(compact.get_moves().contains(m));
+                    assert_eq!(compact.move_count_after(m), board.move_count_after(m));
+                }
+
+                let m = moves[rng.gen_range(0, moves.len())];
+                board.make_move(m);
+                compact = compact.play(m);
+            }
+
+            assert!(compact.is_game_over());
+            assert_eq!(compact.disk_difference(), if board.black_move { board.get_score() } else { -board.get_score() });
+            assert_eq!(compact.empties(), board.all_disks().count_zeros());
+        }
+
+        // Passing swaps the sides, so must change the hash key.
+        let start = CompactBoard::from(&Board::new());
+        assert_ne!(start.hash_key(), start.pass().hash_key());
+        assert_eq!(start.pass().pass(), start);
+    }
+}
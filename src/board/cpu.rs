@@ -0,0 +1,88 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Detects the instruction set extensions used by the fast bitboard routines. Detection runs once,
+//! the first time a feature is checked, and the result is cached so that the hot paths only pay
+//! for a single load and branch.
+//!
+//! Builds which enable a feature at compile time (e.g. with `-C target-cpu=native`) skip the check
+//! for it entirely.
+
+use std::fmt;
+use std::sync::atomic::{ AtomicU8, Ordering };
+
+const DETECTED: u8 = 1;
+const BMI2: u8 = 2;
+const AVX2: u8 = 4;
+
+static FEATURES: AtomicU8 = AtomicU8::new(0);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CpuFeatures {
+    /// PEXT and PDEP, used to flip disks and to index patterns.
+    pub bmi2: bool,
+    /// 256-bit integer vectors, used to generate moves in four directions at once.
+    pub avx2: bool
+}
+
+impl CpuFeatures {
+    /// Returns the features of the current CPU, detecting them on the first call.
+    #[inline]
+    pub fn get() -> CpuFeatures {
+        let mut bits = FEATURES.load(Ordering::Relaxed);
+        if bits == 0 {
+            bits = DETECTED | CpuFeatures::detect().bits();
+            FEATURES.store(bits, Ordering::Relaxed);
+        }
+
+        CpuFeatures {
+            bmi2: cfg!(target_feature = "bmi2") || bits & BMI2 != 0,
+            avx2: cfg!(target_feature = "avx2") || bits & AVX2 != 0
+        }
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn detect() -> CpuFeatures {
+        CpuFeatures {
+            bmi2: is_x86_feature_detected!("bmi2"),
+            avx2: is_x86_feature_detected!("avx2")
+        }
+    }
+
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    fn detect() -> CpuFeatures {
+        CpuFeatures::default()
+    }
+
+    fn bits(self) -> u8 {
+        (if self.bmi2 { BMI2 } else { 0 }) | (if self.avx2 { AVX2 } else { 0 })
+    }
+}
+
+impl fmt::Display for CpuFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = [(self.bmi2, "bmi2"), (self.avx2, "avx2")].iter()
+            .filter(|&&(present, _)| present)
+            .map(|&(_, name)| name)
+            .collect();
+
+        if names.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", names.join(" "))
+        }
+    }
+}
+
+/// Checks for BMI2, the same as `CpuFeatures::get().bmi2`.
+#[inline]
+pub fn has_bmi2() -> bool {
+    CpuFeatures::get().bmi2
+}
+
+/// Checks for AVX2, the same as `CpuFeatures::get().avx2`.
+#[inline]
+pub fn has_avx2() -> bool {
+    CpuFeatures::get().avx2
+}
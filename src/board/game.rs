@@ -0,0 +1,247 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A record of a complete game, from its starting position through every move played, passes
+//! included. Moves can be undone and redone, and games can be read from and written to the usual
+//! transcript format, where the moves are written one after another (`f5d6c3...`) and passes are
+//! left out.
+
+use std::fmt;
+
+use crate::{ Error, Result };
+use super::{ Board, Move };
+
+/// A move in a game, along with anything known about it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameMove {
+    pub m: Move,
+    /// The score given to the move by whoever played it, relative to them, in centi-disks.
+    pub score: Option<i32>,
+    /// The time taken to choose the move, in millis.
+    pub time: Option<u32>
+}
+
+impl GameMove {
+    pub fn new(m: Move) -> GameMove {
+        GameMove {
+            m,
+            score: None,
+            time: None
+        }
+    }
+
+    pub fn with_score(mut self, score: i32) -> GameMove {
+        self.score = Some(score);
+        self
+    }
+
+    pub fn with_time(mut self, time: u32) -> GameMove {
+        self.time = Some(time);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    start: Board,
+    board: Board,
+    /// Every move of the game, including any which have been undone and can be redone.
+    moves: Vec<GameMove>,
+    /// The undo information for each move up to the current position.
+    undos: Vec<u64>
+}
+
+impl Game {
+    /// Creates a game in the standard starting position.
+    pub fn new() -> Game {
+        Game::from_board(Board::new())
+    }
+
+    /// Creates a game which starts from the given position.
+    pub fn from_board(start: Board) -> Game {
+        Game {
+            board: start.clone(),
+            start,
+            moves: Vec::new(),
+            undos: Vec::new()
+        }
+    }
+
+    /// Parses a transcript of moves played from the standard starting position.
+    /// # Arguments:
+    /// * `transcript`: The moves, as coordinates one after another, e.g. `f5d6c3`. Whitespace is
+    ///   ignored, and passes are inserted wherever the player to move has no move.
+    /// # Returns:
+    /// * The game, or an error if a coordinate is malformed or a move is illegal.
+    pub fn from_transcript(transcript: &str) -> Result<Game> {
+        let mut game = Game::new();
+
+        let chars: Vec<char> = transcript.chars().filter(|c| !c.is_whitespace()).collect();
+        if !chars.len().is_multiple_of(2) {
+            return Err(Error::Parse(format!("transcript has an odd number of characters: '{}'", transcript)));
+        }
+
+        for pair in chars.chunks(2) {
+            let coord: String = pair.iter().collect();
+            let m = Move::from_coord(&coord)?;
+
+            if game.board.move_count() == 0 && !game.board.is_game_over() {
+                game.play(Move::Pass)?;
+            }
+            game.play(m)?;
+        }
+
+        Ok(game)
+    }
+
+    /// Returns the position the game started from.
+    pub fn start(&self) -> &Board {
+        &self.start
+    }
+
+    /// Returns the current position.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Returns the moves played to reach the current position.
+    pub fn moves(&self) -> &[GameMove] {
+        &self.moves[..self.undos.len()]
+    }
+
+    /// Plays a move in the current position. Any moves which were undone can no longer be redone.
+    /// # Returns:
+    /// * An error if the move is illegal, in which case the game is unchanged.
+    pub fn play(&mut self, m: Move) -> Result<()> {
+        self.play_move(GameMove::new(m))
+    }
+
+    /// Plays a move along with its score and time, as for `play`.
+    pub fn play_move(&mut self, game_move: GameMove) -> Result<()> {
+        let undo = self.board.play(game_move.m)?;
+
+        self.moves.truncate(self.undos.len());
+        self.moves.push(game_move);
+        self.undos.push(undo);
+
+        Ok(())
+    }
+
+    /// Takes back the last move played.
+    /// # Returns:
+    /// * The move, or None if there are no moves to take back.
+    pub fn undo(&mut self) -> Option<Move> {
+        let undo = self.undos.pop()?;
+        let m = self.moves[self.undos.len()].m;
+        self.board.undo_move(undo, m);
+
+        Some(m)
+    }
+
+    /// Plays the last move taken back again.
+    /// # Returns:
+    /// * The move, or None if there are no moves to redo.
+    pub fn redo(&mut self) -> Option<Move> {
+        let m = self.moves.get(self.undos.len())?.m;
+        self.undos.push(self.board.make_move(m));
+
+        Some(m)
+    }
+
+    /// Checks whether neither player can move.
+    pub fn is_over(&mut self) -> bool {
+        self.board.is_game_over()
+    }
+
+    /// Returns the final score of the game, as black's disks minus white's, or None if the game
+    /// is not over.
+    pub fn result(&mut self) -> Option<i32> {
+        if self.is_over() {
+            Some(self.board.get_score())
+        } else {
+            None
+        }
+    }
+
+    /// Writes the moves played to reach the current position as a transcript, leaving out passes.
+    pub fn transcript(&self) -> String {
+        self.moves().iter().filter(|gm| gm.m != Move::Pass).map(|gm| gm.m.to_string()).collect()
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.transcript())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+
+    use crate::Error;
+    use crate::board::{ Board, Move };
+    use super::{ Game, GameMove };
+
+    #[test]
+    fn test_transcript() {
+        let mut game = Game::from_transcript("f5d6 C3").unwrap();
+        assert_eq!(game.transcript(), "f5d6c3");
+        assert_eq!(game.moves().len(), 3);
+        assert_eq!(game.result(), None);
+
+        assert!(matches!(Game::from_transcript("f5d6c"), Err(Error::Parse(_))));
+        assert!(matches!(Game::from_transcript("f5z9"), Err(Error::Parse(_))));
+        assert!(matches!(Game::from_transcript("f5a1"), Err(Error::IllegalMove(Move::Play(0)))));
+
+        // Random games, which will often contain passes, survive a round trip.
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let mut game = Game::new();
+            while !game.is_over() {
+                let moves = game.board().clone().get_moves();
+                game.play(moves[rng.gen_range(0, moves.len())]).unwrap();
+            }
+
+            let mut parsed = Game::from_transcript(&game.transcript()).unwrap();
+            assert_eq!(parsed.moves(), game.moves());
+            assert_eq!(parsed.result(), game.result());
+        }
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut game = Game::new();
+        game.play_move(GameMove::new(Move::Play(37)).with_score(4).with_time(100)).unwrap();
+        game.play(Move::Play(43)).unwrap();
+        let after = game.board().get_position();
+
+        assert_eq!(game.undo(), Some(Move::Play(43)));
+        assert_eq!(game.undo(), Some(Move::Play(37)));
+        assert_eq!(game.undo(), None);
+        assert_eq!(game.board().get_position(), Board::new().get_position());
+
+        assert_eq!(game.redo(), Some(Move::Play(37)));
+        assert_eq!(game.redo(), Some(Move::Play(43)));
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.board().get_position(), after);
+        assert_eq!(game.moves()[0].score, Some(4));
+
+        // Playing a new move drops the moves which could have been redone.
+        game.undo();
+        game.play(Move::Play(45)).unwrap();
+        assert_eq!(game.transcript(), "f5f6");
+        assert_eq!(game.redo(), None);
+
+        // Illegal moves leave the game as it was.
+        assert!(game.play(Move::Play(0)).is_err());
+        assert_eq!(game.transcript(), "f5f6");
+    }
+}
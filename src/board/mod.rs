@@ -5,12 +5,20 @@
 use std::fmt;
 use std::str;
 
-use serde::{Serialize};
+use serde::{ Deserialize, Serialize };
 
 pub mod bitboard;
+pub mod compact;
+pub mod cpu;
+pub mod game;
 pub mod movelist;
+pub mod perft;
+pub mod symmetry;
+pub mod zobrist;
 
 use movelist::MoveList;
+pub use self::game::{ Game, GameMove };
+pub use self::symmetry::Symmetry;
 
 #[cfg(test)]
 pub mod test;
@@ -41,7 +49,7 @@ pub fn coord_to_bitmask(coord: &str) -> Option<u64> {
     pos &= match chars.next() {
         Some(c) => match c.to_string().parse::<u8>() {
             Ok(r) => {
-                if r >= 1 && r <= 8 {
+                if (1..=8).contains(&r) {
                     bitboard::RANKS[r as usize - 1]
                 } else {
                     return None;
@@ -62,44 +70,25 @@ pub enum Move {
 }
 
 impl Move {
-    /// A function which generates a bitmask corresponding to the given string coordinate.
+    /// Parses a move from a string coordinate, such as `d3`.
     /// # Arguments:
     /// * `coord`: String coordinate of a square on the board.
     /// # Returns:
-    /// * A Move, Play if the coord is valid, Pass otherwise.
-    pub fn from_coord(coord: &str) -> Move {
-        let mut chars = coord.chars();
+    /// * The move to that square, or a parse error if the string is not exactly a coordinate.
+    pub fn from_coord(coord: &str) -> Result<Move, crate::Error> {
+        let invalid = || crate::Error::Parse(format!("invalid coordinate '{}'", coord));
 
-        let file = match chars.next() {
-            Some(c) => match c.to_uppercase().next().unwrap() {
-                'A' => 0,
-                'B' => 1,
-                'C' => 2,
-                'D' => 3,
-                'E' => 4,
-                'F' => 5,
-                'G' => 6,
-                'H' => 7,
-                _ => return Move::Pass,
-            },
-            None => return Move::Pass,
+        let mut chars = coord.chars();
+        let (file, rank) = match (chars.next(), chars.next(), chars.next()) {
+            (Some(file), Some(rank), None) => (file.to_ascii_lowercase(), rank),
+            _ => return Err(invalid())
         };
 
-        let rank = match chars.next() {
-            Some(c) => match c.to_string().parse::<u8>() {
-                Ok(r) => {
-                    if r >= 1 && r <= 8 {
-                        r - 1
-                    } else {
-                        return Move::Pass;
-                    }
-                }
-                Err(_) => return Move::Pass,
-            },
-            None => return Move::Pass,
-        };
+        if !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+            return Err(invalid());
+        }
 
-        Move::Play(file + rank * 8)
+        Ok(Move::Play((file as u8 - b'a') + (rank as u8 - b'1') * 8))
     }
 }
 
@@ -124,6 +113,7 @@ pub struct Board {
     black_moves: u64,
     black_moves_gen: bool,
     pub black_move: bool,
+    hash: u64,
 }
 
 impl Board {
@@ -150,6 +140,7 @@ impl Board {
             white_moves_gen: false,
             black_moves: 0,
             black_moves_gen: false,
+            hash: zobrist::hash(black_disks, white_disks, black_move),
         };
         board.gen_black_moves();
 
@@ -165,10 +156,59 @@ impl Board {
             white_moves_gen: false,
             black_moves: 0,
             black_moves_gen: false,
+            hash: zobrist::hash(black_disks, white_disks, black_move),
+        }
+    }
+
+    /// Creates a board from a position string, in the format written by `get_position`: 64
+    /// characters (`*` for black, `O` for white and `-` for empty) starting at a1 and going across
+    /// each rank, then a space and the side to move (`*` or `O`).
+    /// # Arguments:
+    /// * `pos`: The position string.
+    /// # Returns:
+    /// * The board, or an error describing why the string could not be parsed.
+    pub fn from_position_str(pos: &str) -> Result<Board, crate::Error> {
+        let mut parts = pos.split_whitespace();
+        let (squares, side) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(squares), Some(side), None) => (squares, side),
+            _ => return Err(crate::Error::Parse(format!("expected squares and side to move, got '{}'", pos)))
+        };
+
+        let count = squares.chars().count();
+        if count != 64 {
+            return Err(crate::Error::Parse(format!("expected 64 squares, got {}", count)));
+        }
+
+        let mut black_disks = 0;
+        let mut white_disks = 0;
+        for (i, c) in squares.chars().enumerate() {
+            let sq = 0x80_00_00_00_00_00_00_00 >> i;
+            match c {
+                '*' => black_disks |= sq,
+                'O' => white_disks |= sq,
+                '-' => {},
+                _ => return Err(crate::Error::Parse(format!("invalid square '{}'", c)))
+            }
         }
+
+        let black_move = match side {
+            "*" => true,
+            "O" => false,
+            _ => return Err(crate::Error::Parse(format!("invalid side to move '{}'", side)))
+        };
+
+        Ok(Board::from_pos(black_disks, white_disks, black_move))
     }
 
     pub fn get_position(&self) -> Position {
+        Position {
+            pos: self.to_position_str(),
+            score: None
+        }
+    }
+
+    /// Writes the board as a position string, in the format read by `from_position_str`.
+    pub fn to_position_str(&self) -> String {
         let mut pos = String::new();
 
         for i in 0..64 {
@@ -182,7 +222,7 @@ impl Board {
             });
         }
 
-        pos.push_str(" ");
+        pos.push(' ');
 
         pos.push_str(if self.black_move {
             "*"
@@ -190,10 +230,7 @@ impl Board {
             "O"
         });
 
-        Position {
-            pos,
-            score: None
-        }
+        pos
     }
 
     /// A function which generates all of the moves that black can make in the current position.
@@ -228,7 +265,8 @@ impl Board {
 
     /// A function which generates all of the moves that the current player can make.
     /// # Returns:
-    /// * A list of moves that can be made by the current player.
+    /// * A list of moves that can be made by the current player. It is never empty, since it
+    ///   contains just `Move::Pass` if the player has no other move.
     pub fn get_moves(&mut self) -> MoveList {
         let mut all_moves = if self.black_move {
             self.get_black_moves()
@@ -302,6 +340,19 @@ impl Board {
         (self.get_white_moves() | self.get_black_moves()) != 0
     }
 
+    /// Makes a move for the current player, after checking that it is legal.
+    /// # Arguments:
+    /// * `m`: The move to make. A pass is only legal if the player has no other move.
+    /// # Returns:
+    /// * The undo information for `undo_move`, or an error if the move is illegal.
+    pub fn play(&mut self, m: Move) -> Result<u64, crate::Error> {
+        if self.is_game_over() || !self.get_moves().contains(m) {
+            return Err(crate::Error::IllegalMove(m));
+        }
+
+        Ok(self.make_move(m))
+    }
+
     /// A function makes a move for the current player. Does not check if the move is valid in the
     /// current position.
     /// # Arguments:
@@ -323,10 +374,13 @@ impl Board {
                 self.black_disks ^= flood;
                 if self.black_move {
                     self.white_disks ^= disk;
+                    self.hash ^= zobrist::BLACK_KEYS[63 - m as usize];
                 } else {
                     self.black_disks ^= disk;
+                    self.hash ^= zobrist::WHITE_KEYS[63 - m as usize];
                 }
 
+                self.hash ^= zobrist::hash_disks(&zobrist::FLIP_KEYS, flood ^ disk) ^ zobrist::SIDE_KEY;
                 self.black_move = !self.black_move;
 
 
@@ -337,6 +391,7 @@ impl Board {
             }
             Move::Pass => {
                 self.black_move = !self.black_move;
+                self.hash ^= zobrist::SIDE_KEY;
                 self.black_moves_gen = false;
                 self.white_moves_gen = false;
                 0
@@ -358,15 +413,19 @@ impl Board {
                 let disk = 0x80_00_00_00_00_00_00_00 >> m;
                 if self.black_move {
                     self.black_disks &= !disk;
+                    self.hash ^= zobrist::BLACK_KEYS[63 - m as usize];
                 } else {
                     self.white_disks &= !disk;
+                    self.hash ^= zobrist::WHITE_KEYS[63 - m as usize];
                 }
 
+                self.hash ^= zobrist::hash_disks(&zobrist::FLIP_KEYS, undo) ^ zobrist::SIDE_KEY;
                 self.black_moves_gen = false;
                 self.white_moves_gen = false;
             }
             Move::Pass => {
                 self.black_move = !self.black_move;
+                self.hash ^= zobrist::SIDE_KEY;
 
                 self.black_moves_gen = false;
                 self.white_moves_gen = false;
@@ -374,6 +433,15 @@ impl Board {
         }
     }
 
+    #[inline]
+    /// A function that returns the Zobrist key of the position, which is kept up to date as moves
+    /// are made and undone.
+    /// # Returns:
+    /// * The Zobrist key of the position.
+    pub fn hash_key(&self) -> u64 {
+        self.hash
+    }
+
     #[inline]
     /// A function that returns a bitmask of all of the disks on the board.
     /// # Returns:
@@ -392,6 +460,26 @@ impl Board {
     pub fn get_score(&self) -> i32 {
         self.black_disks.count_ones() as i32 - self.white_disks.count_ones() as i32
     }
+
+    /// A function which finds black's stable disks, which can never be flipped.
+    /// # Returns:
+    /// * A mask of black's stable disks.
+    pub fn get_black_stable(&self) -> u64 {
+        bitboard::stable_disks(self.black_disks, self.white_disks)
+    }
+
+    /// A function which finds white's stable disks, which can never be flipped.
+    /// # Returns:
+    /// * A mask of white's stable disks.
+    pub fn get_white_stable(&self) -> u64 {
+        bitboard::stable_disks(self.white_disks, self.black_disks)
+    }
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl fmt::Debug for Board {
@@ -458,8 +546,18 @@ impl fmt::Display for Board {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub struct Position {
     pos: String,
+    /// The score of the position, relative to the player to move.
     pub score: Option<i32>
-}
\ No newline at end of file
+}
+
+impl Position {
+    /// Creates the board described by this position.
+    /// # Returns:
+    /// * The board, or an error if the position string is malformed.
+    pub fn to_board(&self) -> Result<Board, crate::Error> {
+        Board::from_position_str(&self.pos)
+    }
+}
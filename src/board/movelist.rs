@@ -2,16 +2,20 @@ use std::{fmt, ops};
 
 use super::Move;
 
+/// The most moves a list can hold. Legal positions can have more than 32 moves, and any position
+/// has at most one per empty square.
+const MAX_MOVES: usize = 64;
+
 #[derive(Eq, Clone)]
 pub struct MoveList {
-    moves: [Move; 32],
+    moves: [Move; MAX_MOVES],
     size: u8
 }
 
 impl MoveList {
     pub fn new() -> MoveList {
         MoveList {
-            moves: [Move::Pass; 32],
+            moves: [Move::Pass; MAX_MOVES],
             size: 0
         }
     }
@@ -27,7 +31,7 @@ impl MoveList {
     }
 
     pub fn push(&mut self, m: Move) {
-        assert!(self.size < 32);
+        assert!((self.size as usize) < MAX_MOVES);
 
         self.moves[self.size as usize] = m;
         self.size += 1;
@@ -41,9 +45,9 @@ impl MoveList {
         where F: FnMut(&Move) -> i32
     {
         // Get and store the keys.
-        let mut keys = [0i32; 32];
-        for i in 0..self.size as usize {
-            keys[i] = f(&self.moves[i]);
+        let mut keys = [0i32; MAX_MOVES];
+        for (key, m) in keys.iter_mut().zip(self.moves.iter()).take(self.size as usize) {
+            *key = f(m);
         }
 
         // Sort the move list.
@@ -78,7 +82,7 @@ impl MoveList {
     }
 
     pub fn is_empty(&self) -> bool {
-        return self.size == 0;
+        self.size == 0
     }
 
     pub fn contains(&self, m: Move) -> bool {
@@ -92,6 +96,12 @@ impl MoveList {
     }
 }
 
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl std::cmp::PartialEq for MoveList {
     fn eq(&self, other: &Self) -> bool {
         if self.size != other.size {
@@ -0,0 +1,245 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Counts the positions reachable from a board in a fixed number of plies, which checks and times
+//! move generation. A pass counts as a ply, and a position where the game is over keeps passing
+//! until the depth runs out. This matches the published counts for Othello, which are in `COUNTS`.
+//!
+//! Counts can be cached in a `PerftTable`, which can be shared between threads without locking in
+//! the same way as the search's transposition table.
+
+use std::sync::atomic::{ AtomicU64, Ordering };
+
+use rayon::prelude::*;
+
+use super::Move;
+use super::compact::CompactBoard;
+
+/// The number of positions reachable from the starting position, indexed by depth.
+pub const COUNTS: [u64; 15] = [
+    1, 4, 12, 56, 244, 1396, 8200, 55092, 390216, 3005288, 24571284, 212258800, 1939886636,
+    18429641748, 184042084512
+];
+
+/// The number of bits of an entry's data which hold the count. The depth is in the rest.
+const COUNT_BITS: u32 = 56;
+
+/// The number of plies left below which `divide` counts a position on a single thread. Above it,
+/// the moves of every position are split between threads, not just the moves from the root.
+const SPLIT_DEPTH: u8 = 6;
+
+/// The storage for a single entry. `check` is the key XORed with `data`, so an entry torn by a
+/// concurrent write fails the key check and is treated as a miss.
+#[derive(Debug, Default)]
+struct Slot {
+    check: AtomicU64,
+    data: AtomicU64
+}
+
+impl Slot {
+    fn load(&self) -> (u64, u64) {
+        let data = self.data.load(Ordering::Relaxed);
+        (self.check.load(Ordering::Relaxed) ^ data, data)
+    }
+
+    fn store(&self, key: u64, data: u64) {
+        self.data.store(data, Ordering::Relaxed);
+        self.check.store(key ^ data, Ordering::Relaxed);
+    }
+}
+
+/// A pair of entries. The first keeps the deepest count seen, and the second is always replaced.
+#[derive(Debug, Default)]
+#[repr(align(32))]
+struct Bucket {
+    deep: Slot,
+    recent: Slot
+}
+
+pub struct PerftTable {
+    table: Vec<Bucket>
+}
+
+impl PerftTable {
+    /// Creates an empty table which uses approximately the given amount of memory.
+    /// # Arguments:
+    /// * `mb`: The size of the table in megabytes. The table always has at least one bucket.
+    /// # Returns:
+    /// * An empty `PerftTable`.
+    pub fn with_size_mb(mb: usize) -> Self {
+        let buckets = (mb << 20) / std::mem::size_of::<Bucket>();
+
+        PerftTable {
+            table: (0..buckets.max(1)).map(|_| Bucket::default()).collect()
+        }
+    }
+
+    fn bucket(&self, key: u64) -> &Bucket {
+        &self.table[((u128::from(key) * self.table.len() as u128) >> 64) as usize]
+    }
+
+    /// Looks up the count for the position with the given key.
+    /// # Arguments:
+    /// * `key`: The hash key of the position.
+    /// * `depth`: The depth the count must be for.
+    /// # Returns:
+    /// * The stored count, if there is one for the position at exactly `depth`.
+    pub fn probe(&self, key: u64, depth: u8) -> Option<u64> {
+        let bucket = self.bucket(key);
+
+        [&bucket.deep, &bucket.recent].iter()
+            .map(|slot| slot.load())
+            .find(|&(stored, data)| stored == key && data >> COUNT_BITS == u64::from(depth))
+            .map(|(_, data)| data & ((1 << COUNT_BITS) - 1))
+    }
+
+    /// Saves the count for the position with the given key. It replaces the deep entry if it is at
+    /// least as deep, and the other entry otherwise.
+    pub fn save(&self, key: u64, depth: u8, count: u64) {
+        debug_assert!(count < 1 << COUNT_BITS);
+
+        let bucket = self.bucket(key);
+        let data = u64::from(depth) << COUNT_BITS | count;
+
+        if bucket.deep.load().1 >> COUNT_BITS <= u64::from(depth) {
+            bucket.deep.store(key, data);
+        } else {
+            bucket.recent.store(key, data);
+        }
+    }
+}
+
+/// Counts the positions reachable from the given board.
+/// # Arguments:
+/// * `board`: The position to count from.
+/// * `depth`: The number of plies to play.
+/// # Returns:
+/// * The number of positions reached after `depth` plies.
+pub fn perft(board: CompactBoard, depth: u8) -> u64 {
+    match depth {
+        0 => 1,
+        1 => u64::from(board.moves().count_ones().max(1)),
+        _ => board.get_moves().into_iter().map(|m| perft(board.play(m), depth - 1)).sum()
+    }
+}
+
+/// Counts the positions reachable from the given board, using a table to count each transposition
+/// only once. A key collision in the table would give a wrong count, but with 64-bit keys this is
+/// unlikely to ever happen.
+/// # Arguments:
+/// * `board`: The position to count from.
+/// * `depth`: The number of plies to play.
+/// * `table`: The table to look up and save counts in.
+/// # Returns:
+/// * The number of positions reached after `depth` plies.
+pub fn perft_hashed(board: CompactBoard, depth: u8, table: &PerftTable) -> u64 {
+    if depth < 3 {
+        return perft(board, depth);
+    }
+
+    let key = board.hash_key();
+    if let Some(count) = table.probe(key, depth) {
+        return count;
+    }
+
+    let count = board.get_moves().into_iter().map(|m| perft_hashed(board.play(m), depth - 1, table)).sum();
+    table.save(key, depth, count);
+    count
+}
+
+/// Counts the positions reachable after each move from the given board, in parallel on the current
+/// `rayon` thread pool. Positions with at least `SPLIT_DEPTH` plies left split their moves between
+/// threads, so that more threads help even though there are only a few moves from the root.
+/// # Arguments:
+/// * `board`: The position to count from.
+/// * `depth`: The number of plies to play, including the move. Must be at least one.
+/// * `table`: The table to use, if any.
+/// # Returns:
+/// * The count for each move, in the order of `get_moves`.
+pub fn divide(board: CompactBoard, depth: u8, table: Option<&PerftTable>) -> Vec<(Move, u64)> {
+    assert!(depth > 0, "Cannot divide a perft of depth zero.");
+
+    let moves: Vec<Move> = board.get_moves().into_iter().collect();
+    moves.into_par_iter().map(|m| (m, perft_parallel(board.play(m), depth - 1, table))).collect()
+}
+
+/// Counts the positions reachable from the given board like `perft_hashed`, or `perft` without a
+/// table, but splits the moves between threads while there are at least `SPLIT_DEPTH` plies left.
+fn perft_parallel(board: CompactBoard, depth: u8, table: Option<&PerftTable>) -> u64 {
+    if depth < SPLIT_DEPTH {
+        return match table {
+            Some(table) => perft_hashed(board, depth, table),
+            None => perft(board, depth)
+        };
+    }
+
+    let key = board.hash_key();
+    if let Some(count) = table.and_then(|table| table.probe(key, depth)) {
+        return count;
+    }
+
+    let moves: Vec<Move> = board.get_moves().into_iter().collect();
+    let count = moves.into_par_iter().map(|m| perft_parallel(board.play(m), depth - 1, table)).sum();
+    if let Some(table) = table {
+        table.save(key, depth, count);
+    }
+    count
+}
+
+#[cfg(test)]
+mod test {
+    use rayon::ThreadPoolBuilder;
+
+    use super::*;
+    use crate::board::Board;
+
+    fn start() -> CompactBoard {
+        CompactBoard::from(&Board::new())
+    }
+
+    #[test]
+    fn test_perft() {
+        for (depth, &count) in COUNTS.iter().enumerate().take(9) {
+            assert_eq!(perft(start(), depth as u8), count, "Wrong count at depth {}.", depth);
+        }
+    }
+
+    #[test]
+    fn test_divide_hashed() {
+        let table = PerftTable::with_size_mb(16);
+        for (depth, &count) in COUNTS.iter().enumerate().take(11).skip(1) {
+            let counts = divide(start(), depth as u8, Some(&table));
+            assert_eq!(counts.len(), 4);
+            assert_eq!(counts.iter().map(|&(_, count)| count).sum::<u64>(), count, "Wrong count at depth {}.", depth);
+        }
+
+        // The starting position is symmetric, so every move leads to the same count.
+        let counts = divide(start(), 9, None);
+        assert!(counts.iter().all(|&(_, count)| count == COUNTS[9] / 4));
+
+        // More threads than moves from the root split the positions below it between them.
+        let pool = ThreadPoolBuilder::new().num_threads(8).build().unwrap();
+        let table = PerftTable::with_size_mb(16);
+        assert_eq!(pool.install(|| divide(start(), 9, None)), counts);
+        assert_eq!(pool.install(|| divide(start(), 9, Some(&table))), counts);
+    }
+
+    #[test]
+    fn test_game_over_passes() {
+        // Black has no disks, so neither side can move, and every ply is a pass.
+        let board = CompactBoard::new(0, 0xFF, true);
+        assert!(board.is_game_over());
+        assert_eq!(perft(board, 5), 1);
+        assert_eq!(divide(board, 5, None), vec![(Move::Pass, 1)]);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_perft_deep() {
+        let table = PerftTable::with_size_mb(256);
+        for (depth, &count) in COUNTS.iter().enumerate().skip(11) {
+            assert_eq!(perft_hashed(start(), depth as u8, &table), count, "Wrong count at depth {}.", depth);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The 8 symmetries of the board (the rotations and reflections of a square), and a canonical
+//! form which is the same for every position related by a symmetry. Rotations are clockwise, with
+//! the board drawn as usual, a1 in the top left and h8 in the bottom right.
+
+use super::{ Board, Move };
+use super::bitboard::{ flip_diag, flip_horizontal, flip_vertical };
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Swaps rank 1 with rank 8.
+    FlipVertical,
+    /// Swaps file A with file H.
+    FlipHorizontal,
+    /// Mirrors along the a1-h8 diagonal.
+    FlipDiagonal,
+    /// Mirrors along the h1-a8 diagonal.
+    FlipAntiDiagonal
+}
+
+impl Symmetry {
+    /// Every symmetry, starting with the identity.
+    pub const ALL: [Symmetry; 8] = [
+        Symmetry::Identity,
+        Symmetry::Rotate90,
+        Symmetry::Rotate180,
+        Symmetry::Rotate270,
+        Symmetry::FlipVertical,
+        Symmetry::FlipHorizontal,
+        Symmetry::FlipDiagonal,
+        Symmetry::FlipAntiDiagonal
+    ];
+
+    /// Applies the symmetry to a bitboard.
+    pub fn apply(self, x: u64) -> u64 {
+        match self {
+            Symmetry::Identity => x,
+            Symmetry::Rotate90 => flip_diag(flip_vertical(x)),
+            Symmetry::Rotate180 => flip_vertical(flip_horizontal(x)),
+            Symmetry::Rotate270 => flip_vertical(flip_diag(x)),
+            Symmetry::FlipVertical => flip_vertical(x),
+            Symmetry::FlipHorizontal => flip_horizontal(x),
+            Symmetry::FlipDiagonal => flip_diag(x),
+            Symmetry::FlipAntiDiagonal => flip_vertical(flip_horizontal(flip_diag(x)))
+        }
+    }
+
+    /// Returns the symmetry which undoes this one.
+    pub fn inverse(self) -> Symmetry {
+        match self {
+            Symmetry::Rotate90 => Symmetry::Rotate270,
+            Symmetry::Rotate270 => Symmetry::Rotate90,
+            other => other
+        }
+    }
+}
+
+impl Move {
+    /// Maps a move through a symmetry, so that it is the same move in the transformed position.
+    pub fn transform(self, sym: Symmetry) -> Move {
+        match self {
+            Move::Play(m) => Move::Play(sym.apply(0x80_00_00_00_00_00_00_00 >> m).leading_zeros() as u8),
+            Move::Pass => Move::Pass
+        }
+    }
+}
+
+impl Board {
+    /// Applies a symmetry to the board. The side to move is unchanged.
+    pub fn transform(&self, sym: Symmetry) -> Board {
+        Board::from_pos(sym.apply(self.black_disks), sym.apply(self.white_disks), self.black_move)
+    }
+
+    /// Finds the canonical form of the board, which is the same for all 8 symmetric positions.
+    /// # Returns:
+    /// * The symmetric board with the smallest (black, white) disks, and the symmetry which
+    ///   produces it from this board. Moves in the canonical board can be mapped back with the
+    ///   inverse of the symmetry.
+    pub fn canonical(&self) -> (Board, Symmetry) {
+        let sym = Symmetry::ALL.iter()
+            .min_by_key(|sym| (sym.apply(self.black_disks), sym.apply(self.white_disks)))
+            .copied()
+            .unwrap_or(Symmetry::Identity);
+
+        (self.transform(sym), sym)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+
+    use crate::board::{ Board, Move };
+    use super::Symmetry;
+
+    #[test]
+    fn test_moves() {
+        // Where a2 (next to the a1 corner, below it) ends up under each symmetry.
+        let expected = ["a2", "g1", "h7", "b8", "a7", "h2", "b1", "g8"];
+        for (&sym, &coord) in Symmetry::ALL.iter().zip(expected.iter()) {
+            assert_eq!(Move::Play(8).transform(sym), Move::from_coord(coord).unwrap(), "{:?}", sym);
+            assert_eq!(Move::Play(8).transform(sym).transform(sym.inverse()), Move::Play(8));
+        }
+        assert_eq!(Move::Pass.transform(Symmetry::Rotate90), Move::Pass);
+
+        // The starting position is unchanged by the half turn and both diagonal flips.
+        let start = Board::new();
+        let unchanged = Symmetry::ALL.iter().filter(|&&sym| start.transform(sym).get_position() == start.get_position());
+        assert_eq!(unchanged.count(), 4);
+    }
+
+    #[test]
+    fn test_canonical() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let mut board = Board::new();
+            for _ in 0..rng.gen_range(0, 40usize) {
+                let moves = board.get_moves();
+                board.make_move(moves[rng.gen_range(0, moves.len())]);
+            }
+
+            let (canonical, sym) = board.canonical();
+            assert_eq!(canonical.get_position(), board.transform(sym).get_position());
+
+            for &other in Symmetry::ALL.iter() {
+                // Every symmetric position has the same canonical form.
+                let mut transformed = board.transform(other);
+                assert_eq!(transformed.canonical().0.get_position(), canonical.get_position());
+                assert_eq!(transformed.transform(other.inverse()).get_position(), board.get_position());
+
+                // Moves map to the moves of the transformed position.
+                let moves = board.get_moves();
+                let mut mapped: Vec<Move> = (&moves).into_iter().map(|m| m.transform(other)).collect();
+                let mut actual: Vec<Move> = (&transformed.get_moves()).into_iter().collect();
+                mapped.sort_by_key(|m| format!("{}", m));
+                actual.sort_by_key(|m| format!("{}", m));
+                assert_eq!(mapped, actual);
+
+                let m = moves[rng.gen_range(0, moves.len())];
+                let mut after = board.clone();
+                after.make_move(m);
+                transformed.make_move(m.transform(other));
+                assert_eq!(transformed.get_position(), after.transform(other).get_position());
+            }
+        }
+    }
+}
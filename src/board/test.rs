@@ -2,7 +2,10 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use super::{coord_to_bitmask, Move, Board, movelist::MoveList};
+use rand::Rng;
+
+use crate::Error;
+use super::{coord_to_bitmask, Move, Board, Position, movelist::MoveList};
 
 #[test]
 fn test_coord_to_bitmask() {
@@ -27,21 +30,45 @@ fn test_coord_to_bitmask() {
 #[test]
 fn test_coord_to_move() {
     // Testing each file.
-    assert_eq!(Move::from_coord("a1"), Move::Play(0));
-    assert_eq!(Move::from_coord("b2"), Move::Play(9));
-    assert_eq!(Move::from_coord("c3"), Move::Play(18));
-    assert_eq!(Move::from_coord("d4"), Move::Play(27));
-    assert_eq!(Move::from_coord("e5"), Move::Play(36));
-    assert_eq!(Move::from_coord("f6"), Move::Play(45));
-    assert_eq!(Move::from_coord("g7"), Move::Play(54));
-    assert_eq!(Move::from_coord("h8"), Move::Play(63));
+    assert_eq!(Move::from_coord("a1").unwrap(), Move::Play(0));
+    assert_eq!(Move::from_coord("b2").unwrap(), Move::Play(9));
+    assert_eq!(Move::from_coord("c3").unwrap(), Move::Play(18));
+    assert_eq!(Move::from_coord("d4").unwrap(), Move::Play(27));
+    assert_eq!(Move::from_coord("e5").unwrap(), Move::Play(36));
+    assert_eq!(Move::from_coord("f6").unwrap(), Move::Play(45));
+    assert_eq!(Move::from_coord("g7").unwrap(), Move::Play(54));
+    assert_eq!(Move::from_coord("h8").unwrap(), Move::Play(63));
+
+    assert_eq!(Move::from_coord("H8").unwrap(), Move::Play(63));
 
     // Testing all possible failure modes.
-    assert_eq!(Move::from_coord(""), Move::Pass);
-    assert_eq!(Move::from_coord("j1"), Move::Pass);
-    assert_eq!(Move::from_coord("a9"), Move::Pass);
-    assert_eq!(Move::from_coord("ab"), Move::Pass);
-    assert_eq!(Move::from_coord("a"), Move::Pass);
+    for coord in &["", "j1", "a9", "ab", "a", "a10", "a1b", "é1"] {
+        assert!(matches!(Move::from_coord(coord), Err(Error::Parse(_))));
+    }
+}
+
+#[test]
+fn test_play() {
+    let mut board = Board::new();
+    let start = board.get_position();
+
+    assert!(matches!(board.play(Move::Play(0)), Err(Error::IllegalMove(Move::Play(0)))));
+    assert!(matches!(board.play(Move::Pass), Err(Error::IllegalMove(Move::Pass))));
+    assert_eq!(board.get_position(), start);
+
+    let d3 = Move::from_coord("d3").unwrap();
+    let undo = board.play(d3).unwrap();
+    assert!(board.get_position() != start);
+    board.undo_move(undo, d3);
+    assert_eq!(board.get_position(), start);
+}
+
+#[test]
+fn test_many_moves() {
+    // More moves than fit in 32 slots.
+    let pos = "---------OO*O-OO**O-*-**-OO*-O-O-----O---*--***-*O--O*O-*-*--*-- *";
+    let mut board = Board::from_position_str(pos).unwrap();
+    assert_eq!(board.get_moves().len(), 34);
 }
 
 #[test]
@@ -64,7 +91,7 @@ fn test_board_derives() {
     assert_eq!(board, clone);
     assert_eq!(clone, board);
 
-    board.make_move(Move::from_coord("d3"));
+    board.make_move(Move::from_coord("d3").unwrap());
     assert!(board != clone);
     assert!(board == board);
 }
@@ -249,3 +276,112 @@ fn test_board_debug() {
 
     assert_eq!(format!("{:?}", board), debug);
 }
+
+#[test]
+fn test_position_round_trip() {
+    let mut rng = rand::thread_rng();
+
+    // Play random games, the same way the training data generators do, and make sure every
+    // position along the way survives being written and read back.
+    for _ in 0..100 {
+        let mut board = Board::new();
+        while !board.is_game_over() {
+            let mut pos = board.get_position();
+            pos.score = Some(board.get_score());
+
+            let json = serde_json::to_string(&pos).unwrap();
+            let loaded: Position = serde_json::from_str(&json).unwrap();
+            assert_eq!(loaded, pos);
+
+            let parsed = loaded.to_board().unwrap();
+            assert_eq!(parsed.black_disks, board.black_disks);
+            assert_eq!(parsed.white_disks, board.white_disks);
+            assert_eq!(parsed.black_move, board.black_move);
+
+            let moves = board.get_moves();
+            board.make_move(moves[rng.gen_range(0, moves.len())]);
+        }
+    }
+}
+
+#[test]
+fn test_from_position_str() {
+    let start = "---------------------------O*------*O--------------------------- *";
+    let board = Board::from_position_str(start).unwrap();
+    assert_eq!(board.black_disks, 0x00_00_00_08_10_00_00_00);
+    assert_eq!(board.white_disks, 0x00_00_00_10_08_00_00_00);
+    assert!(board.black_move);
+
+    // Testing all possible failure modes.
+    let error = |pos: &str| match Board::from_position_str(pos) {
+        Err(Error::Parse(msg)) => msg,
+        result => panic!("expected a parse error, got {:?}", result)
+    };
+    assert_eq!(error(""), "expected squares and side to move, got ''");
+    assert_eq!(error(&start[..64]), format!("expected squares and side to move, got '{}'", &start[..64]));
+    assert_eq!(error(&start[1..]), "expected 64 squares, got 63");
+    assert_eq!(error(&start.replace('O', "X")), "invalid square 'X'");
+    assert_eq!(error(&start.replace(" *", " #")), "invalid side to move '#'");
+}
+
+#[test]
+fn test_hash_key() {
+    let mut rng = rand::thread_rng();
+
+    // The incrementally updated key should always match the key computed from scratch, both while
+    // playing a game and while taking it back.
+    for _ in 0..100 {
+        let mut board = Board::new();
+        let mut history = Vec::new();
+        while !board.is_game_over() {
+            let moves = board.get_moves();
+            let m = moves[rng.gen_range(0, moves.len())];
+            let undo = board.make_move(m);
+            history.push((undo, m));
+
+            let fresh = Board::from_pos(board.black_disks, board.white_disks, board.black_move);
+            assert_eq!(board.hash_key(), fresh.hash_key());
+        }
+
+        while let Some((undo, m)) = history.pop() {
+            board.undo_move(undo, m);
+
+            let fresh = Board::from_pos(board.black_disks, board.white_disks, board.black_move);
+            assert_eq!(board.hash_key(), fresh.hash_key());
+        }
+
+        assert_eq!(board.hash_key(), Board::new().hash_key());
+    }
+}
+
+#[test]
+fn test_stable_disks() {
+    // Black owns the a1 corner with the rank 1 edge filled up to e1, and a wedge behind it.
+    let board = Board::from_pos(0xF8_C0_80_00_00_00_00_00 | 0x00_00_00_10_08_00_00_00, 0x04_20_40_08_10_00_00_00, true);
+    assert_eq!(board.get_black_stable(), 0xF8_C0_80_00_00_00_00_00);
+    assert_eq!(board.get_white_stable(), 0);
+
+    // Filling rank 1 makes white's f1 stable, and the full rank makes the rest of it stable too.
+    let board = Board::from_pos(0xF8_C0_80_00_00_00_00_00, 0x07_00_00_00_00_00_00_00, true);
+    assert_eq!(board.get_black_stable(), 0xF8_C0_80_00_00_00_00_00);
+    assert_eq!(board.get_white_stable(), 0x07_00_00_00_00_00_00_00);
+
+    let mut rng = rand::thread_rng();
+
+    // A stable disk must keep its color for the rest of any game.
+    for _ in 0..100 {
+        let mut board = Board::new();
+        let mut black_stable = 0;
+        let mut white_stable = 0;
+        while !board.is_game_over() {
+            let moves = board.get_moves();
+            board.make_move(moves[rng.gen_range(0, moves.len())]);
+
+            assert_eq!(board.black_disks & black_stable, black_stable);
+            assert_eq!(board.white_disks & white_stable, white_stable);
+
+            black_stable = board.get_black_stable();
+            white_stable = board.get_white_stable();
+        }
+    }
+}
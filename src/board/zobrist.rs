@@ -0,0 +1,142 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Contains the Zobrist keys used to hash positions. A position's key is the XOR of the key of
+//! every disk on the board, plus `SIDE_KEY` if white is to move. Since XOR is its own inverse, the
+//! key can be updated incrementally as moves are made and undone. `Board` and `CompactBoard` both
+//! keep their key up to date this way, and the transposition tables are keyed on it.
+//!
+//! The keys are indexed by bit position in the bitboard (so the disk at bit `i` is `1 << i`), and
+//! are generated at compile time with SplitMix64 so that they are the same on every run.
+
+/// Generates the next value of a SplitMix64 sequence.
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+/// Generates 64 keys starting from the given seed.
+const fn gen_keys(seed: u64) -> [u64; 64] {
+    let mut keys = [0; 64];
+    let mut state = seed;
+    let mut i = 0;
+    while i < 64 {
+        let (next, key) = splitmix64(state);
+        keys[i] = key;
+        state = next;
+        i += 1;
+    }
+    keys
+}
+
+/// Combines the black and white keys, giving the change in key when a disk is flipped.
+const fn gen_flip_keys() -> [u64; 64] {
+    let mut keys = [0; 64];
+    let mut i = 0;
+    while i < 64 {
+        keys[i] = BLACK[i] ^ WHITE[i];
+        i += 1;
+    }
+    keys
+}
+
+/// Combines the flip keys of every mask of disks within each row, so that the change in key from
+/// flipping any set of disks takes one lookup per row.
+const fn gen_flip_row_keys() -> [[u64; 256]; 8] {
+    let flip = gen_flip_keys();
+    let mut keys = [[0; 256]; 8];
+    let mut row = 0;
+    while row < 8 {
+        let mut mask = 1;
+        while mask < 256 {
+            // Each mask adds its highest disk to a mask which has already been filled in.
+            let high = 7 - (mask as u8).leading_zeros() as usize;
+            keys[row][mask] = keys[row][mask ^ (1 << high)] ^ flip[row * 8 + high];
+            mask += 1;
+        }
+        row += 1;
+    }
+    keys
+}
+
+const BLACK: [u64; 64] = gen_keys(0x5275_7468_6C65_7373);
+const WHITE: [u64; 64] = gen_keys(0x4F74_6865_6C6C_6F21);
+
+/// The keys for black disks, indexed by bit position.
+pub static BLACK_KEYS: [u64; 64] = BLACK;
+/// The keys for white disks, indexed by bit position.
+pub static WHITE_KEYS: [u64; 64] = WHITE;
+/// The keys for flipping a disk from one color to the other, indexed by bit position.
+pub static FLIP_KEYS: [u64; 64] = gen_flip_keys();
+/// The keys for flipping every set of disks within a row, indexed by row and then by the disks in
+/// the row as a byte.
+pub static FLIP_ROW_KEYS: [[u64; 256]; 8] = gen_flip_row_keys();
+/// The key which is included when white is to move.
+pub const SIDE_KEY: u64 = splitmix64(0x5369_6465_546F_4D76).1;
+
+/// XORs together the keys for every disk in the given mask.
+/// # Arguments:
+/// * `keys`: The keys to use, indexed by bit position.
+/// * `mask`: The disks to hash.
+/// # Returns:
+/// * The combined key of all of the disks.
+#[inline]
+pub fn hash_disks(keys: &[u64; 64], mut mask: u64) -> u64 {
+    let mut hash = 0;
+    while mask != 0 {
+        hash ^= keys[mask.trailing_zeros() as usize];
+        mask &= mask - 1;
+    }
+    hash
+}
+
+/// Gives the change in key from flipping every disk in the given mask. Equivalent to
+/// `hash_disks(&FLIP_KEYS, mask)`, but without a branch for each disk.
+#[inline]
+pub fn hash_flips(mask: u64) -> u64 {
+    let mut hash = 0;
+    for (row, keys) in FLIP_ROW_KEYS.iter().enumerate() {
+        hash ^= keys[(mask >> (row * 8)) as u8 as usize];
+    }
+    hash
+}
+
+/// Computes the key of a position from scratch.
+/// # Arguments:
+/// * `black_disks`: The bitboard representing black's disks.
+/// * `white_disks`: The bitboard representing white's disks.
+/// * `black_move`: Whether it is black's turn to move.
+/// # Returns:
+/// * The Zobrist key of the position.
+pub fn hash(black_disks: u64, white_disks: u64, black_move: bool) -> u64 {
+    let side = if black_move { 0 } else { SIDE_KEY };
+    hash_disks(&BLACK_KEYS, black_disks) ^ hash_disks(&WHITE_KEYS, white_disks) ^ side
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_keys_unique() {
+        let mut keys = HashSet::new();
+        keys.extend(super::BLACK_KEYS.iter());
+        keys.extend(super::WHITE_KEYS.iter());
+        keys.insert(&super::SIDE_KEY);
+
+        assert_eq!(keys.len(), 129);
+        assert!(!keys.contains(&0));
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(super::hash(0, 0, true), 0);
+        assert_eq!(super::hash(0, 0, false), super::SIDE_KEY);
+        assert_eq!(super::hash(1, 0, true), super::BLACK_KEYS[0]);
+        assert_eq!(super::hash(0, 1 << 63, true), super::WHITE_KEYS[63]);
+    }
+}
@@ -0,0 +1,77 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The error type returned by the library. Anything which depends on outside input, such as
+//! coordinates, positions, game records and weight files, returns an `Error` rather than
+//! panicking, so that the crate can be embedded in long-running programs.
+
+use std::error;
+use std::fmt;
+use std::io;
+
+use rayon::ThreadPoolBuildError;
+
+use crate::board::Move;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Text could not be parsed, e.g. a coordinate, position string or game record.
+    Parse(String),
+    /// A file could not be read or written.
+    Io(io::Error),
+    /// A weight or parameter file was read, but its contents are not valid.
+    InvalidWeights(String),
+    /// A move was not legal in the position it was played in.
+    IllegalMove(Move),
+    /// The threads for a parallel search could not be started.
+    ThreadPool(ThreadPoolBuildError)
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse(msg) => write!(f, "parse error: {}", msg),
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::InvalidWeights(msg) => write!(f, "invalid weights: {}", msg),
+            Error::IllegalMove(m) => write!(f, "illegal move: {}", m),
+            Error::ThreadPool(err) => write!(f, "unable to create thread pool: {}", err)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::ThreadPool(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<ThreadPoolBuildError> for Error {
+    fn from(err: ThreadPoolBuildError) -> Error {
+        Error::ThreadPool(err)
+    }
+}
+
+/// Weight files are json, so anything other than a failure to read the file means the contents
+/// are invalid.
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        if err.is_io() {
+            Error::Io(err.into())
+        } else {
+            Error::InvalidWeights(err.to_string())
+        }
+    }
+}
@@ -0,0 +1,431 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Reads and writes games in the Generic Game Format used by GGS. A record looks like
+//! `(;GM[Othello]PB[black]PW[white]TY[8]RE[+4.000]BO[8 <squares> *]B[f5/0.50/1.2]W[d6];)`, where
+//! each tag holds a value in brackets. Moves hold the coordinate (or `PA` for a pass), then
+//! optionally the evaluation in disks and the time taken in seconds, separated by slashes.
+//!
+//! Only 8x8 boards are supported. Tags which are not understood are skipped.
+
+use std::fmt;
+
+use crate::{ Error, Result };
+use crate::board::{ Board, Game, GameMove, Move };
+
+/// How a game ended, as recorded after the score in the `RE` tag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ending {
+    /// The game was played out.
+    Normal,
+    /// A player resigned (`:r`).
+    Resigned,
+    /// A player ran out of time (`:t`).
+    Timeout,
+    /// The players agreed on the score (`:s`).
+    Agreed
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GgfResult {
+    /// Black's disks minus white's.
+    pub score: f32,
+    pub ending: Ending
+}
+
+/// A game record, along with the information about the game stored with it.
+#[derive(Clone, Debug)]
+pub struct GgfGame {
+    /// The starting position and the moves played, with their evaluations and times.
+    pub game: Game,
+    pub place: Option<String>,
+    pub date: Option<String>,
+    pub black: Option<String>,
+    pub white: Option<String>,
+    pub black_rating: Option<f32>,
+    pub white_rating: Option<f32>,
+    /// The time control, e.g. `05:00//02:00`.
+    pub time_control: Option<String>,
+    /// Black's clock, when it differs from the time control.
+    pub black_clock: Option<String>,
+    /// White's clock, when it differs from the time control.
+    pub white_clock: Option<String>,
+    /// The game type, e.g. `8` or `8r` for a random start.
+    pub game_type: String,
+    /// The result of the game, or None if it is unknown.
+    pub result: Option<GgfResult>
+}
+
+impl GgfGame {
+    /// Creates a record of the given game, with no other information.
+    pub fn new(game: Game) -> GgfGame {
+        GgfGame {
+            game,
+            place: None,
+            date: None,
+            black: None,
+            white: None,
+            black_rating: None,
+            white_rating: None,
+            time_control: None,
+            black_clock: None,
+            white_clock: None,
+            game_type: String::from("8"),
+            result: None
+        }
+    }
+
+    /// Parses a single game record.
+    /// # Arguments:
+    /// * `record`: The record, which may be surrounded by whitespace.
+    /// # Returns:
+    /// * The game, or an error if the record is malformed, is not for an 8x8 board, or contains an
+    ///   illegal move.
+    pub fn parse(record: &str) -> Result<GgfGame> {
+        let (game, rest) = parse_record(record)?;
+        if !rest.trim().is_empty() {
+            return Err(Error::Parse(String::from("unexpected text after game record")));
+        }
+
+        Ok(game)
+    }
+
+    /// Parses every game record in a file, such as a GGS archive with one game per line.
+    pub fn parse_all(text: &str) -> Result<Vec<GgfGame>> {
+        let mut games = Vec::new();
+        let mut rest = text.trim_start();
+
+        while !rest.is_empty() {
+            let (game, after) = parse_record(rest)?;
+            games.push(game);
+            rest = after.trim_start();
+        }
+
+        Ok(games)
+    }
+}
+
+/// Parses the record at the start of the text.
+/// # Returns:
+/// * The game, and the text after the end of the record.
+fn parse_record(text: &str) -> Result<(GgfGame, &str)> {
+    let (tags, rest) = parse_tags(text)?;
+
+    let mut ggf = GgfGame::new(Game::new());
+    let mut board = None;
+
+    for (name, value) in tags {
+        match name.as_str() {
+            "GM" if !value.eq_ignore_ascii_case("othello") => {
+                return Err(Error::Parse(format!("unsupported game '{}'", value)));
+            }
+            "PC" => ggf.place = Some(value),
+            "DT" => ggf.date = Some(value),
+            "PB" => ggf.black = Some(value),
+            "PW" => ggf.white = Some(value),
+            "RB" => ggf.black_rating = Some(parse_number(&name, &value)?),
+            "RW" => ggf.white_rating = Some(parse_number(&name, &value)?),
+            "TI" => ggf.time_control = Some(value),
+            "TB" => ggf.black_clock = Some(value),
+            "TW" => ggf.white_clock = Some(value),
+            "TY" => {
+                let size: String = value.chars().filter(|c| c.is_ascii_digit()).collect();
+                if size != "8" {
+                    return Err(Error::Parse(format!("unsupported game type '{}', only 8x8 boards are supported", value)));
+                }
+                ggf.game_type = value;
+            }
+            "RE" => ggf.result = parse_result(&value)?,
+            "BO" => {
+                if board.is_some() {
+                    return Err(Error::Parse(String::from("game record has more than one board")));
+                }
+                board = Some(Game::from_board(parse_board(&value)?));
+            }
+            "B" | "W" => {
+                let game = board.as_mut().ok_or_else(|| Error::Parse(String::from("move before board in game record")))?;
+                play_move(game, name == "B", &value)?;
+            }
+            _ => {}
+        }
+    }
+
+    ggf.game = board.ok_or_else(|| Error::Parse(String::from("game record has no board")))?;
+
+    Ok((ggf, rest))
+}
+
+/// Splits the record at the start of the text into its tags.
+/// # Returns:
+/// * A list of (tag, value) pairs in the order they appear, and the text after the record.
+fn parse_tags(text: &str) -> Result<(Vec<(String, String)>, &str)> {
+    let mut rest = text.trim_start().strip_prefix("(;")
+        .ok_or_else(|| Error::Parse(String::from("game record does not start with '(;'")))?;
+    let mut tags = Vec::new();
+
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix(";)") {
+            return Ok((tags, after));
+        }
+
+        let name_len = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        if name_len == 0 || !rest[name_len..].starts_with('[') {
+            return Err(Error::Parse(format!("expected a tag at '{}'", rest.chars().take(20).collect::<String>())));
+        }
+        let name = rest[..name_len].to_ascii_uppercase();
+
+        // Values end at the first unescaped ']'.
+        let mut value = String::new();
+        let mut chars = rest[name_len + 1..].char_indices();
+        let mut end = None;
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => value.extend(chars.next().map(|(_, c)| c)),
+                ']' => {
+                    end = Some(name_len + 1 + i + 1);
+                    break;
+                }
+                _ => value.push(c)
+            }
+        }
+
+        let end = end.ok_or_else(|| Error::Parse(format!("unterminated value for tag '{}'", name)))?;
+        tags.push((name, value));
+        rest = &rest[end..];
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<f32> {
+    value.trim().parse().map_err(|_| Error::Parse(format!("invalid number '{}' for tag '{}'", value, name)))
+}
+
+/// Parses the `RE` tag, which is `?` if the result is unknown.
+fn parse_result(value: &str) -> Result<Option<GgfResult>> {
+    if value.trim() == "?" {
+        return Ok(None);
+    }
+
+    let mut parts = value.trim().splitn(2, ':');
+    let score = parse_number("RE", parts.next().unwrap_or(""))?;
+    let ending = match parts.next() {
+        None => Ending::Normal,
+        Some("r") => Ending::Resigned,
+        Some("t") => Ending::Timeout,
+        Some("s") => Ending::Agreed,
+        Some(other) => return Err(Error::Parse(format!("unknown game ending '{}'", other)))
+    };
+
+    Ok(Some(GgfResult { score, ending }))
+}
+
+/// Parses the `BO` tag, which holds the board size, the squares and the side to move. The squares
+/// may be split up by whitespace.
+fn parse_board(value: &str) -> Result<Board> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    if parts.len() < 3 {
+        return Err(Error::Parse(format!("invalid board '{}'", value)));
+    }
+    if parts[0] != "8" {
+        return Err(Error::Parse(format!("unsupported board size '{}', only 8x8 boards are supported", parts[0])));
+    }
+
+    let squares = parts[1..parts.len() - 1].concat();
+    Board::from_position_str(&format!("{} {}", squares, parts[parts.len() - 1]))
+}
+
+/// Plays a move from a `B` or `W` tag, inserting a pass if the other player is to move but has no
+/// moves.
+fn play_move(game: &mut Game, black: bool, value: &str) -> Result<()> {
+    let mut parts = value.split('/');
+
+    let coord = parts.next().unwrap_or("").trim();
+    let m = if coord.eq_ignore_ascii_case("pa") || coord.eq_ignore_ascii_case("pass") {
+        Move::Pass
+    } else {
+        Move::from_coord(coord)?
+    };
+
+    let mut game_move = GameMove::new(m);
+    if let Some(eval) = parts.next().filter(|eval| !eval.trim().is_empty()) {
+        game_move.score = Some((parse_number("eval", eval)? * 100.0).round() as i32);
+    }
+    if let Some(time) = parts.next().filter(|time| !time.trim().is_empty()) {
+        game_move.time = Some((parse_number("time", time)? * 1000.0).round() as u32);
+    }
+
+    if game.board().black_move != black {
+        if game.board().clone().move_count() != 0 {
+            return Err(Error::Parse(format!("{} moved out of turn with '{}'", if black { "black" } else { "white" }, value)));
+        }
+        game.play(Move::Pass)?;
+    }
+
+    game.play_move(game_move)
+}
+
+/// Escapes the characters which would end a tag value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(']', "\\]")
+}
+
+impl fmt::Display for GgfGame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(;GM[Othello]")?;
+
+        let strings = [
+            ("PC", &self.place),
+            ("DT", &self.date),
+            ("PB", &self.black),
+            ("PW", &self.white)
+        ];
+        for (name, value) in strings.iter() {
+            if let Some(value) = value {
+                write!(f, "{}[{}]", name, escape(value))?;
+            }
+        }
+        if let Some(rating) = self.black_rating {
+            write!(f, "RB[{}]", rating)?;
+        }
+        if let Some(rating) = self.white_rating {
+            write!(f, "RW[{}]", rating)?;
+        }
+
+        let clocks = [
+            ("TI", &self.time_control),
+            ("TB", &self.black_clock),
+            ("TW", &self.white_clock)
+        ];
+        for (name, value) in clocks.iter() {
+            if let Some(value) = value {
+                write!(f, "{}[{}]", name, escape(value))?;
+            }
+        }
+        write!(f, "TY[{}]", escape(&self.game_type))?;
+
+        match self.result {
+            Some(GgfResult { score, ending }) => {
+                let suffix = match ending {
+                    Ending::Normal => "",
+                    Ending::Resigned => ":r",
+                    Ending::Timeout => ":t",
+                    Ending::Agreed => ":s"
+                };
+                write!(f, "RE[{:+.3}{}]", score, suffix)?;
+            }
+            None => write!(f, "RE[?]")?
+        }
+
+        let position = self.game.start().to_position_str();
+        let (squares, side) = position.split_at(64);
+        write!(f, "BO[8 {} {}]", squares, side.trim())?;
+
+        let mut black = self.game.start().black_move;
+        for game_move in self.game.moves() {
+            let coord = match game_move.m {
+                Move::Play(_) => game_move.m.to_string(),
+                Move::Pass => String::from("PA")
+            };
+            write!(f, "{}[{}", if black { "B" } else { "W" }, coord)?;
+
+            match (game_move.score, game_move.time) {
+                (None, None) => {}
+                (score, time) => {
+                    write!(f, "/")?;
+                    if let Some(score) = score {
+                        write!(f, "{:.2}", score as f32 / 100.0)?;
+                    }
+                    if let Some(time) = time {
+                        write!(f, "/{:.3}", time as f32 / 1000.0)?;
+                    }
+                }
+            }
+            write!(f, "]")?;
+
+            black = !black;
+        }
+
+        write!(f, ";)")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Error;
+    use crate::board::{ Board, Game, GameMove, Move };
+    use super::{ Ending, GgfGame, GgfResult };
+
+    const RECORD: &str = "(;GM[Othello]PC[GGS/os]DT[2003.12.15_13:24:03.MST]PB[alpha]PW[beta]RB[2197.72]\
+        RW[1850.5]TI[05:00//02:00]TY[8]RE[+6.000:r]\
+        BO[8 -------- -------- -------- ---O*--- ---*O--- -------- -------- -------- *]\
+        B[f5//1.5]W[d6/-0.50/2.25]B[c3/1.00];)";
+
+    #[test]
+    fn test_parse() {
+        let mut ggf = GgfGame::parse(RECORD).unwrap();
+
+        assert_eq!(ggf.place.as_deref(), Some("GGS/os"));
+        assert_eq!(ggf.black.as_deref(), Some("alpha"));
+        assert_eq!(ggf.white.as_deref(), Some("beta"));
+        assert_eq!(ggf.black_rating, Some(2197.72));
+        assert_eq!(ggf.white_rating, Some(1850.5));
+        assert_eq!(ggf.time_control.as_deref(), Some("05:00//02:00"));
+        assert_eq!(ggf.result, Some(GgfResult { score: 6.0, ending: Ending::Resigned }));
+
+        assert_eq!(ggf.game.transcript(), "f5d6c3");
+        assert_eq!(ggf.game.moves()[0], GameMove::new(Move::Play(37)).with_time(1500));
+        assert_eq!(ggf.game.moves()[1], GameMove::new(Move::Play(43)).with_score(-50).with_time(2250));
+        assert_eq!(ggf.game.moves()[2], GameMove::new(Move::Play(18)).with_score(100));
+        assert_eq!(ggf.game.start().to_position_str(), Board::new().to_position_str());
+        assert_eq!(ggf.game.result(), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ggf = GgfGame::parse(RECORD).unwrap();
+        let written = ggf.to_string();
+        let parsed = GgfGame::parse(&written).unwrap();
+
+        assert_eq!(parsed.to_string(), written);
+        assert_eq!(parsed.game.moves(), ggf.game.moves());
+        assert_eq!(parsed.black_rating, ggf.black_rating);
+        assert_eq!(parsed.result, ggf.result);
+
+        // Passes are written explicitly, and names are escaped.
+        let mut game = Game::from_transcript("f5d6c3d3c4f4f6f3e6e7d7c5b5").unwrap();
+        while !game.is_over() {
+            let m = game.board().clone().get_moves()[0];
+            game.play(m).unwrap();
+        }
+        let mut ggf = GgfGame::new(game);
+        ggf.black = Some(String::from("a]b\\c"));
+        let parsed = GgfGame::parse(&ggf.to_string()).unwrap();
+        assert_eq!(parsed.black, ggf.black);
+        assert_eq!(parsed.game.moves(), ggf.game.moves());
+
+        let both = format!("{}\n{}\n", RECORD, ggf);
+        assert_eq!(GgfGame::parse_all(&both).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases = [
+            RECORD.replace("TY[8]", "TY[10]"),
+            RECORD.replace("BO[8", "BO[10"),
+            RECORD.replace("GM[Othello]", "GM[Chess]"),
+            RECORD.replace("RB[2197.72]", "RB[high]"),
+            RECORD.replace("RE[+6.000:r]", "RE[+6.000:x]"),
+            RECORD.replace("W[d6/-0.50/2.25]", "B[d6]"),
+            RECORD.replace(";)", ""),
+            String::from(&RECORD[..RECORD.find("PB[alpha").unwrap() + 8]),
+            String::from("(;GM[Othello];)"),
+            String::from("GM[Othello]")
+        ];
+        for case in cases.iter() {
+            assert!(matches!(GgfGame::parse(case), Err(Error::Parse(_))), "{}", case);
+        }
+
+        assert!(matches!(GgfGame::parse(&RECORD.replace("c3", "a1")), Err(Error::IllegalMove(Move::Play(0)))));
+    }
+}
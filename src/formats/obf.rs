@@ -0,0 +1,186 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Reads and writes test positions in the Othello Board Format used by Edax and other engines for
+//! problem suites such as FFO. Each line holds one position, written as the 64 squares from a1 to
+//! h8 (`X` for black, `O` for white, `-` for empty) and the side to move, optionally followed by
+//! the scores of some of the moves:
+//!
+//! `O--OOOOX-OOOOOOXOOXXOOOXOOXOOOXXOOOOOOXX---OOOOX----O--X-------- X; A2:+38;`
+//!
+//! Scores are final disk differences relative to the side to move. Anything after a `%` is a
+//! comment, and blank lines are skipped.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::{ Error, Result };
+use crate::board::{ Board, Move };
+
+/// A test position, along with the known scores of its moves.
+#[derive(Clone, Debug)]
+pub struct ObfPosition {
+    pub board: Board,
+    /// The exact scores of some or all of the moves, relative to the side to move.
+    pub moves: Vec<(Move, i32)>,
+    /// The comment at the end of the line, if any.
+    pub comment: Option<String>
+}
+
+impl ObfPosition {
+    /// Creates a position with no known move scores.
+    pub fn new(board: Board) -> ObfPosition {
+        ObfPosition {
+            board,
+            moves: Vec::new(),
+            comment: None
+        }
+    }
+
+    /// Parses a single line of a suite.
+    /// # Returns:
+    /// * The position, or an error if the line is malformed or a scored move is illegal.
+    pub fn parse(line: &str) -> Result<ObfPosition> {
+        let (line, comment) = match line.find('%') {
+            Some(i) => (&line[..i], Some(line[i + 1..].trim()).filter(|c| !c.is_empty()).map(String::from)),
+            None => (line, None)
+        };
+
+        let mut fields = line.split(';').map(str::trim);
+        let mut pos = fields.next().unwrap_or("").split_whitespace();
+        let (squares, side) = match (pos.next(), pos.next(), pos.next()) {
+            (Some(squares), Some(side), None) => (squares, side),
+            _ => return Err(Error::Parse(format!("invalid OBF position '{}'", line.trim())))
+        };
+
+        let squares: String = squares.chars().map(|c| match c {
+            'X' | 'x' | '*' => '*',
+            'O' | 'o' => 'O',
+            '-' | '.' => '-',
+            other => other
+        }).collect();
+        let side = match side {
+            "X" | "x" | "*" => "*",
+            "O" | "o" => "O",
+            other => return Err(Error::Parse(format!("invalid side to move '{}'", other)))
+        };
+        let mut board = Board::from_position_str(&format!("{} {}", squares, side))?;
+
+        let legal = board.get_moves();
+        let mut moves = Vec::new();
+        for field in fields.filter(|f| !f.is_empty()) {
+            let mut parts = field.split(':');
+            let (coord, score) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(coord), Some(score), None) => (coord.trim(), score.trim()),
+                _ => return Err(Error::Parse(format!("invalid OBF move '{}'", field)))
+            };
+
+            let m = if coord.eq_ignore_ascii_case("pa") || coord.eq_ignore_ascii_case("ps") {
+                Move::Pass
+            } else {
+                Move::from_coord(coord)?
+            };
+            if !legal.contains(m) {
+                return Err(Error::IllegalMove(m));
+            }
+            let score = score.parse().map_err(|_| Error::Parse(format!("invalid OBF score '{}'", score)))?;
+
+            moves.push((m, score));
+        }
+
+        Ok(ObfPosition { board, moves, comment })
+    }
+
+    /// Parses every position in a suite, skipping blank lines and comments.
+    pub fn parse_all(text: &str) -> Result<Vec<ObfPosition>> {
+        text.lines()
+            .filter(|line| !line.split('%').next().unwrap_or("").trim().is_empty())
+            .map(ObfPosition::parse)
+            .collect()
+    }
+
+    /// Reads every position in the suite file at the given path.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<ObfPosition>> {
+        ObfPosition::parse_all(&fs::read_to_string(path)?)
+    }
+
+    /// Returns the best known score, or None if no moves are scored.
+    pub fn best_score(&self) -> Option<i32> {
+        self.moves.iter().map(|&(_, score)| score).max()
+    }
+
+    /// Returns every move with the best known score.
+    pub fn best_moves(&self) -> Vec<Move> {
+        let best = self.best_score();
+        self.moves.iter().filter(|&&(_, score)| Some(score) == best).map(|&(m, _)| m).collect()
+    }
+}
+
+impl fmt::Display for ObfPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{};", self.board.to_position_str().replace('*', "X"))?;
+
+        for &(m, score) in &self.moves {
+            let coord = match m {
+                Move::Play(_) => m.to_string().to_uppercase(),
+                Move::Pass => String::from("PA")
+            };
+            write!(f, " {}:{:+};", coord, score)?;
+        }
+
+        if let Some(comment) = &self.comment {
+            write!(f, " % {}", comment)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Error;
+    use crate::board::{ Board, Move };
+    use super::ObfPosition;
+
+    const FFO_40: &str = "O--OOOOX-OOOOOOXOOXXOOOXOOXOOOXXOOOOOOXX---OOOOX----O--X-------- X; A2:+38; C7:+36; B1:+30;";
+
+    #[test]
+    fn test_parse() {
+        let pos = ObfPosition::parse(FFO_40).unwrap();
+        assert_eq!(pos.board.get_position(), Board::from_pos(0x0101312303010100, 0x9E7ECEDCFC1E0800, true).get_position());
+        assert_eq!(pos.moves, vec![(Move::Play(8), 38), (Move::Play(50), 36), (Move::Play(1), 30)]);
+        assert_eq!(pos.best_score(), Some(38));
+        assert_eq!(pos.best_moves(), vec![Move::Play(8)]);
+        assert_eq!(pos.comment, None);
+
+        // Round trip, with a comment and alternate square characters.
+        let line = format!("{} % #40", FFO_40.replace('-', ".").replace('X', "x"));
+        let pos = ObfPosition::parse(&line).unwrap();
+        assert_eq!(pos.comment.as_deref(), Some("#40"));
+        assert_eq!(pos.to_string(), format!("{} % #40", FFO_40));
+
+        let suite = format!("% FFO\n\n{}\n{}\n", FFO_40, FFO_40.split(';').next().unwrap());
+        let positions = ObfPosition::parse_all(&suite).unwrap();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions[1].best_score(), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases = [
+            FFO_40.replace(" X;", ";"),
+            FFO_40.replace(" X;", " Y;"),
+            FFO_40.replace("O--", "O-"),
+            FFO_40.replace("A2:+38", "A2"),
+            FFO_40.replace("A2:+38", "A2:big"),
+            FFO_40.replace("A2:+38", "Z9:+38")
+        ];
+        for case in cases.iter() {
+            assert!(matches!(ObfPosition::parse(case), Err(Error::Parse(_))), "{}", case);
+        }
+
+        assert!(matches!(ObfPosition::parse(&FFO_40.replace("A2:+38", "H8:+38")), Err(Error::IllegalMove(Move::Play(63)))));
+    }
+}
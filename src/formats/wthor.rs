@@ -0,0 +1,263 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Reads game databases in the binary WTHOR (`.wtb`) format used by the French Othello
+//! federation. A file is a 16 byte header followed by fixed size 68 byte game records, with all
+//! numbers little endian.
+//!
+//! Each record holds the tournament and player numbers, black's final disk count, black's disk
+//! count with perfect play from a given depth, and up to 60 moves. Moves are stored as
+//! `10 * row + column`, counting from 1, so `11` is a1 and `88` is h8. Passes are not stored and
+//! unused moves at the end of a game are 0.
+//!
+//! Games are streamed one at a time and replayed as they are read, so that illegal moves are
+//! caught.
+
+use std::fs::File;
+use std::io::{ BufReader, Read };
+use std::path::Path;
+
+use crate::{ Error, Result };
+use crate::board::{ Game, Move };
+
+const HEADER_SIZE: usize = 16;
+const RECORD_SIZE: usize = 68;
+const RECORD_MOVES: usize = 60;
+
+/// The information at the start of a WTHOR file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WthorHeader {
+    /// The date the file was created, as (year, month, day).
+    pub created: (u16, u8, u8),
+    /// The number of games in the file.
+    pub game_count: u32,
+    /// The year the games were played in.
+    pub year: u16,
+    /// The depth, in empties, from which the theoretical scores were computed.
+    pub depth: u8
+}
+
+/// A game read from a WTHOR file.
+#[derive(Clone, Debug)]
+pub struct WthorGame {
+    /// The index of the tournament in the accompanying `.TRN` file.
+    pub tournament: u16,
+    /// The index of the black player in the accompanying `.JOU` file.
+    pub black: u16,
+    /// The index of the white player in the accompanying `.JOU` file.
+    pub white: u16,
+    /// Black's disk count at the end of the game, with any empties given to the winner.
+    pub score: u8,
+    /// Black's disk count with perfect play from the position at `WthorHeader::depth` empties.
+    pub theoretical_score: u8,
+    /// The game, including passes.
+    pub game: Game
+}
+
+impl WthorGame {
+    /// Converts a disk count from a record into a score in the usual form, black's disks minus
+    /// white's.
+    pub fn disk_difference(disks: u8) -> i32 {
+        2 * i32::from(disks) - 64
+    }
+}
+
+/// Streams the games out of a WTHOR file.
+pub struct WthorReader<R: Read> {
+    reader: R,
+    header: WthorHeader,
+    remaining: u32
+}
+
+impl WthorReader<BufReader<File>> {
+    /// Opens the WTHOR file at the given path and reads its header.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        WthorReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> WthorReader<R> {
+    /// Reads the header from the given reader.
+    /// # Returns:
+    /// * The reader, or an error if the header cannot be read or is not for 8x8 games.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut bytes = [0u8; HEADER_SIZE];
+        reader.read_exact(&mut bytes)?;
+
+        // Older files leave the board size as 0, meaning 8x8.
+        if bytes[12] != 0 && bytes[12] != 8 {
+            return Err(Error::Parse(format!("unsupported WTHOR board size {}, only 8x8 boards are supported", bytes[12])));
+        }
+        if bytes[13] != 0 {
+            return Err(Error::Parse(String::from("WTHOR file contains solitaires rather than games")));
+        }
+
+        let header = WthorHeader {
+            created: (u16::from(bytes[0]) * 100 + u16::from(bytes[1]), bytes[2], bytes[3]),
+            game_count: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            year: u16::from_le_bytes([bytes[10], bytes[11]]),
+            depth: bytes[14]
+        };
+
+        Ok(WthorReader {
+            reader,
+            header,
+            remaining: header.game_count
+        })
+    }
+
+    pub fn header(&self) -> &WthorHeader {
+        &self.header
+    }
+
+    fn read_game(&mut self) -> Result<WthorGame> {
+        let mut bytes = [0u8; RECORD_SIZE];
+        self.reader.read_exact(&mut bytes)?;
+
+        let mut game = Game::new();
+        let moves = &bytes[RECORD_SIZE - RECORD_MOVES..];
+        let length = moves.iter().position(|&b| b == 0).unwrap_or(RECORD_MOVES);
+        if moves[length..].iter().any(|&b| b != 0) {
+            return Err(Error::Parse(String::from("WTHOR game has a gap in its moves")));
+        }
+
+        for &b in &moves[..length] {
+            let (row, col) = (b / 10, b % 10);
+            if !(1..=8).contains(&row) || !(1..=8).contains(&col) {
+                return Err(Error::Parse(format!("invalid WTHOR move {}", b)));
+            }
+            let m = Move::Play((col - 1) + (row - 1) * 8);
+
+            let mut board = game.board().clone();
+            if board.move_count() == 0 && !board.is_game_over() {
+                game.play(Move::Pass)?;
+            }
+            game.play(m)?;
+        }
+
+        Ok(WthorGame {
+            tournament: u16::from_le_bytes([bytes[0], bytes[1]]),
+            black: u16::from_le_bytes([bytes[2], bytes[3]]),
+            white: u16::from_le_bytes([bytes[4], bytes[5]]),
+            score: bytes[6],
+            theoretical_score: bytes[7],
+            game
+        })
+    }
+}
+
+/// Reads games until the count in the header is reached. After an error no more games are read,
+/// as the rest of the file cannot be trusted.
+impl<R: Read> Iterator for WthorReader<R> {
+    type Item = Result<WthorGame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let game = self.read_game();
+        self.remaining = if game.is_ok() { self.remaining - 1 } else { 0 };
+
+        Some(game)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+
+    use crate::Error;
+    use crate::board::{ Game, Move };
+    use super::{ WthorGame, WthorHeader, WthorReader };
+
+    fn header(games: u32) -> Vec<u8> {
+        let mut bytes = vec![20, 19, 7, 14];
+        bytes.extend(&games.to_le_bytes());
+        bytes.extend(&[0, 0]);
+        bytes.extend(&2019u16.to_le_bytes());
+        bytes.extend(&[8, 0, 22, 0]);
+        bytes
+    }
+
+    fn record(game: &Game, score: u8) -> Vec<u8> {
+        let mut bytes = vec![3, 0, 1, 1, 2, 0, score, 34];
+        for gm in game.moves() {
+            if let Move::Play(m) = gm.m {
+                bytes.push((m / 8 + 1) * 10 + m % 8 + 1);
+            }
+        }
+        bytes.resize(super::RECORD_SIZE, 0);
+        bytes
+    }
+
+    #[test]
+    fn test_read() {
+        let mut games = vec![Game::from_transcript("f5d6c3d3c4").unwrap()];
+
+        // Random games will often contain passes, which the file leaves out.
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let mut game = Game::new();
+            while !game.is_over() {
+                let moves = game.board().clone().get_moves();
+                game.play(moves[rng.gen_range(0, moves.len())]).unwrap();
+            }
+            games.push(game);
+        }
+
+        let mut bytes = header(games.len() as u32);
+        for game in &games {
+            bytes.extend(record(game, 40));
+        }
+
+        let mut reader = WthorReader::new(&bytes[..]).unwrap();
+        assert_eq!(*reader.header(), WthorHeader {
+            created: (2019, 7, 14),
+            game_count: games.len() as u32,
+            year: 2019,
+            depth: 22
+        });
+
+        let read: Vec<WthorGame> = reader.by_ref().map(|g| g.unwrap()).collect();
+        assert_eq!(read.len(), games.len());
+        for (read, game) in read.iter().zip(games.iter()) {
+            assert_eq!(read.game.moves(), game.moves());
+            assert_eq!((read.tournament, read.black, read.white), (3, 257, 2));
+            assert_eq!(read.score, 40);
+            assert_eq!(WthorGame::disk_difference(read.theoretical_score), 4);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let game = Game::from_transcript("f5d6c3").unwrap();
+
+        let mut bytes = header(1);
+        bytes[12] = 10;
+        assert!(matches!(WthorReader::new(&bytes[..]), Err(Error::Parse(_))));
+        assert!(matches!(WthorReader::new(&bytes[..8]), Err(Error::Io(_))));
+
+        // A truncated game, an illegal move, an invalid square and a gap in the moves.
+        let mut bytes = header(2);
+        bytes.extend(record(&game, 33));
+        bytes.extend(&record(&game, 33)[..20]);
+        let results: Vec<_> = WthorReader::new(&bytes[..]).unwrap().collect();
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Error::Io(_))));
+
+        let cases = [(10, 11), (10, 19), (9, 0)];
+        for &(i, b) in cases.iter() {
+            let mut bytes = header(1);
+            let mut rec = record(&game, 33);
+            rec[i] = b;
+            bytes.extend(rec);
+            let result = WthorReader::new(&bytes[..]).unwrap().next().unwrap();
+            match b {
+                11 => assert!(matches!(result, Err(Error::IllegalMove(Move::Play(0))))),
+                _ => assert!(matches!(result, Err(Error::Parse(_))))
+            }
+        }
+    }
+}
@@ -2,6 +2,11 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+pub mod error;
 pub mod board;
 pub mod search;
-pub mod ml;
\ No newline at end of file
+pub mod ml;
+pub mod nboard;
+pub mod formats;
+
+pub use error::{ Error, Result };
@@ -5,38 +5,35 @@
 #[macro_use]
 extern crate clap;
 
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::{ self, BufRead, BufReader, BufWriter, Write };
 use std::time::Instant;
 
-use clap::App;
+use clap::{ App, ArgMatches };
 use rand::Rng;
 use rayon::prelude::*;
-use ruthless::board::{ self, Move, Board, Position };
-use ruthless::search::{ endgame, negamax, bns, iterative, nm_new, eval::{ PatternEvaluator, StagedPatternEvaluator } };
+use rayon::ThreadPoolBuilder;
+use ruthless::board::{ self, Move, Board, Game, GameMove, compact::CompactBoard, perft::{ self, PerftTable } };
+use ruthless::search::{ endgame, negamax, bns, nm_new, mcts, SearchLimits, SearchResult, Searcher, format_pv };
+use ruthless::search::eval::{ Evaluator, StagedPatternEvaluator };
 use ruthless::search::endgame::EndgameSearcher;
-use ruthless::ml::{ self, eval::{ StagedRLPatternEvaluator, RLPatternEvaluator } };
-use serde::Deserialize;
+use ruthless::search::probcut::{ self, ProbCutParams, Sample };
+use ruthless::nboard::NBoardEngine;
+use ruthless::formats::{ obf::ObfPosition, wthor::WthorReader };
+use ruthless::ml::{ self, TrainingData, eval::StagedRLPatternEvaluator };
 use serde_json::{ from_reader, to_writer };
 
-#[derive(Deserialize)]
-struct PatternFile {
-    masks: Vec<u64>,
-    weights: Vec<Vec<f32>>,
-    parity_e: f32,
-    parity_o: f32
-}
-
 fn main() {
     let yaml = load_yaml!("cli.yml");
     let matches = App::from_yaml(yaml).get_matches();
 
     if let Some(perft_matches) = matches.subcommand_matches("perft") {
         let depth_str = perft_matches.value_of("DEPTH").unwrap();
-        if let Ok(depth) = depth_str.parse::<u8>() {
-            perft(depth);
-        } else {
-            panic!("DEPTH must be a positive integer less than 256.");
+        let hash_mb = perft_matches.value_of("hash").map(|mb| mb.parse::<usize>().expect("MB must be a positive integer."));
+        match depth_str.parse::<u8>() {
+            Ok(depth) if depth > 0 => perft(depth, perft_matches.is_present("divide"), hash_mb, threads(perft_matches)),
+            _ => panic!("DEPTH must be a positive integer less than 256.")
         }
     }
 
@@ -48,23 +45,23 @@ fn main() {
 
         if let Ok(empties) = empties_str.parse::<u8>() {
             if let Ok(num_pos) = num_pos_str.parse::<usize>() {
-                if let Ok(depth) = depth_maybe_str.parse::<u8>() {
-                    let positions = training_data_heuristic(empties, depth, num_pos);
-                    println!("Serializing position data...");
-                    let json_out = serde_json::to_string(&positions).unwrap_or(String::new());
-                    println!("Writing output to file...");
-                    let mut file = File::create(output_file).unwrap();
-                    file.write_all(json_out.as_bytes()).expect("Unable to write to output file.");
-                    println!("Done.");
+                let boards = match gtd.values_of("wthor") {
+                    Some(files) => wthor_boards(files.collect(), empties, num_pos),
+                    None => random_boards(empties, num_pos)
+                };
+
+                let data = if let Ok(depth) = depth_maybe_str.parse::<u8>() {
+                    println!("Solving positions...");
+                    TrainingData::heuristic(boards, depth, &eval(gtd))
                 } else {
-                    let positions = training_data_solve(empties, num_pos);
-                    println!("Serializing position data...");
-                    let json_out = serde_json::to_string(&positions).unwrap_or(String::new());
-                    println!("Writing output to file...");
-                    let mut file = File::create(output_file).unwrap();
-                    file.write_all(json_out.as_bytes()).expect("Unable to write to output file.");
-                    println!("Done.");
-                }
+                    TrainingData::solve(boards, eval(gtd))
+                };
+                println!("Serializing position data...");
+                let json_out = serde_json::to_string(&data).unwrap_or_default();
+                println!("Writing output to file...");
+                let mut file = File::create(output_file).unwrap();
+                file.write_all(json_out.as_bytes()).expect("Unable to write to output file.");
+                println!("Done.");
             } else {
                 panic!("NUM_POSITIONS must be a positive integer.");
             }
@@ -73,8 +70,24 @@ fn main() {
         }
     }
 
-    if let Some(_play_matches) = matches.subcommand_matches("play") {
-        play();
+    if let Some(play_matches) = matches.subcommand_matches("play") {
+        play(eval(play_matches), threads(play_matches), probcut_params(play_matches));
+    }
+
+    if let Some(bench_matches) = matches.subcommand_matches("bench") {
+        let depth = bench_matches.value_of("depth").unwrap_or("9").parse::<u8>().expect("depth must be a positive integer less than 256.");
+        let suite = bench_matches.value_of("suite").unwrap_or("suites/ffo.obf");
+        let count = bench_matches.value_of("count").unwrap_or("1").parse::<usize>().expect("count must be a positive integer.");
+        bench(depth, suite, count, eval(bench_matches), threads(bench_matches));
+    }
+
+    if let Some(solve_matches) = matches.subcommand_matches("solve") {
+        let file = solve_matches.value_of("FILE").unwrap();
+        let wld = solve_matches.is_present("wld");
+        let check = solve_matches.is_present("check");
+        if !solve(file, wld, check, eval(solve_matches), threads(solve_matches)) {
+            std::process::exit(1);
+        }
     }
 
     if let Some(sp_matches) = matches.subcommand_matches("self-play") {
@@ -86,7 +99,7 @@ fn main() {
         if let Ok(num_games) = num_str.parse::<u64>() {
             if let Ok(lr) = lr_str.parse::<f32>() {
                 if let Ok(ex) = ex_str.parse::<f32>() {
-                    let mut eval_st;
+                    let mut eval_st: StagedRLPatternEvaluator;
                     if let Some(input) = sp_matches.value_of("INPUT") {
                         let file = File::open(input).expect("File read error.");
                         let reader = BufReader::new(file);
@@ -132,7 +145,7 @@ fn main() {
                         // );
                     }
 
-                    eval_st = ml::self_play(eval_st, lr, ex, 2000, num_games as usize);
+                    eval_st = ml::self_play(eval_st, &eval(sp_matches), lr, ex, 2000, num_games as usize);
 
                     let file = File::create(output).expect("Unable to create file.");
                     let writer = BufWriter::new(file);
@@ -154,12 +167,14 @@ fn main() {
 
         if let Ok(deep) = pc_deep_str.parse::<u8>() {
             if let Ok(shallow) = pc_shallow_str.parse::<u8>() {
-                let mut rng = rand::thread_rng();
+                if shallow == 0 {
+                    panic!("SHALLOW must be a positive integer less than 256.");
+                }
 
-                let pat_eval = StagedPatternEvaluator::from_file("end_ms.json").expect("Unable to load evaluator");
+                let mut rng = rand::thread_rng();
 
-                let mut searcher = nm_new::NegamaxSearcher::with_eval(pat_eval);
-                searcher.set_verbose(1);
+                let mut searcher = nm_new::NegamaxSearcher::with_eval(eval(pct));
+                searcher.set_verbose(0);
 
                 for i in 0..100000 {
                     let start_depth = rng.gen_range(shallow, 63 - (deep + 1));
@@ -171,15 +186,15 @@ fn main() {
                         board.make_move(moves[rng.gen::<usize>() % moves.len()]);
                     }
 
-                    // Calculate scores
-                    let deep_score = searcher.search_to_depth(&mut board, deep).0;
-                    let shallow_score = searcher.search_to_depth(&mut board, shallow).0;
+                    // Calculate scores. The table is cleared first, and the shallow search runs before
+                    // the deep one, so that the shallow score never comes from deeper results.
+                    searcher.clear_table();
+                    let shallow_score = searcher.search_to_depth(&mut board, shallow).score;
+                    let deep_score = searcher.search_to_depth(&mut board, deep).score;
 
-                    if board.black_move {
-                        println!("{}, {}", deep_score, shallow_score);
-                    } else {
-                        println!("{}, {}", -deep_score, -shallow_score);
-                    }
+                    // Scores are relative to the player to move, as they are during a search.
+                    let disks = board.all_disks().count_ones();
+                    println!("{}, {}, {}, {}, {}", deep, shallow, disks, deep_score, shallow_score);
 
                     if i % 1000 == 0 {
                         eprintln!("{}", i);
@@ -193,18 +208,67 @@ fn main() {
         }
     }
 
+    if let Some(pcf) = matches.subcommand_matches("pc-fit") {
+        let output = pcf.value_of("OUTPUT").unwrap();
+
+        let mut samples = Vec::new();
+        for input in pcf.values_of("INPUT").unwrap() {
+            let file = File::open(input).expect("Unable to open input file.");
+            for line in BufReader::new(file).lines() {
+                let line = line.expect("Unable to read input file.");
+                if let Some(sample) = Sample::from_line(&line) {
+                    samples.push(sample);
+                }
+            }
+        }
+
+        let params = ProbCutParams::fit(&samples, probcut::DEFAULT_THRESHOLD, probcut::DEFAULT_STAGE_SIZE);
+        for pair in &params.pairs {
+            let fitted = pair.stages.iter().filter(|fit| fit.is_some()).count();
+            println!("Depth pair {}/{}: fitted {} of {} stages.", pair.deep, pair.shallow, fitted, pair.stages.len());
+        }
+
+        let file = File::create(output).expect("Unable to create file.");
+        let writer = BufWriter::new(file);
+        to_writer(writer, &params).expect("Unable to write to file.");
+    }
+
     if let Some(cs2_matches) = matches.subcommand_matches("cs2l") {
         let board = board::Board::new();
         let black = cs2_matches.value_of("COLOR").unwrap() == "Black";
+        let algorithm = cs2_matches.value_of("ALGORITHM").unwrap_or("pvs");
+
+        cs2_play(board, black, algorithm, eval(cs2_matches), threads(cs2_matches), probcut_params(cs2_matches));
+    }
 
-        cs2_play(board, black);
+    if let Some(nboard_matches) = matches.subcommand_matches("nboard") {
+        nboard_play(eval(nboard_matches), threads(nboard_matches), probcut_params(nboard_matches));
     }
 }
 
-fn play() {
-    let mut board = board::Board::new();
+/// Reads the number of search threads from the command line, defaulting to one.
+fn threads(matches: &ArgMatches) -> usize {
+    match matches.value_of("threads").map(|t| t.parse::<usize>()) {
+        Some(Ok(threads)) if threads > 0 => threads,
+        Some(_) => panic!("THREADS must be a positive integer."),
+        None => 1
+    }
+}
+
+/// Loads the evaluator given on the command line, falling back to the `RUTHLESS_EVAL` environment
+/// variable and then to the built-in weights.
+fn eval(matches: &ArgMatches) -> StagedPatternEvaluator {
+    StagedPatternEvaluator::load(matches.value_of("eval")).unwrap_or_else(|err| panic!("Unable to load evaluator: {}", err))
+}
+
+/// Loads the Multi-ProbCut parameters given on the command line, if selective search is enabled.
+fn probcut_params(matches: &ArgMatches) -> Option<ProbCutParams> {
+    matches.value_of("probcut").map(|path| ProbCutParams::from_file(path).expect("Unable to load ProbCut parameters."))
+}
+
+fn play(pat_eval: StagedPatternEvaluator, threads: usize, probcut: Option<ProbCutParams>) {
+    let mut game = Game::new();
     let stdin = io::stdin();
-    let mut undo_stack: Vec<(u64, Move)> = Vec::new();
 
     let print_info = |b: &mut board::Board| {
         println!("\n{}", b);
@@ -217,132 +281,160 @@ fn play() {
         io::stdout().flush().expect("Unable to flush stdout.");
     };
 
-    let pat_eval = StagedPatternEvaluator::from_file("end_ms.json").expect("Unable to load evaluator.");
-
-    print_info(&mut board);
+    print_info(&mut game.board().clone());
 
     for line in stdin.lock().lines() {
         // Get input from the player.
         if let Ok(text) = line {
             let split: Vec<&str> = text.split(' ').collect();
+            let mut board = game.board().clone();
 
             if split[0] == "exit" {
                 break;
             } else if split[0] == "play" {
-                let m = Move::from_coord(split[1]);
+                let m = match split.get(1) {
+                    Some(coord) if coord.eq_ignore_ascii_case("pass") => Ok(Move::Pass),
+                    coord => Move::from_coord(coord.unwrap_or(&""))
+                };
 
-                if board.get_moves().contains(m) {
-                    println!("Playing move: {}", m);
-                    // Make the move and save for undo.
-                    let undo_info = board.make_move(m);
-                    undo_stack.push((undo_info, m));
-                } else {
-                    println!("Invalid move. Try another.");
+                match m.and_then(|m| game.play(m).map(|_| m)) {
+                    Ok(m) => println!("Playing move: {}", m),
+                    Err(err) => println!("Invalid move ({}). Try another.", err)
                 }
             } else if split[0] == "undo" {
-                // Pop a move off the undo stack (if possible) and undo it.
-                if let Some((undo, m)) = undo_stack.pop() {
-                    board.undo_move(undo, m);
-                    println!("Undoing move {}", m);
-                } else {
-                    println!("No moves to undo!");
+                match game.undo() {
+                    Some(m) => println!("Undoing move {}", m),
+                    None => println!("No moves to undo!")
+                }
+            } else if split[0] == "redo" {
+                match game.redo() {
+                    Some(m) => println!("Redoing move {}", m),
+                    None => println!("No moves to redo!")
                 }
             } else if split[0] == "go" {
                 // Get the search depth
                 let depth = match split.get(1) {
-                    Some(dep) => match dep.parse::<u8>() { Ok(x) => x, _ => 8 },
+                    Some(dep) => dep.parse::<u8>().unwrap_or(8),
                     None => 8
                 };
 
                 // Get the best move.
-                let (score, best_move, _) = if depth <= board.all_disks().count_zeros() as u8 {
+                let (result, score) = if depth <= board.all_disks().count_zeros() as u8 {
                     // If the search is not full depth, then run a normal search.
-                    if let Some(&alg) = split.get(2) {
-                        match alg {
-                            "nm" => negamax::negamax(&mut board, depth, &pat_eval, true),
-                            "bns" => bns::best_node_search(&mut board, depth, &pat_eval),
-                            _ => negamax::negamax(&mut board, depth, &pat_eval, true)
-                        }
-                    } else {
-                        negamax::negamax(&mut board, depth, &pat_eval, true)
-                    }
+                    let mut searcher = make_searcher(split.get(2).unwrap_or(&"nm"), &pat_eval, true, threads, probcut.clone());
+                    let result = searcher.search(&mut board, SearchLimits::new().with_depth(depth));
+                    let score = result.score;
+                    (result, score)
                 } else {
                     // If the search will be full-depth, then just endgame solve.
-                    endgame::endgame_solve(&mut board, false, true)
+                    let mut solver = EndgameSearcher::with_eval(pat_eval.clone(), true);
+                    solver.set_threads(threads).expect("Unable to create thread pool.");
+                    let result = solver.endgame_solve(&mut board, false);
+                    let score = result.score * 100;
+                    (result, score)
                 };
 
-                println!("Computer is playing {}, which had score {}.", best_move, score);
-                // Make move and save undo information.
-                let undo_info = board.make_move(best_move);
-                undo_stack.push((undo_info, best_move));
+                println!("Computer is playing {}, which had score {}.", result.best_move, result.score);
+                println!("Principal variation: {}", format_pv(&result.pv));
+                play_result(&mut game, &result, Some(score));
             } else if split[0] == "gt" {
                 // Get the search depth
                 let time = match split.get(1) {
-                    Some(dep) => match dep.parse::<u32>() { Ok(x) => x, _ => 1000 },
+                    Some(dep) => dep.parse::<u32>().unwrap_or(1000),
                     None => 1000
                 };
 
                 // Get the best move.
-                let (score, best_move, _) = if let Some(&alg) = split.get(2) {
-                    match alg {
-                        "nm" => negamax::negamax_id(&mut board, time, &pat_eval, false),
-                        "bns" => iterative::bns_iter_deep(&mut board, time, &pat_eval),
-                        _ => negamax::negamax_id(&mut board, time, &pat_eval, true)
-                    }
-                } else {
-                    negamax::negamax_id(&mut board, time, &pat_eval, true)
-                };
-
-                println!("Computer is playing {}, which had score {}.", best_move, score);
-                // Make move and save undo information.
-                let undo_info = board.make_move(best_move);
-                undo_stack.push((undo_info, best_move));
+                let mut searcher = make_searcher(split.get(2).unwrap_or(&"nm"), &pat_eval, true, threads, probcut.clone());
+                let result = searcher.search(&mut board, SearchLimits::new().with_time(time));
+
+                println!("Computer is playing {}, which had score {}.", result.best_move, result.score);
+                println!("Principal variation: {}", format_pv(&result.pv));
+                play_result(&mut game, &result, Some(result.score));
+            } else if split[0] == "moves" {
+                println!("Game so far: {}", game);
             } else {
                 println!("Invalid action. Must be one of:");
-                let actions = vec![ "play <coord>", "go [depth] [algorithm]", "undo", "exit" ];
+                let actions = vec![ "play <coord|pass>", "go [depth] [algorithm]", "gt [millis] [algorithm]", "undo", "redo", "moves", "exit" ];
                 for action in actions {
                     println!("  - {}", action);
                 }
             }
 
             // Handle a game over.
-            if board.is_game_over() {
-                let black_disks = board.black_disks.count_ones();
-                let white_disks = board.white_disks.count_ones();
+            if let Some(score) = game.result() {
+                let board = game.board();
+                println!("Game Over! Score {} to {}", board.black_disks.count_ones(), board.white_disks.count_ones());
 
-                println!("Game Over! Score {} to {}", black_disks, white_disks);
-
-                if black_disks > white_disks {
+                if score > 0 {
                     println!("BLACK wins!");
-                } else if black_disks < white_disks {
+                } else if score < 0 {
                     println!("WHITE wins!");
                 } else {
                     println!("TIE!");
                 }
                 println!("\n{}", board);
+                println!("Game: {}", game);
                 break;
             }
 
             // Print info.
-            print_info(&mut board);
+            print_info(&mut game.board().clone());
         } else {
             break;
         }
     }
 }
 
-fn cs2_play(mut board: Board, black: bool) {
+/// Plays the best move of a search in the game, recording its score and the time taken.
+/// # Arguments:
+/// * `game`: The game to play the move in.
+/// * `result`: The result of the search.
+/// * `score`: The score of the move in centi-disks, if it has one.
+fn play_result(game: &mut Game, result: &SearchResult, score: Option<i32>) {
+    let mut game_move = GameMove::new(result.best_move).with_time(result.data.time);
+    game_move.score = score;
+    if let Err(err) = game.play_move(game_move) {
+        eprintln!("Search returned an invalid move ({}).", err);
+    }
+}
+
+/// Creates the searcher with the given name: `nm` for Negamax, `bns` for Best Node Search, `pvs`
+/// for the principal variation searcher, or `mcts` for Monte Carlo Tree Search with evaluator-guided
+/// playouts. Unknown names fall back to Negamax.
+/// # Arguments:
+/// * `name`: The name of the search algorithm.
+/// * `eval`: The evaluator for the searcher to use.
+/// * `print`: Whether to print search information. `pvs` prints to stderr, the others to stdout.
+/// * `threads`: The number of threads for `pvs` to use.
+/// * `probcut`: The Multi-ProbCut parameters for `pvs`, if it should search selectively.
+fn make_searcher<'a, E: Evaluator + Send + Sync + 'a>(name: &str, eval: E, print: bool, threads: usize, probcut: Option<ProbCutParams>) -> Box<dyn Searcher + 'a> {
+    match name {
+        "bns" => Box::new(bns::BestNodeSearch::new(eval, print)),
+        "pvs" => {
+            let mut searcher = nm_new::NegamaxSearcher::with_eval(eval);
+            searcher.set_threads(threads);
+            searcher.set_probcut(probcut);
+            searcher.set_verbose(print as u8);
+            searcher.set_output(Box::new(io::stderr()));
+            Box::new(searcher)
+        }
+        "mcts" => Box::new(mcts::MonteCarloSearcher::new(mcts::EvalPlayout::new(eval), print)),
+        _ => Box::new(negamax::Negamax::new(eval, print))
+    }
+}
+
+fn cs2_play(board: Board, black: bool, algorithm: &str, pat_eval: StagedPatternEvaluator, threads: usize, probcut: Option<ProbCutParams>) {
     let stdin = io::stdin();
     let mut first_move = true;
     let mut last_bf = 10.0;
+    let mut game = Game::from_board(board);
 
-    let pat_eval = StagedPatternEvaluator::from_file("end_ms.json").expect("Unable to load evaluator");
-
-    let mut searcher = nm_new::NegamaxSearcher::with_eval(pat_eval);
-    searcher.set_output(Box::new(io::stderr()));
+    // The searcher must not print to stdout, since that is used to communicate with the referee.
+    let mut searcher = make_searcher(algorithm, pat_eval, algorithm == "pvs", threads, probcut);
 
     eprintln!("Initialized...");
-    println!("");
+    println!();
 
     for line in stdin.lock().lines() {
         let line = line.unwrap();
@@ -350,7 +442,7 @@ fn cs2_play(mut board: Board, black: bool) {
 
         eprintln!(
             "\nRuthless: Making {} Move",
-            if !board.black_move { "Dark" } else { "Light" }
+            if !game.board().black_move { "Dark" } else { "Light" }
         );
 
         let line_split: Vec<&str> = line.split(" ").collect();
@@ -358,55 +450,55 @@ fn cs2_play(mut board: Board, black: bool) {
         let x: i8 = str::parse::<i8>(line_split[0]).unwrap();
         let y: i8 = str::parse::<i8>(line_split[1]).unwrap();
         let ms_left: i64 = str::parse::<i64>(line_split[2]).unwrap();
-        if x >= 0 && y >= 0 {
-            let coord: u8 = (y * 8 + x) as u8;
-            board.make_move(Move::Play(coord));
-            eprintln!("Move: {}", Move::Play(coord));
+        let opponent_move = if x >= 0 && y >= 0 {
+            Some(Move::Play((y * 8 + x) as u8))
         } else if black && first_move {
             eprintln!("First move & black.");
+            None
         } else {
-            board.make_move(Move::Pass);
-            eprintln!("Move: {}", Move::Pass);
+            Some(Move::Pass)
+        };
+        if let Some(m) = opponent_move {
+            eprintln!("Move: {}", m);
+            if let Err(err) = game.play(m) {
+                eprintln!("Opponent move rejected ({}).", err);
+            }
         }
 
+        let mut board = game.board().clone();
+
         let x: i32;
         let y: i32;
-        let best_move;
-        let best_score;
-        let srch_data;
 
         let time_allocated = (2.5 / (44. - board.all_disks().count_ones() as f32).max(3.0) * ms_left as f32) as u32;
 
         eprintln!("Allocating {:.2} s to search.", time_allocated as f32 / 1000.0);
 
-        if board.all_disks().count_zeros() > 24 && !(board.all_disks().count_zeros() < 26 && last_bf < 3.5) {
-            let (score, best, data) = searcher.search(&mut board, time_allocated);//negamax::negamax_id(&mut board, time_allocated, &pat_eval, false);
-            best_move = best;
-            best_score = score;
-            last_bf = (data.nodes as f32).powf(1.0 / data.depth as f32);
-            srch_data = data;
+        let limits = SearchLimits::new().with_time(time_allocated);
+
+        let result = if board.all_disks().count_zeros() > 24 && !(board.all_disks().count_zeros() < 26 && last_bf < 3.5) {
+            let result = searcher.search(&mut board, limits);
+            last_bf = (result.data.nodes as f32).powf(1.0 / result.data.depth as f32);
+            result
         } else if board.all_disks().count_zeros() > 20 {
-            let (score, best, data) = endgame::endgame_solve(&mut board, true, false);
-            if score == -1 { // TODO: This is bad.
-                let (score, best, data) = searcher.search(&mut board, time_allocated);//negamax::negamax_id(&mut board, time_allocated, &pat_eval, false);
-                best_move = best;
-                best_score = score;
-                last_bf = (data.nodes as f32).powf(1.0 / data.depth as f32);
+            let wld_result = endgame::endgame_solve(&mut board, true, false);
+            if wld_result.score == -1 { // TODO: This is bad.
+                let mut result = searcher.search(&mut board, limits);
+                last_bf = (result.data.nodes as f32).powf(1.0 / result.data.depth as f32);
+                result.data = wld_result.data;
+                result
             } else {
-                best_move = best;
-                best_score = score;
                 last_bf = 0.0;
+                wld_result
             }
-            srch_data = data;
         } else {
             last_bf = 0.0;
-            let (score, best, data) = endgame::endgame_solve(&mut board, false, false);
-            best_move = best;
-            best_score = score;
-            srch_data = data;
-        }
+            endgame::endgame_solve(&mut board, false, false)
+        };
+        let (best_move, best_score, srch_data) = (result.best_move, result.score, result.data);
 
         eprintln!("\nBest move was {} with score {}", best_move, best_score);
+        eprintln!("PV: {}", format_pv(&result.pv));
 
         eprintln!("Move: {}", best_move);
 
@@ -421,33 +513,99 @@ fn cs2_play(mut board: Board, black: bool) {
             }
         }
 
-        board.make_move(best_move);
+        // Midgame scores are in centi-disks, exact solves in disks, and WLD solves have no score.
+        let score = if last_bf != 0.0 {
+            Some(best_score)
+        } else if board.all_disks().count_zeros() <= 20 {
+            Some(best_score * 100)
+        } else {
+            None
+        };
+        play_result(&mut game, &result, score);
+        eprintln!("Game: {}", game);
         first_move = false;
 
-        eprintln!("");
+        eprintln!();
         eprintln!("Searched {} nodes in {} ms ({:.2} kn/s). Final depth was {}.", srch_data.nodes, srch_data.time, srch_data.nodes as f32 / srch_data.time as f32, srch_data.depth);
-        eprintln!("");
+        eprintln!();
         println!("{} {}", x, y);
     }
 }
 
-fn perft(depth: u8) {
+fn nboard_play(pat_eval: StagedPatternEvaluator, threads: usize, probcut: Option<ProbCutParams>) {
+    let mut endgame = EndgameSearcher::with_eval(pat_eval.clone(), false);
+    let midgame = make_searcher("pvs", pat_eval, true, threads, probcut);
+    endgame.set_threads(threads).expect("Unable to create thread pool.");
+
+    let mut engine = NBoardEngine::new(midgame, Box::new(endgame));
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    engine.run(stdin.lock(), &mut stdout.lock()).expect("Unable to communicate with GUI.");
+}
+
+/// Counts the positions reachable from the starting position, splitting the moves from it between
+/// threads.
+/// # Arguments:
+/// * `depth`: The number of plies to play.
+/// * `divide`: Whether to print the count after each move as well as the total.
+/// * `hash_mb`: The size of the hash table to cache counts in, if any.
+/// * `threads`: The number of threads to use.
+fn perft(depth: u8, divide: bool, hash_mb: Option<usize>, threads: usize) {
     println!("Running perft test at depth {}.", depth);
-    let mut board = board::Board::new();
+    let board = CompactBoard::from(&board::Board::new());
+    let table = hash_mb.map(PerftTable::with_size_mb);
+    let pool = ThreadPoolBuilder::new().num_threads(threads).build().expect("Unable to create thread pool.");
 
     let start_time = Instant::now();
-    let nodes = perft_impl(depth, &mut board);
-    let end_time = Instant::now();
+    let counts = pool.install(|| perft::divide(board, depth, table.as_ref()));
+    let duration = start_time.elapsed();
 
-    let duration = end_time - start_time;
+    let nodes: u64 = counts.iter().map(|&(_, count)| count).sum();
     let time_taken = duration.as_secs() as u32 * 1000 + duration.subsec_millis();
 
-    let nps = nodes as f32 / time_taken as f32;
+    if divide {
+        for (m, count) in &counts {
+            println!("  {:<4} : {}", m.to_string(), count);
+        }
+    }
 
     println!("Perft Finished:");
     println!("  Nodes      : {} nodes", nodes);
     println!("  Time Taken : {} millis", time_taken);
-    println!("  Nodes/Sec  : {} knodes/sec", nps);
+    println!("  Nodes/Sec  : {} knodes/sec", nodes / u64::from(time_taken.max(1)));
+    if let Some(&expected) = perft::COUNTS.get(depth as usize) {
+        println!("  Expected   : {} nodes ({})", expected, if nodes == expected { "ok" } else { "MISMATCH" });
+    }
+}
+
+/// Compares the speed of `Board` and `CompactBoard` with perft from the starting position, then
+/// measures the node rate of the endgame solver on the first positions of an OBF suite.
+/// # Arguments:
+/// * `depth`: The depth of the perft tests.
+/// * `suite`: The suite to solve.
+/// * `count`: How many positions of the suite to solve.
+fn bench(depth: u8, suite: &str, count: usize, pat_eval: StagedPatternEvaluator, threads: usize) {
+    let timed = |perft: &dyn Fn() -> u64| {
+        let start_time = Instant::now();
+        let nodes = perft();
+        let duration = start_time.elapsed();
+        (nodes, duration.as_secs() as u32 * 1000 + duration.subsec_millis())
+    };
+
+    println!("CPU features: {}", board::cpu::CpuFeatures::get());
+    println!("Perft to depth {}:", depth);
+    let (board_nodes, board_time) = timed(&|| perft_impl(depth, &mut board::Board::new()));
+    println!("  Board        : {} nodes in {} ms ({} knodes/sec)", board_nodes, board_time, board_nodes / u64::from(board_time.max(1)));
+    let (compact_nodes, compact_time) = timed(&|| perft::perft(CompactBoard::from(&board::Board::new()), depth));
+    println!("  CompactBoard : {} nodes in {} ms ({} knodes/sec)", compact_nodes, compact_time, compact_nodes / u64::from(compact_time.max(1)));
+    assert_eq!(board_nodes, compact_nodes, "Perft results differ between Board and CompactBoard.");
+    println!("  Speedup      : {:.2}x", board_time.max(1) as f32 / compact_time.max(1) as f32);
+
+    let positions = ObfPosition::read(suite).unwrap_or_else(|e| panic!("Unable to read {}: {}", suite, e));
+    println!();
+    println!("Solving {} of {}:", count.min(positions.len()), suite);
+    solve_positions(&positions[..count.min(positions.len())], false, true, pat_eval, threads);
 }
 
 fn perft_impl(depth: u8, board: &mut board::Board) -> u64 {
@@ -466,49 +624,64 @@ fn perft_impl(depth: u8, board: &mut board::Board) -> u64 {
     nodes
 }
 
-fn training_data_solve(empties: u8, num_pos: usize) -> Vec<Position> {
-    // TODO: Make this a lot cleaner.
-    let idxs: Vec<usize> = (0..num_pos).collect();
-    let searcher: EndgameSearcher = EndgameSearcher::new(false);
-    idxs.par_iter().map(|&_| random_solved(empties, &searcher)).collect()
+/// Solves every position in an OBF suite, printing the result of each.
+/// # Arguments:
+/// * `path`: The suite to solve.
+/// * `wld`: Whether to only solve for a win, loss or draw.
+/// * `check`: Whether to check the results against the scores in the suite.
+/// # Returns:
+/// * Whether every checked result matched the suite.
+fn solve(path: &str, wld: bool, check: bool, pat_eval: StagedPatternEvaluator, threads: usize) -> bool {
+    let positions = ObfPosition::read(path).unwrap_or_else(|e| panic!("Unable to read {}: {}", path, e));
+
+    solve_positions(&positions, wld, check, pat_eval, threads)
 }
 
-fn random_solved(empties: u8, solver: &EndgameSearcher) -> Position {
-    let mut rng = rand::thread_rng();
-    'new_pos: loop {
-        let mut board = Board::new();
-        while board.all_disks().count_zeros() > empties.into() {
-            let moves = board.get_moves();
-            board.make_move(moves[rng.gen_range(0, moves.len())]);
-            if board.is_game_over() {
-                continue 'new_pos;
+fn solve_positions(positions: &[ObfPosition], wld: bool, check: bool, pat_eval: StagedPatternEvaluator, threads: usize) -> bool {
+    let mut searcher = EndgameSearcher::with_eval(pat_eval, false);
+    searcher.set_threads(threads).expect("Unable to create thread pool.");
+
+    println!("{:>6} {:>7} {:>6} {:>5} {:>14} {:>10} {:>12}  {}", "#", "Empties", "Score", "Move", "Nodes", "Time (ms)", "Nodes/Sec", if check { "Check" } else { "" });
+
+    let (mut total_nodes, mut total_time, mut failures) = (0, 0, 0);
+    for (i, pos) in positions.iter().enumerate() {
+        let mut board = pos.board.clone();
+        let result = searcher.endgame_solve(&mut board, wld);
+        let (nodes, time) = (result.data.nodes, result.data.time);
+        total_nodes += nodes;
+        total_time += u64::from(time);
+
+        let status = match pos.best_score() {
+            _ if !check => String::new(),
+            None => String::from("-"),
+            Some(best) if wld && result.score == best.signum() => String::from("ok"),
+            Some(best) if !wld && result.score == best && pos.best_moves().contains(&result.best_move) => String::from("ok"),
+            Some(best) => {
+                failures += 1;
+                let moves: Vec<String> = pos.best_moves().iter().map(|m| m.to_string()).collect();
+                format!("FAILED (expected {:+} {})", if wld { best.signum() } else { best }, moves.join("/"))
             }
-        }
-        let mut score = solver.endgame_solve(&mut board, false).0;
-        if !board.black_move {
-            score = -score;
-        }
-        let mut pos = board.get_position();
-        pos.score = Some(score);
-        return pos;
-    }
-}
+        };
 
-fn training_data_heuristic(empties: u8, depth: u8, num_pos: usize) -> Vec<Position> {
-    let idxs: Vec<usize> = (0..num_pos).collect();
+        let name = pos.comment.clone().unwrap_or_else(|| (i + 1).to_string());
+        println!("{:>6} {:>7} {:>+6} {:>5} {:>14} {:>10} {:>12}  {}", name, board.all_disks().count_zeros(), result.score,
+            result.best_move.to_string(), nodes, time, nodes * 1000 / u64::from(time.max(1)), status);
+    }
 
-    let file = File::open("pat31-36p.json").expect("File read error.");
-    let reader = BufReader::new(file);
-    let pat_file: PatternFile = from_reader(reader).expect("Unable to parse json");
+    println!("Solved {} positions: {} nodes in {} ms ({} nodes/sec).", positions.len(), total_nodes, total_time,
+        total_nodes * 1000 / total_time.max(1));
+    if check {
+        println!("{} of {} positions failed.", failures, positions.len());
+    }
 
-    let pat_eval = PatternEvaluator::from(pat_file.masks, pat_file.weights, pat_file.parity_e, pat_file.parity_o);
-    
-    println!("Solving positions...");
+    failures == 0
+}
 
-    idxs.par_iter().map(|&_| random_heuristic(empties, depth, &pat_eval)).collect()
+fn random_boards(empties: u8, num_pos: usize) -> Vec<Board> {
+    (0..num_pos).into_par_iter().map(|_| random_board(empties)).collect()
 }
 
-fn random_heuristic(empties: u8, depth: u8, heuristic: &PatternEvaluator) -> Position {
+fn random_board(empties: u8) -> Board {
     let mut rng = rand::thread_rng();
     'new_pos: loop {
         let mut board = Board::new();
@@ -519,12 +692,46 @@ fn random_heuristic(empties: u8, depth: u8, heuristic: &PatternEvaluator) -> Pos
                 continue 'new_pos;
             }
         }
-        let mut score = negamax::negamax(&mut board, depth, heuristic, false).0;
-        if !board.black_move {
-            score = -score;
+        return board;
+    }
+}
+
+/// Identifies a position up to the symmetries of the board, for finding duplicates.
+fn canonical_key(board: &Board) -> (u64, u64, bool) {
+    let (canonical, _) = board.canonical();
+    (canonical.black_disks, canonical.white_disks, canonical.black_move)
+}
+
+/// Takes the position with the given number of empties from each game in the WTHOR files, skipping
+/// games which ended before reaching it.
+fn wthor_boards(files: Vec<&str>, empties: u8, num_pos: usize) -> Vec<Board> {
+    let mut boards = Vec::new();
+    let mut seen = HashSet::new();
+
+    for path in files {
+        let reader = WthorReader::open(path).unwrap_or_else(|e| panic!("Unable to read {}: {}", path, e));
+        for wthor_game in reader {
+            let wthor_game = wthor_game.unwrap_or_else(|e| panic!("Unable to read {}: {}", path, e));
+
+            let mut board = wthor_game.game.start().clone();
+            for game_move in wthor_game.game.moves() {
+                if board.all_disks().count_zeros() <= empties.into() {
+                    break;
+                }
+                board.make_move(game_move.m);
+            }
+
+            // Many games share openings, so skip positions already seen in any orientation.
+            if board.all_disks().count_zeros() == u32::from(empties) && !board.is_game_over()
+                && seen.insert(canonical_key(&board)) {
+                boards.push(board);
+                if boards.len() == num_pos {
+                    return boards;
+                }
+            }
         }
-        let mut pos = board.get_position();
-        pos.score = Some(score);
-        return pos;
     }
+
+    println!("Found {} positions with {} empties.", boards.len(), empties);
+    boards
 }
@@ -0,0 +1,92 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Training data for the pattern evaluators, as written by `gen-training-data`. Positions are
+//! labelled with a score relative to the player to move, either by solving them or with a
+//! heuristic search, and can be read back for training or to be labelled again.
+
+use rayon::prelude::*;
+use serde::{ Deserialize, Serialize };
+
+use crate::board::{ Board, Position };
+use crate::search::negamax;
+use crate::search::endgame::EndgameSearcher;
+use crate::search::eval::{ Perspective, StagedPatternEvaluator };
+
+/// A set of labelled positions. The perspective of the labels is recorded, since labels were once
+/// relative to black.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct TrainingData {
+    pub perspective: Perspective,
+    pub positions: Vec<Position>
+}
+
+impl TrainingData {
+    /// Labels each position with its exact score, solving them in parallel.
+    pub fn solve(boards: Vec<Board>, pat_eval: StagedPatternEvaluator) -> TrainingData {
+        // The clones made for each split share the searcher's table.
+        let searcher = EndgameSearcher::with_eval(pat_eval, false);
+        let positions = boards.into_par_iter().map_with(searcher, |solver, mut board| {
+            let score = solver.endgame_solve(&mut board, false).score;
+            scored_position(&board, score)
+        }).collect();
+
+        TrainingData { perspective: Perspective::Mover, positions }
+    }
+
+    /// Labels each position with the score of a negamax search to the given depth.
+    pub fn heuristic(boards: Vec<Board>, depth: u8, pat_eval: &StagedPatternEvaluator) -> TrainingData {
+        let positions = boards.into_par_iter().map(|mut board| {
+            let score = negamax::negamax(&mut board, depth, pat_eval, false).score;
+            scored_position(&board, score)
+        }).collect();
+
+        TrainingData { perspective: Perspective::Mover, positions }
+    }
+}
+
+/// Labels a position with its score, which is relative to the player to move like the scores of
+/// the evaluators trained on it.
+fn scored_position(board: &Board, score: i32) -> Position {
+    let mut pos = board.get_position();
+    pos.score = Some(score);
+    pos
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+
+    use crate::board::Board;
+    use crate::search::eval::{ PatternEvaluator, Perspective, StagedPatternEvaluator };
+    use super::TrainingData;
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = rand::thread_rng();
+        let eval = StagedPatternEvaluator::from(vec![], vec![PatternEvaluator::new()]);
+
+        let boards: Vec<Board> = (0..20).map(|_| {
+            let mut board = Board::new();
+            while board.all_disks().count_zeros() > 10 && !board.is_game_over() {
+                let moves = board.get_moves();
+                board.make_move(moves[rng.gen_range(0, moves.len())]);
+            }
+            board
+        }).collect();
+
+        let data = TrainingData::solve(boards.clone(), eval.clone());
+        let json = serde_json::to_string(&data).unwrap();
+        let read: TrainingData = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, data);
+        assert_eq!(read.perspective, Perspective::Mover);
+
+        // Reloaded positions are the boards that were labelled, and label the same way again.
+        let reloaded: Vec<Board> = read.positions.iter().map(|pos| pos.to_board().unwrap()).collect();
+        for (board, original) in reloaded.iter().zip(&boards) {
+            assert_eq!(board.get_position(), original.get_position());
+        }
+        assert_eq!(TrainingData::solve(reloaded, eval), data);
+    }
+}
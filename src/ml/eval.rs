@@ -1,5 +1,5 @@
-use crate::board::Board;
-use crate::search::eval::{ Evaluator, pattern_util::* };
+use crate::board::{ Board, compact::CompactBoard };
+use crate::search::eval::{ Evaluator, Perspective, pattern_util::* };
 
 use super::Trainable;
 
@@ -9,12 +9,17 @@ use rand::prelude::*;
 
 const GAMMA: f32 = 0.9;
 
+/// A pattern evaluator which can be trained by self-play. Like `PatternEvaluator`, it scores
+/// positions from the point of view of the player to move, and its weights can be relative to
+/// either the player to move or black.
 #[derive(Default, Serialize, Deserialize, Clone)]
 pub struct RLPatternEvaluator {
     masks: Vec<u64>,
     weights: Vec<Vec<f32>>,
     parity_e: f32,
     parity_o: f32,
+    #[serde(default)]
+    perspective: Perspective,
 
     #[serde(skip)]
     weight_vs: Vec<Vec<f32>>,
@@ -34,6 +39,7 @@ impl RLPatternEvaluator {
             weights: Vec::new(),
             parity_e: 0f32,
             parity_o: 0f32,
+            perspective: Perspective::Mover,
             weight_vs: Vec::new(),
             v_parity_e: 1f32,
             v_parity_o: 1f32,
@@ -45,6 +51,7 @@ impl RLPatternEvaluator {
         let weight_vs = weights.iter().map(|w| vec![1f32; w.len()]).collect();
         RLPatternEvaluator {
             masks, weights, parity_e, parity_o,
+            perspective: Perspective::Mover,
             weight_vs, v_parity_e: 1f32, v_parity_o: 1f32,
             loss_ema: 0f32
         }
@@ -76,6 +83,7 @@ impl RLPatternEvaluator {
             weights,
             parity_e: 0f32,
             parity_o: 0f32,
+            perspective: Perspective::Mover,
             weight_vs,
             v_parity_e: 1f32,
             v_parity_o: 1f32,
@@ -85,22 +93,38 @@ impl RLPatternEvaluator {
 
     pub fn reset(&mut self) {
         for weights in self.weights.iter_mut() {
-            for i in 0..weights.len() {
-                weights[i] = 0.0;
+            for weight in weights.iter_mut() {
+                *weight = 0.0;
             }
         }
     }
 }
 
-impl Trainable for RLPatternEvaluator {
-    fn update(&mut self, board: &Board, score: f32, lr: f32) -> f32 {
-        let mut error = self.get_float_score(board) - score;
+impl RLPatternEvaluator {
+    /// Returns the disks which are the 2 digits and the 1 digits of the pattern indices, and
+    /// whether the weights score the position for the opponent of the player to move.
+    fn pattern_disks(&self, board: &Board) -> (u64, u64, bool) {
+        let (player, opponent) = if board.black_move {
+            (board.black_disks, board.white_disks)
+        } else {
+            (board.white_disks, board.black_disks)
+        };
 
-        if !board.black_move {
-            error = -error;
+        if self.perspective.swaps(board.black_move) {
+            (opponent, player, true)
+        } else {
+            (player, opponent, false)
         }
+    }
+}
+
+impl Trainable for RLPatternEvaluator {
+    fn update(&mut self, board: &Board, score: f32, lr: f32) -> f32 {
+        let error = self.get_float_score(board) - score;
+        let (mut players, mut opponents, swap) = self.pattern_disks(board);
 
-        let gradient = error;//error / (error.powi(2) + 1.0).sqrt();
+        // Weights which score the position for the opponent move the other way.
+        let gradient = if swap { -error } else { error };//error / (error.powi(2) + 1.0).sqrt();
         let loss = error.powi(2);//(error.powi(2) + 1.0).sqrt() - 1.0;
 
         const EMA_A: f32 = 0.0001;
@@ -115,24 +139,21 @@ impl Trainable for RLPatternEvaluator {
             self.v_parity_o = GAMMA * self.v_parity_o + (1.0 - GAMMA) * loss;
         };
 
-        let mut blacks = board.black_disks;
-        let mut whites = board.white_disks;
-
         for (mask, (vs, weights)) in self.masks.iter().zip(self.weight_vs.iter_mut().zip(self.weights.iter_mut())) {
             for _ in 0..4 {
                 // Extract the pattern from both bitboards
-                let black_pat = pext64(blacks, *mask) as usize;
-                let white_pat = pext64(whites, *mask) as usize;
+                let player_pat = pext64(players, *mask) as usize;
+                let opponent_pat = pext64(opponents, *mask) as usize;
                 // Get the index of the given pattern
-                let index = ONES_TERNARY[white_pat] + TWOS_TERNARY[black_pat];
+                let index = ONES_TERNARY[opponent_pat] + TWOS_TERNARY[player_pat];
 
                 // Add the pattern's weight to the score
                 weights[index] -= (lr / vs[index].sqrt()) * gradient;
                 vs[index] = GAMMA * vs[index] + (1.0 - GAMMA) * loss;
 
                 // Rotate
-                blacks = flip_vertical(flip_diag(blacks));
-                whites = flip_vertical(flip_diag(whites));
+                players = flip_vertical(flip_diag(players));
+                opponents = flip_vertical(flip_diag(opponents));
             }
         }
 
@@ -146,31 +167,26 @@ impl Trainable for RLPatternEvaluator {
             self.parity_e
         };
 
-        let mut blacks = board.black_disks;
-        let mut whites = board.white_disks;
+        let (mut players, mut opponents, swap) = self.pattern_disks(board);
 
         for (mask, weights) in self.masks.iter().zip(self.weights.iter()) {
             for _ in 0..4 {
                 // Extract the pattern from both bitboards
-                let black_pat = pext64(blacks, *mask) as usize;
-                let white_pat = pext64(whites, *mask) as usize;
+                let player_pat = pext64(players, *mask) as usize;
+                let opponent_pat = pext64(opponents, *mask) as usize;
                 // Get the index of the given pattern
-                let index = ONES_TERNARY[white_pat] + TWOS_TERNARY[black_pat];
+                let index = ONES_TERNARY[opponent_pat] + TWOS_TERNARY[player_pat];
 
                 // Add the pattern's weight to the score
                 score += weights[index];
 
                 // Rotate
-                blacks = flip_vertical(flip_diag(blacks));
-                whites = flip_vertical(flip_diag(whites));
+                players = flip_vertical(flip_diag(players));
+                opponents = flip_vertical(flip_diag(opponents));
             }
         }
 
-        if board.black_move {
-            score
-        } else {
-            -score
-        }
+        if swap { -score } else { score }
     }
 
     fn loss_ema(&self) -> f32 {
@@ -179,8 +195,8 @@ impl Trainable for RLPatternEvaluator {
 }
 
 impl Evaluator for RLPatternEvaluator {
-    fn get_score(&self, board: &Board) -> i32 {
-        (self.get_float_score(board) * 100.0) as i32
+    fn get_score(&self, board: &CompactBoard) -> i32 {
+        (self.get_float_score(&board.to_board()) * 100.0) as i32
     }
 }
 
@@ -257,7 +273,7 @@ impl Trainable for StagedRLPatternEvaluator {
         let disks = board.all_disks().count_ones();
         let stage = self.stage_map.get(&disks).unwrap();
 
-        let loss = self.evaluators[*stage].update(&board, score, lr);
+        let loss = self.evaluators[*stage].update(board, score, lr);
 
         const EMA_A: f32 = 0.0001;
 
@@ -279,7 +295,7 @@ impl Trainable for StagedRLPatternEvaluator {
 }
 
 impl Evaluator for StagedRLPatternEvaluator {
-    fn get_score(&self, board: &Board) -> i32 {
+    fn get_score(&self, board: &CompactBoard) -> i32 {
         let disks = board.all_disks().count_ones();
         let stage = self.stage_map.get(&disks).unwrap();
 
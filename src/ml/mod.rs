@@ -4,12 +4,12 @@ use crate::search::eval::{ Evaluator, PieceSquareEvaluator };
 
 use std::collections::VecDeque;
 use std::f32;
-use std::io::{ self, Write, BufReader };
-use std::fs::File;
+use std::io::{ self, Write };
 use rand::prelude::*;
-use serde_json::{ from_reader };
 
 pub mod eval;
+mod data;
+pub use self::data::TrainingData;
 
 pub trait Trainable {
     fn update(&mut self, board: &Board, score: f32, lr: f32) -> f32;
@@ -41,10 +41,13 @@ const RESET_RATIO: f32 = 1.2;
 const FORGIVENESS: f32 = 1.0;
 const TD_LAMBDA: f32 = 0.25;
 
-pub fn self_play<E: Evaluator + Trainable + Clone>(mut eval: E, mut lr: f32, mut e: f32, batch_size: usize, rounds: usize) -> E {
+/// Trains an evaluator by self-play, with TD updates after each game. After every batch it plays
+/// test games against a random player, the piece-square evaluator and `bench`, and is reset to the
+/// last checkpoint if it gets much worse.
+pub fn self_play<E: Evaluator + Trainable + Clone, B: Evaluator>(mut eval: E, bench: &B, lr: f32, e: f32, batch_size: usize, rounds: usize) -> E {
     let mut rng = thread_rng();
 
-    let (bw_r, ww_r, bw_ps, ww_ps, bw_pat, ww_pat) = game_stats(&eval);
+    let (bw_r, ww_r, bw_ps, ww_ps, bw_pat, ww_pat) = game_stats(&eval, bench);
     let escore = ((bw_r + ww_r) + (bw_ps + ww_ps) + (bw_pat + ww_pat)) / 6.0;
     
     let mut checkpoint: Option<(E, f32)> = Some((eval.clone(), escore));
@@ -60,7 +63,7 @@ pub fn self_play<E: Evaluator + Trainable + Clone>(mut eval: E, mut lr: f32, mut
             count += n;
         }
 
-        let (bw_r, ww_r, bw_ps, ww_ps, bw_pat, ww_pat) = game_stats(&eval);
+        let (bw_r, ww_r, bw_ps, ww_ps, bw_pat, ww_pat) = game_stats(&eval, bench);
         
         let escore = ((bw_r + ww_r) + (bw_ps + ww_ps) + (bw_pat + ww_pat)) / 6.0;
 
@@ -118,14 +121,12 @@ fn self_play_td_impl<E: Evaluator + Trainable>(board: &mut Board, rng: &mut Thre
         );
     }
 
-    let m;
-    if rng.gen::<f32>() > e {
-        let (_, best_move, _) = negamax::negamax(board, 1, eval, false); 
-        m = best_move;
+    let m = if rng.gen::<f32>() > e {
+        negamax::negamax(board, 1, eval, false).best_move
     } else {
         let moves = board.get_moves();
-        m = moves[rng.gen::<usize>() % moves.len()];
-    }
+        moves[rng.gen::<usize>() % moves.len()]
+    };
     
     let undo = board.make_move(m);
     let (score, count) = self_play_td_impl(board, rng, eval, e, lr);
@@ -139,18 +140,14 @@ fn self_play_td_impl<E: Evaluator + Trainable>(board: &mut Board, rng: &mut Thre
 }
 
 
-fn game_stats<E: Evaluator>(eval: &E) -> (f32, f32, f32, f32, f32, f32) {
-    let file = File::open("bench.json").expect("File read error.");
-    let reader = BufReader::new(file);
-    let pat_eval: eval::RLPatternEvaluator = from_reader(reader).expect("Unable to parse json");
-
+fn game_stats<E: Evaluator, B: Evaluator>(eval: &E, bench: &B) -> (f32, f32, f32, f32, f32, f32) {
     let ps_eval = PieceSquareEvaluator::new();
 
     let (black_win_r, white_win_r) = test_random(500, eval);
 
     let (black_win_ps, white_win_ps) = test(500, eval, &ps_eval);
 
-    let (black_win_pat, white_win_pat) = test(500, eval, &pat_eval);
+    let (black_win_pat, white_win_pat) = test(500, eval, bench);
 
     (black_win_r, white_win_r, black_win_ps, white_win_ps, black_win_pat, white_win_pat)
 }
@@ -164,7 +161,7 @@ fn test_random<E:Evaluator>(num_games: u64, eval: &E) -> (f32, f32) {
         // Play as black
         let mut board = Board::new();
         while !board.is_game_over() {
-            let (_, m, _) = negamax::negamax::<E>(&mut board, 1, eval, false);
+            let m = negamax::negamax::<E>(&mut board, 1, eval, false).best_move;
             board.make_move(m);
 
             let moves = board.get_moves();
@@ -181,7 +178,7 @@ fn test_random<E:Evaluator>(num_games: u64, eval: &E) -> (f32, f32) {
             let moves = board.get_moves();
             board.make_move(moves[rng.gen::<usize>() % moves.len()]);
 
-            let (_, m, _) = negamax::negamax::<E>(&mut board, 1, eval, false);
+            let m = negamax::negamax::<E>(&mut board, 1, eval, false).best_move;
             board.make_move(m);
         }
 
@@ -202,11 +199,11 @@ fn test<E:Evaluator, B: Evaluator>(num_games: u64, eval: &E, bench: &B) -> (f32,
         // Play as black
         let mut board = Board::new();
         while !board.is_game_over() {
-            let (_, m, _) = negamax::negamax::<E>(&mut board, 1, eval, false);
+            let m = negamax::negamax::<E>(&mut board, 1, eval, false).best_move;
             board.make_move(m);
 
             if rng.gen::<f32>() > 0.05 {
-                let (_, m, _) = negamax::negamax(&mut board, 1, bench, false);
+                let m = negamax::negamax(&mut board, 1, bench, false).best_move;
                 board.make_move(m);
             } else {
                 let moves = board.get_moves();
@@ -222,14 +219,14 @@ fn test<E:Evaluator, B: Evaluator>(num_games: u64, eval: &E, bench: &B) -> (f32,
         let mut board = Board::new();
         while !board.is_game_over() {
             if rng.gen::<f32>() > 0.05 {
-                let (_, m, _) = negamax::negamax(&mut board, 1, bench, false);
+                let m = negamax::negamax(&mut board, 1, bench, false).best_move;
                 board.make_move(m);
             } else {
                 let moves = board.get_moves();
                 board.make_move(moves[rng.gen::<usize>() % moves.len()]);
             }
             
-            let (_, m, _) = negamax::negamax::<E>(&mut board, 1, eval, false);
+            let m = negamax::negamax::<E>(&mut board, 1, eval, false).best_move;
             board.make_move(m);
         }
 
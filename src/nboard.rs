@@ -0,0 +1,363 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Contains an engine loop which speaks the NBoard text protocol, allowing Ruthless to be used from
+//! NBoard or any other GUI which supports it.
+//!
+//! # Protocol:
+//! The GUI sends one command per line on stdin, and the engine replies on stdout. The commands
+//! supported are `nboard`, `set depth`, `set game`, `set contempt`, `move`, `hint`, `go`, `ping`,
+//! `learn`, `analyze` and `quit`. Positions are sent as GGF game records, and moves are sent as
+//! coordinates (`F5`) or `PA` for a pass, optionally followed by `/eval/time`.
+//!
+//! While searching, the engine reports `status` lines, and after each search a `search` line with
+//! the principal variation and a `nodestats` line.
+//! The searchers used should not write to stdout, so as not to confuse the GUI.
+
+use std::io::{ self, BufRead, Write };
+use std::time::Instant;
+
+use crate::Result;
+use crate::board::{ Board, Move };
+use crate::formats::ggf::GgfGame;
+use crate::search::{ SearchLimits, SearchResult, Searcher };
+
+/// The name the engine reports to the GUI.
+const ENGINE_NAME: &str = "Ruthless";
+/// The midgame search depth used until the GUI sends `set depth`.
+const DEFAULT_DEPTH: u8 = 12;
+/// The maximum number of empties at which the endgame solver will be used instead of a midgame
+/// search.
+const SOLVE_EMPTIES: u32 = 20;
+
+/// Parses a move as sent by NBoard, which is either a coordinate or `PA` for a pass, optionally
+/// followed by an evaluation and time, separated by slashes.
+/// # Arguments:
+/// * `text`: The move text to parse.
+/// # Returns:
+/// * The move, or a parse error if the text is not a valid move.
+pub fn parse_move(text: &str) -> Result<Move> {
+    let coord = text.split('/').next().unwrap_or("").trim();
+
+    if coord.eq_ignore_ascii_case("pa") || coord.eq_ignore_ascii_case("pass") {
+        Ok(Move::Pass)
+    } else {
+        Move::from_coord(coord)
+    }
+}
+
+/// Formats a move in the form NBoard expects.
+/// # Arguments:
+/// * `m`: The move to format.
+/// # Returns:
+/// * The coordinate of the move, or `PA` for a pass.
+pub fn format_move(m: Move) -> String {
+    match m {
+        Move::Play(_) => m.to_string().to_uppercase(),
+        Move::Pass => String::from("PA")
+    }
+}
+
+/// Formats a principal variation in the form NBoard expects, with the moves run together.
+/// # Arguments:
+/// * `pv`: The moves to format.
+/// # Returns:
+/// * The moves of the line, e.g. `F5D6C3`.
+pub fn format_pv(pv: &[Move]) -> String {
+    pv.iter().map(|&m| format_move(m)).collect()
+}
+
+/// Builds the board from a GGF game record, by reading the starting position from the `BO` tag and
+/// playing out all of the `B` and `W` moves.
+/// # Arguments:
+/// * `ggf`: The GGF game record.
+/// # Returns:
+/// * The position at the end of the record, or an error if the record is invalid or contains an
+///   illegal move.
+pub fn board_from_ggf(ggf: &str) -> Result<Board> {
+    Ok(GgfGame::parse(ggf)?.game.board().clone())
+}
+
+/// An engine which plays using the NBoard protocol. The midgame searcher is used to the depth set
+/// by the GUI, and the endgame solver once few enough empties remain.
+pub struct NBoardEngine {
+    board: Board,
+    depth: u8,
+    midgame: Box<dyn Searcher>,
+    endgame: Box<dyn Searcher>
+}
+
+impl NBoardEngine {
+    /// Creates a new engine in the starting position.
+    /// # Arguments:
+    /// * `midgame`: The searcher to use for midgame positions, with scores in centi-disks.
+    /// * `endgame`: The solver to use for endgame positions, with scores in disks.
+    pub fn new(midgame: Box<dyn Searcher>, endgame: Box<dyn Searcher>) -> NBoardEngine {
+        NBoardEngine {
+            board: Board::new(),
+            depth: DEFAULT_DEPTH,
+            midgame,
+            endgame
+        }
+    }
+
+    /// Returns the current position of the game.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Runs the engine until the input is closed or `quit` is received.
+    /// # Arguments:
+    /// * `input`: The stream to read commands from.
+    /// * `output`: The stream to write responses to.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, output: &mut W) -> io::Result<()> {
+        for line in input.lines() {
+            if !self.handle_command(&line?, output)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles a single command from the GUI.
+    /// # Arguments:
+    /// * `line`: The command to handle.
+    /// * `output`: The stream to write responses to.
+    /// # Returns:
+    /// * false if the engine should quit, true otherwise.
+    pub fn handle_command<W: Write>(&mut self, line: &str, output: &mut W) -> io::Result<bool> {
+        let line = line.trim();
+        let (command, args) = match line.find(' ') {
+            Some(idx) => (&line[..idx], line[idx + 1..].trim()),
+            None => (line, "")
+        };
+
+        match command {
+            "nboard" => writeln!(output, "set myname {}", ENGINE_NAME)?,
+            "set" => self.handle_set(args),
+            "move" => match parse_move(args) {
+                Ok(m) if self.make_move(m) => {},
+                _ => eprintln!("Ignoring invalid move: {}", args)
+            },
+            "hint" => {
+                let count = args.parse::<usize>().unwrap_or(1);
+                self.hint(count, output)?;
+            }
+            "go" => self.go(output)?,
+            "ping" => writeln!(output, "pong {}", args)?,
+            "learn" => writeln!(output, "learned")?,
+            "analyze" | "" => {},
+            "quit" => return Ok(false),
+            _ => eprintln!("Unknown command: {}", line)
+        }
+
+        output.flush()?;
+
+        Ok(true)
+    }
+
+    fn handle_set(&mut self, args: &str) {
+        let (option, value) = match args.find(' ') {
+            Some(idx) => (&args[..idx], args[idx + 1..].trim()),
+            None => (args, "")
+        };
+
+        match option {
+            "depth" => match value.parse::<u8>() {
+                Ok(depth) if depth > 0 => self.depth = depth,
+                _ => eprintln!("Invalid depth: {}", value)
+            },
+            "game" => match board_from_ggf(value) {
+                Ok(board) => self.board = board,
+                Err(err) => eprintln!("Unable to load game: {}", err)
+            },
+            "contempt" => {},
+            _ => eprintln!("Unknown option: {}", option)
+        }
+    }
+
+    /// Makes a move in the current game, inserting a pass if the player to move has no moves.
+    fn make_move(&mut self, m: Move) -> bool {
+        if m != Move::Pass && self.board.move_count() == 0 {
+            self.board.make_move(Move::Pass);
+        }
+
+        if self.board.get_moves().contains(m) {
+            self.board.make_move(m);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the given board will be handed to the endgame solver.
+    fn solves(&self, board: &Board) -> bool {
+        let empties = board.all_disks().count_zeros();
+        empties <= (2 * self.depth as u32).min(SOLVE_EMPTIES)
+    }
+
+    /// Searches the current position, returning the score in disks along with the result.
+    fn search(&mut self, board: &mut Board, depth: u8) -> (f32, SearchResult) {
+        if self.solves(board) {
+            let result = self.endgame.search(board, SearchLimits::new());
+            (result.score as f32, result)
+        } else {
+            let result = self.midgame.search(board, SearchLimits::new().with_depth(depth));
+            (result.score as f32 / 100.0, result)
+        }
+    }
+
+    fn go<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        writeln!(output, "status Thinking")?;
+        output.flush()?;
+
+        let start_time = Instant::now();
+
+        let mut board = self.board.clone();
+        let depth = self.depth;
+        let (score, result) = self.search(&mut board, depth);
+
+        let elapsed = start_time.elapsed().as_millis() as f32 / 1000.0;
+
+        if self.solves(&board) {
+            writeln!(output, "search {} {:.2} 0 100%", format_pv(&result.pv), score)?;
+        } else {
+            writeln!(output, "search {} {:.2} 0 {}", format_pv(&result.pv), score, result.data.depth)?;
+        }
+        writeln!(output, "nodestats {} {:.2}", result.data.nodes, elapsed)?;
+        writeln!(output, "status")?;
+        writeln!(output, "=== {}/{:.2}/{:.2}", format_move(result.best_move), score, elapsed)
+    }
+
+    fn hint<W: Write>(&mut self, count: usize, output: &mut W) -> io::Result<()> {
+        writeln!(output, "status Thinking")?;
+        output.flush()?;
+
+        let start_time = Instant::now();
+        let mut total_nodes = 0;
+
+        let mut board = self.board.clone();
+        let depth = self.depth.max(2) - 1;
+        let solved = self.solves(&board);
+
+        let mut results = Vec::new();
+        for m in &board.get_moves() {
+            let undo = board.make_move(m);
+            let (score, result) = self.search(&mut board, depth);
+            board.undo_move(undo, m);
+
+            total_nodes += result.data.nodes;
+
+            let mut pv = vec![m];
+            pv.extend_from_slice(&result.pv);
+            results.push((-score, pv));
+        }
+
+        results.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+
+        for (score, pv) in results.iter().take(count) {
+            if solved {
+                writeln!(output, "search {} {:.2} 0 100%", format_pv(pv), score)?;
+            } else {
+                writeln!(output, "search {} {:.2} 0 {}", format_pv(pv), score, self.depth)?;
+            }
+        }
+
+        let elapsed = start_time.elapsed().as_millis() as f32 / 1000.0;
+
+        writeln!(output, "nodestats {} {:.2}", total_nodes, elapsed)?;
+        writeln!(output, "status")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::board::{ Board, Move };
+    use crate::search::{ endgame::EndgameSearcher, nm_new::NegamaxSearcher };
+    use crate::search::eval::{ PieceSquareEvaluator, StagedPatternEvaluator };
+    use crate::Error;
+    use super::{ NBoardEngine, board_from_ggf, parse_move };
+
+    const START_GGF: &str = "(;GM[Othello]PC[NBoard]PB[a]PW[b]RE[?]TI[5:00]TY[8]\
+        BO[8 ---------------------------O*------*O--------------------------- *];)";
+
+    fn engine() -> NBoardEngine {
+        let mut midgame = NegamaxSearcher::with_eval(PieceSquareEvaluator::new());
+        midgame.set_verbose(0);
+        let endgame = EndgameSearcher::with_eval(StagedPatternEvaluator::new(), false);
+        NBoardEngine::new(Box::new(midgame), Box::new(endgame))
+    }
+
+    fn position(board: &Board) -> (u64, u64, bool) {
+        (board.black_disks, board.white_disks, board.black_move)
+    }
+
+    fn command(engine: &mut NBoardEngine, line: &str) -> String {
+        let mut output = Vec::new();
+        engine.handle_command(line, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_parse_move() {
+        assert_eq!(parse_move("F5").unwrap(), Move::Play(37));
+        assert_eq!(parse_move("f5/1.5/0.2").unwrap(), Move::Play(37));
+        assert_eq!(parse_move("PA").unwrap(), Move::Pass);
+        assert!(parse_move("Z9").is_err());
+        assert!(parse_move("").is_err());
+    }
+
+    #[test]
+    fn test_board_from_ggf() {
+        assert_eq!(position(&board_from_ggf(START_GGF).unwrap()), position(&Board::new()));
+
+        let ggf = START_GGF.replace(";)", "B[F5//1.2]W[d6/-0.50];)");
+        let mut expected = Board::new();
+        expected.make_move(Move::Play(37));
+        expected.make_move(Move::Play(43));
+        assert_eq!(position(&board_from_ggf(&ggf).unwrap()), position(&expected));
+
+        assert!(matches!(board_from_ggf(&START_GGF.replace("BO[8", "BO[10")), Err(Error::Parse(_))));
+        assert!(matches!(board_from_ggf(&START_GGF.replace(";)", "B[A1];)")), Err(Error::IllegalMove(Move::Play(0)))));
+        assert!(board_from_ggf("(;GM[Othello];)").is_err());
+    }
+
+    #[test]
+    fn test_nboard_commands() {
+        let mut engine = engine();
+
+        assert_eq!(command(&mut engine, "nboard 2"), "set myname Ruthless\n");
+        assert_eq!(command(&mut engine, "ping 3"), "pong 3\n");
+
+        command(&mut engine, &format!("set game {}", START_GGF));
+        command(&mut engine, "move F5/0.00/1.0");
+        let mut expected = Board::new();
+        expected.make_move(Move::Play(37));
+        assert_eq!(position(engine.board()), position(&expected));
+
+        // Illegal moves are ignored.
+        command(&mut engine, "move A1");
+        assert_eq!(position(engine.board()), position(&expected));
+    }
+
+    #[test]
+    fn test_nboard_go() {
+        let mut engine = engine();
+
+        command(&mut engine, "set depth 2");
+        let response = command(&mut engine, "go");
+        let last = response.lines().last().unwrap();
+        assert!(last.starts_with("=== "));
+
+        let m = super::parse_move(&last[4..]).unwrap();
+        assert!(Board::new().get_moves().contains(m));
+
+        // The reported principal variation starts with the move played.
+        let search = response.lines().find(|l| l.starts_with("search ")).unwrap();
+        let pv = search.split(' ').nth(1).unwrap();
+        assert_eq!(pv.len(), 4);
+        assert_eq!(&pv[..2], super::format_move(m));
+    }
+}
@@ -28,25 +28,27 @@
 
 // TODO: Get link to paper on BNS
 
-use std::i32;
 use std::io::{ self, Write };
 use std::time::Instant;
 
-use crate::board::{ Board, Move };
-use crate::search::{ SearchData, eval::Evaluator };
+use crate::board::{ Board, compact::CompactBoard };
+use crate::search::{ SearchData, SearchLimits, SearchResult, Searcher, format_pv, iter_deep, iterative, eval::Evaluator };
 
 pub use crate::search::negamax::negamax_impl;
 
 /// A BNS implementation which returns the best move for a curent position, along with score.
 /// This function should be called only if the best move is what is desired. Prints information
-/// about the search to stdout.
+/// about the search to stdout if `print` is set.
 /// # Arguments:
 /// * `board`: Board to search.
 /// * `depth`: Depth to search to.
 /// * `evaluator`: Evaluator to use for position evaluation at a leaf.
+/// * `print`: Whether to print search information.
 /// # Returns:
-/// * A tuple containing the score of the best move and the best move.
-pub fn best_node_search<T: Evaluator>(board: &mut Board, depth: u8, evaluator: &T) -> (i32, Move, SearchData) {
+/// * A `SearchResult` containing the score of the best move, the best move and the principal
+///   variation. Since BNS only proves a bound on the best move, the principal variation comes from
+///   one extra full-window search of the best move.
+pub fn best_node_search<T: Evaluator>(board: &mut Board, depth: u8, evaluator: &T, print: bool) -> SearchResult {
     let next_guess = | a: i32, b: i32, count: u32 | {
         a + ((b - a) as f32 * ((count as f32 - 1.0) / count as f32)) as i32
     };
@@ -59,12 +61,17 @@ pub fn best_node_search<T: Evaluator>(board: &mut Board, depth: u8, evaluator: &
         (secs as u32 * 1000) + millis
     };
 
-    let (initial, _) = negamax_impl(board, -i32::MAX, i32::MAX, depth / 2, evaluator);
+    let board = CompactBoard::from(&*board);
+    let mut line = Vec::new();
+
+    let (initial, _) = negamax_impl(&board, -i32::MAX, i32::MAX, depth / 2, evaluator, &mut line);
     let mut alpha = initial - 20;
     let mut beta = initial + 20;
     let mut better = board.move_count();
 
-    println!("Running BNS depth {}:", depth);
+    if print {
+        println!("Running BNS depth {}:", depth);
+    }
 
     let total_time_start = Instant::now();
     let mut total_nodes = 0;
@@ -77,13 +84,13 @@ pub fn best_node_search<T: Evaluator>(board: &mut Board, depth: u8, evaluator: &
 
         let guess = next_guess(alpha, beta, better);
 
-        print!("  - α: {}, β: {}, G: {}", alpha, beta, guess);
-        io::stdout().flush().expect("asdf");
+        if print {
+            print!("  - α: {}, β: {}, G: {}", alpha, beta, guess);
+            io::stdout().flush().expect("Unable to flush stdout.");
+        }
 
         let filtered = moves.filtered(| &m | {
-            let undo = board.make_move(m);
-            let (mut result, nodes) = negamax_impl(board, -guess, -(guess - 1), depth - 1, evaluator);
-            board.undo_move(undo, m);
+            let (mut result, nodes) = negamax_impl(&board.play(m), -guess, -(guess - 1), depth - 1, evaluator, &mut line);
 
             result = -result;
             iter_nodes += nodes;
@@ -101,14 +108,66 @@ pub fn best_node_search<T: Evaluator>(board: &mut Board, depth: u8, evaluator: &
             beta = guess;
         }
 
-        println!(" -- Time: {} ms, Nodes: {} -- Better: {}", time_ms(iter_time_start, Instant::now()), iter_nodes, better);
+        if print {
+            println!(" -- Time: {} ms, Nodes: {} -- Better: {}", time_ms(iter_time_start, Instant::now()), iter_nodes, better);
+        }
     }
 
+    let best_move = moves[0];
+    let (_, pv_nodes) = negamax_impl(&board.play(best_move), -i32::MAX, i32::MAX, depth - 1, evaluator, &mut line);
+
+    total_nodes += pv_nodes;
+
+    let mut pv = vec![best_move];
+    pv.extend_from_slice(&line);
+
     let total_time = time_ms(total_time_start, Instant::now());
 
-    println!("BNS Finished. Time: {} ms, Nodes: {}, Best Move: {}", total_time, total_nodes, moves[0]);
+    if print {
+        println!("BNS Finished. Time: {} ms, Nodes: {}, Best Move: {}", total_time, total_nodes, best_move);
+        println!("PV: {}", format_pv(&pv));
+    }
 
-    (alpha, moves[0], SearchData { nodes: total_nodes, time: total_time, depth })
+    SearchResult {
+        score: alpha,
+        best_move,
+        pv,
+        data: SearchData { nodes: total_nodes, time: total_time, depth }
+    }
+}
+
+/// A `Searcher` which uses Best Node Search. Depth-limited searches use `best_node_search`,
+/// time-limited ones use `iterative::bns_iter_deep`, and any other combination of limits uses
+/// `iter_deep`.
+pub struct BestNodeSearch<E: Evaluator> {
+    eval: E,
+    print: bool
+}
+
+impl<E: Evaluator> BestNodeSearch<E> {
+    pub fn new(eval: E, print: bool) -> BestNodeSearch<E> {
+        BestNodeSearch {
+            eval,
+            print
+        }
+    }
+}
+
+impl<E: Evaluator> Searcher for BestNodeSearch<E> {
+    fn search(&mut self, board: &mut Board, limits: SearchLimits) -> SearchResult {
+        let eval = &self.eval;
+        let print = self.print;
+
+        match limits {
+            SearchLimits { depth: Some(depth), time: None, nodes: None } => {
+                best_node_search(board, depth.max(1), eval, print)
+            }
+            SearchLimits { depth: None, time: Some(time), nodes: None } => {
+                iterative::bns_iter_deep(board, time, eval, print)
+            }
+            _ => iter_deep(board, limits, |b, depth| best_node_search(b, depth, eval, print))
+        }
+    }
 }
 
 #[cfg(test)]
@@ -121,8 +180,9 @@ mod test {
         let mut board = Board::from_pos(0x000040BC00000000, 0x0000004000000000, false);
         let eval = PieceSquareEvaluator::from([1; 10]);
 
-        let (_score, m, _) = bns::best_node_search(&mut board, 2, &eval);
+        let result = bns::best_node_search(&mut board, 2, &eval, false);
 
-        assert_eq!(m, Move::Play(9));
+        assert_eq!(result.best_move, Move::Play(9));
+        assert_eq!(result.pv[0], Move::Play(9));
     }
 }
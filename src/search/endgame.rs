@@ -2,33 +2,126 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, AtomicI32, Ordering };
 use std::time::Instant;
 
-use crate::board::{ Board, Move };
-use crate::search::{ SearchData, eval::{ Evaluator, StagedPatternEvaluator } };
+use rayon::prelude::*;
+use rayon::{ ThreadPool, ThreadPoolBuilder };
 
+use crate::Result;
+use crate::board::{ Board, Move, compact::CompactBoard, movelist::MoveList };
+use crate::search::{ SearchData, SearchLimits, SearchResult, Searcher, format_pv, eval::{ Evaluator, StagedPatternEvaluator } };
+use crate::search::hashtable::{ HashTable, Score };
+
+mod last_empties;
+
+// Hashtable Params
+const DEFAULT_TABLE_MB: usize = 16;
+
+// The number of empties at which the specialized solvers take over.
+const LAST_EMPTIES: u32 = 4;
+
+// The minimum number of empties at which nodes are stored in the transposition table.
+const DEFAULT_HASH_EMPTIES: u32 = 6;
+
+// Parallel Params
+const MIN_SPLIT_EMPTIES: u32 = 14;
+
+/// Solves endgame positions exactly. Clones share the transposition table and thread pool, so
+/// several positions can be solved at once without copying the table.
+#[derive(Clone)]
 pub struct EndgameSearcher {
     eval: StagedPatternEvaluator,
-    print: bool
+    print: bool,
+    wld: bool,
+    hashtable: Arc<HashTable>,
+    hash_empties: u32,
+    pool: Option<Arc<ThreadPool>>
+}
+
+/// A node where the remaining moves are being searched in parallel. When one of them fails high,
+/// the others are stopped, along with anything they split off in turn.
+struct Split<'a> {
+    stop: AtomicBool,
+    parent: Option<&'a Split<'a>>
+}
+
+impl<'a> Split<'a> {
+    fn new(parent: Option<&'a Split<'a>>) -> Split<'a> {
+        Split {
+            stop: AtomicBool::new(false),
+            parent
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed) || self.parent.is_some_and(|parent| parent.stopped())
+    }
 }
 
 impl EndgameSearcher {
-    pub fn new(print: bool) -> EndgameSearcher {
-        let pat_eval = StagedPatternEvaluator::from_file("end_ms.json").expect("Unable to load evaluator.");
+    /// Creates a searcher using the default evaluator, as chosen by `StagedPatternEvaluator::load`.
+    pub fn new(print: bool) -> Result<EndgameSearcher> {
+        let pat_eval = StagedPatternEvaluator::load(None)?;
+
+        Ok(EndgameSearcher::with_eval(pat_eval, print))
+    }
 
+    pub fn with_eval(eval: StagedPatternEvaluator, print: bool) -> EndgameSearcher {
         EndgameSearcher {
-            eval: pat_eval,
-            print
+            eval,
+            print,
+            wld: false,
+            hashtable: Arc::new(HashTable::with_size_mb(DEFAULT_TABLE_MB)),
+            hash_empties: DEFAULT_HASH_EMPTIES,
+            pool: None
         }
     }
 
-    pub fn endgame_solve(&self, board: &mut Board, wld: bool) -> (i32, Move, SearchData) {
+    /// Sets whether searches through the `Searcher` interface only solve for win/loss/draw.
+    pub fn set_wld(&mut self, wld: bool) {
+        self.wld = wld;
+    }
+
+    /// Sets the minimum number of empties at which positions are stored in and probed from the
+    /// transposition table. Below it, a probe costs more than re-solving the position.
+    pub fn set_hash_empties(&mut self, empties: u32) {
+        self.hash_empties = empties;
+    }
+
+    /// Sets the number of threads used to solve. With more than one thread, positions are solved
+    /// in parallel using Young Brothers Wait, where the remaining moves of a node are split between
+    /// threads once its first move has been searched.
+    /// # Arguments:
+    /// * `threads`: The number of threads to use.
+    /// # Returns:
+    /// * An error if the threads could not be started, in which case the settings are unchanged.
+    pub fn set_threads(&mut self, threads: usize) -> Result<()> {
+        self.pool = if threads > 1 {
+            let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+            Some(Arc::new(pool))
+        } else {
+            None
+        };
+
+        Ok(())
+    }
+
+    pub fn endgame_solve(&mut self, board: &mut Board, wld: bool) -> SearchResult {
+        self.hashtable.new_search();
+
+        let board = CompactBoard::from(&*board);
+        if let Some(pool) = self.pool.as_ref().filter(|_| board.empties() >= MIN_SPLIT_EMPTIES) {
+            return pool.install(|| self.endgame_solve_parallel(&board, wld));
+        }
+
         let start_time = Instant::now();
         let mut total_nodes = 0;
 
         let mut moves = board.get_moves();
-        if board.all_disks().count_zeros() > 12 {
-            moves.sort_by(|&m| -self.eval.move_order_score(board, m));
+        if board.empties() > 12 {
+            moves.sort_by(|&m| -self.eval.move_order_score(&board, m));
         } else {
             moves.sort_by(|&m| board.move_count_after(m) as i32);
         }
@@ -38,9 +131,7 @@ impl EndgameSearcher {
         let mut best_move = moves[0];
 
         for m in &moves {
-            let undo = board.make_move(m);
-            let (mut result, nodes) = self.endgame_negamax(board, -beta, -best_score, wld);
-            board.undo_move(undo, m);
+            let (mut result, nodes) = self.endgame_node(&board.play(m), -beta, -best_score, wld, None);
 
             total_nodes += nodes;
 
@@ -62,16 +153,251 @@ impl EndgameSearcher {
         let duration = end_time - start_time;
         let time_taken = duration.as_secs() as u32 * 1000 + duration.subsec_millis();
 
+        // The line is found by re-solving, which is left out of the statistics of the solve.
+        let (pv, pv_nodes) = solved_line(&board, best_move, best_score, |b, alpha, beta| {
+            self.endgame_node(b, alpha, beta, wld, None)
+        });
+
         if self.print {
             println!("[{}] Searched {} nodes in {} ms.", if wld { "WLD" } else { "FULL" }, total_nodes, time_taken);
+            println!("PV: {} ({} nodes to extract)", format_pv(&pv), pv_nodes);
+        }
+
+        SearchResult {
+            score: best_score,
+            best_move,
+            pv,
+            data: SearchData { nodes: total_nodes, time: time_taken, depth: board.empties() as u8 }
+        }
+    }
+
+    fn endgame_solve_parallel(&self, board: &CompactBoard, wld: bool) -> SearchResult {
+        let start_time = Instant::now();
+
+        // The cutoffs of `endgame_ybwc` are skipped at the root, as they do not give a legal move.
+        let beta = if wld { 1 } else { 64 };
+        let (_, hash_move) = self.probe_node(board, -beta, beta, wld);
+        let moves = self.ordered_moves(board, hash_move);
+        let (best_score, best_move, total_nodes) = self.ybwc_split(board, &moves, -beta, beta, wld, None);
+
+        let end_time = Instant::now();
+        let duration = end_time - start_time;
+        let time_taken = duration.as_secs() as u32 * 1000 + duration.subsec_millis();
+
+        // The line is found by re-solving, which is left out of the statistics of the solve.
+        let (pv, pv_nodes) = solved_line(board, best_move, best_score, |b, alpha, beta| {
+            let (score, _, nodes) = self.endgame_ybwc(b, alpha, beta, wld, None);
+            (score, nodes)
+        });
+
+        if self.print {
+            println!("[{}] Searched {} nodes in {} ms.", if wld { "WLD-P" } else { "FULL-P" }, total_nodes, time_taken);
+            println!("PV: {} ({} nodes to extract)", format_pv(&pv), pv_nodes);
+        }
+
+        SearchResult {
+            score: best_score,
+            best_move,
+            pv,
+            data: SearchData { nodes: total_nodes, time: time_taken, depth: board.empties() as u8 }
+        }
+    }
+
+    /// Orders the moves of a position for the endgame search, trying the hash move first and the
+    /// rest by their evaluation.
+    fn ordered_moves(&self, board: &CompactBoard, hash_move: Option<Move>) -> MoveList {
+        let mut moves = board.get_moves();
+
+        if let Some(hash_move) = hash_move {
+            moves.sort_by(|&m| if m == hash_move { i32::MIN } else { -self.eval.move_order_score(board, m) });
+        } else {
+            moves.sort_by(|&m| -self.eval.move_order_score(board, m));
+        }
+
+        moves
+    }
+
+    /// Looks up a position in the transposition table. Scores are stored as bounds on the exact
+    /// disk difference, so WLD and exact searches can share entries, and are converted back to
+    /// win/loss/draw scores for WLD searches. Positions with fewer than `hash_empties` empties
+    /// are never stored.
+    /// # Returns:
+    /// * The stored score, if it is useful for the given window.
+    /// * The stored best move, if the position is in the table at all.
+    fn probe_node(&self, board: &CompactBoard, alpha: i32, beta: i32, wld: bool) -> (Option<i32>, Option<Move>) {
+        let empties = board.empties();
+        if empties < self.hash_empties {
+            return (None, None);
+        }
+
+        let (score, hash_move) = self.hashtable.probe(board.hash_key(), empties as u8, alpha, beta);
+        let score = score.map(|score| {
+            let value = match score {
+                Score::Exact(value) | Score::Lower(value) | Score::Upper(value) => value
+            };
+            if wld { value.signum() } else { value }
+        });
+
+        (score, hash_move)
+    }
+
+    /// Saves the result of a node to the transposition table. The results of WLD searches are
+    /// converted to bounds on the exact score, e.g. a win only shows the score is at least 1.
+    fn save_node(&self, board: &CompactBoard, score: i32, alpha: i32, beta: i32, best_move: Move, wld: bool) {
+        let empties = board.empties();
+        if empties < self.hash_empties {
+            return;
+        }
+
+        let node_score = if score <= alpha {
+            Score::Upper(if wld { [-1, 0, 64][(score.signum() + 1) as usize] } else { score })
+        } else if score >= beta {
+            Score::Lower(if wld { [-64, 0, 1][(score.signum() + 1) as usize] } else { score })
+        } else if wld {
+            match score.signum() {
+                1 => Score::Lower(1),
+                -1 => Score::Upper(-1),
+                _ => Score::Exact(0)
+            }
+        } else {
+            Score::Exact(score)
+        };
+        self.hashtable.save(board.hash_key(), node_score, empties as u8, best_move);
+    }
+
+    /// Enhanced transposition cutoff. Before any child is searched, checks whether the table
+    /// already shows one of them to be good enough for a beta-cutoff.
+    /// # Returns:
+    /// * The score and move causing the cutoff, if any.
+    fn etc_cutoff(&self, board: &CompactBoard, moves: &MoveList, alpha: i32, beta: i32, wld: bool) -> Option<(i32, Move)> {
+        for m in moves {
+            let (score, _) = self.probe_node(&board.play(m), -beta, -alpha, wld);
+
+            if let Some(score) = score.filter(|&score| -score >= beta) {
+                return Some((-score, m));
+            }
+        }
+
+        None
+    }
+
+    /// The parallel version of `endgame_negamax`, using Young Brothers Wait. The first move is
+    /// searched on its own, and only if it does not cause a cutoff are the rest searched in
+    /// parallel, with the best score so far shared between them. Nodes with fewer than
+    /// `MIN_SPLIT_EMPTIES` empties are searched serially.
+    /// # Arguments:
+    /// * `board`: Board to search.
+    /// * `alpha`: Alpha-cutoff.
+    /// * `beta`: Beta-cutoff.
+    /// * `wld`: Whether to only solve for win/loss/draw.
+    /// * `split`: The parallel node this search is part of, if any. If it is stopped, the result
+    ///   is meaningless.
+    /// # Returns:
+    /// * A tuple containing the score, the best move and the number of nodes searched.
+    fn endgame_ybwc(&self, board: &CompactBoard, alpha: i32, beta: i32, wld: bool, split: Option<&Split>) -> (i32, Move, u64) {
+        if board.empties() < MIN_SPLIT_EMPTIES || board.is_game_over() {
+            let (score, nodes) = self.endgame_node(board, alpha, beta, wld, split);
+            return (score, Move::Pass, nodes);
         }
 
-        (best_score, best_move, SearchData { nodes: total_nodes, time: time_taken, depth: board.all_disks().count_zeros() as u8 })
+        if stability_cutoff(board, alpha, wld) {
+            return (alpha, Move::Pass, 1);
+        }
+
+        let (score, hash_move) = self.probe_node(board, alpha, beta, wld);
+        if let Some(score) = score {
+            return (score, hash_move.unwrap_or(Move::Pass), 1);
+        }
+
+        let moves = self.ordered_moves(board, hash_move);
+        if let Some((score, m)) = self.etc_cutoff(board, &moves, alpha, beta, wld) {
+            return (score, m, 1);
+        }
+
+        self.ybwc_split(board, &moves, alpha, beta, wld, split)
     }
 
-    fn endgame_negamax(&self, board: &mut Board, mut alpha: i32, beta: i32, wld: bool) -> (i32, u64) {
+    /// Searches the moves of a node for `endgame_ybwc`, once none of its cutoffs apply. The first
+    /// move is searched on its own, and the rest are split between threads if it does not cause a
+    /// cutoff.
+    /// # Returns:
+    /// * A tuple containing the score, the best move and the number of nodes searched.
+    fn ybwc_split(&self, board: &CompactBoard, moves: &MoveList, mut alpha: i32, beta: i32, wld: bool, split: Option<&Split>) -> (i32, Move, u64) {
+        let alpha_original = alpha;
+
+        // The eldest brother is searched before anything is split off.
+        let mut best_move = moves[0];
+        let (result, _, mut total_nodes) = self.endgame_ybwc(&board.play(best_move), -beta, -alpha, wld, split);
+
+        if split.is_some_and(|split| split.stopped()) {
+            return (alpha, best_move, total_nodes);
+        }
+
+        alpha = alpha.max(-result);
+
+        if alpha < beta && moves.len() > 1 {
+            let shared_alpha = AtomicI32::new(alpha);
+            let child_split = Split::new(split);
+
+            let results: Vec<(Option<(i32, Move)>, u64)> = (1..moves.len()).into_par_iter().map(|i| {
+                if child_split.stopped() {
+                    return (None, 0);
+                }
+
+                let m = moves[i];
+                let window = shared_alpha.load(Ordering::Relaxed);
+                let (result, _, nodes) = self.endgame_ybwc(&board.play(m), -beta, -window, wld, Some(&child_split));
+                let result = -result;
+
+                if result >= beta && !child_split.stopped() {
+                    child_split.stop.store(true, Ordering::Relaxed);
+                    return (Some((result, m)), nodes);
+                }
+
+                // A move that fails low is only known to be no better than the window, so it can
+                // never be the best move.
+                if child_split.stopped() || result <= window {
+                    return (None, nodes);
+                }
+
+                shared_alpha.fetch_max(result, Ordering::Relaxed);
+                (Some((result, m)), nodes)
+            }).collect();
+
+            for (result, nodes) in results {
+                total_nodes += nodes;
+                if let Some((score, m)) = result {
+                    if score > alpha {
+                        alpha = score;
+                        best_move = m;
+                    }
+                }
+            }
+
+            if split.is_some_and(|split| split.stopped()) {
+                return (alpha, best_move, total_nodes);
+            }
+        }
+
+        self.save_node(board, alpha, alpha_original, beta, best_move, wld);
+
+        (alpha, best_move, total_nodes)
+    }
+
+    /// Solves a position with whichever search suits the number of empties. The pattern
+    /// evaluator is only used for move ordering while there are more than 12 empties. If `split`
+    /// is stopped, the search returns early and the result is meaningless.
+    fn endgame_node(&self, board: &CompactBoard, alpha: i32, beta: i32, wld: bool, split: Option<&Split>) -> (i32, u64) {
+        if board.empties() > 12 {
+            self.endgame_negamax(board, alpha, beta, wld, split)
+        } else {
+            self.endgame_negamax_ffo(board, alpha, beta, wld, split)
+        }
+    }
+
+    fn endgame_negamax(&self, board: &CompactBoard, mut alpha: i32, beta: i32, wld: bool, split: Option<&Split>) -> (i32, u64) {
         if board.is_game_over() {
-            let score = if board.black_move { board.get_score() } else { -board.get_score() };
+            let score = board.disk_difference();
             if wld {
                 return (score.signum(), 1);
             } else {
@@ -79,26 +405,45 @@ impl EndgameSearcher {
             }
         }
 
-        let mut moves = board.get_moves();
-        let empties = board.all_disks().count_zeros();
-        moves.sort_by(|&m| -self.eval.move_order_score(board, m));
+        if stability_cutoff(board, alpha, wld) {
+            return (alpha, 1);
+        }
 
+        let (score, hash_move) = self.probe_node(board, alpha, beta, wld);
+        if let Some(score) = score {
+            return (score, 1);
+        }
+
+        let moves = self.ordered_moves(board, hash_move);
+        if let Some((score, _)) = self.etc_cutoff(board, &moves, alpha, beta, wld) {
+            return (score, 1);
+        }
+
+        let empties = board.empties();
+
+        let alpha_original = alpha;
+        let mut best_move = moves[0];
         let mut total_nodes = 0;
 
         for m in &moves {
-            let undo = board.make_move(m);
+            let child = board.play(m);
             let (mut result, nodes) = if empties > 12 {
-                self.endgame_negamax(board, -beta, -alpha, wld)
+                self.endgame_negamax(&child, -beta, -alpha, wld, split)
             } else {
-                self.endgame_negamax_ffo(board, -beta, -alpha, wld)
+                self.endgame_negamax_ffo(&child, -beta, -alpha, wld, split)
             };
-            board.undo_move(undo, m);
+
+            // A stopped search must not save its result, as it is not a bound on the score.
+            if split.is_some_and(|split| split.stopped()) {
+                return (alpha, total_nodes + nodes);
+            }
 
             result = -result;
             total_nodes += nodes;
 
             if result > alpha {
                 alpha = result;
+                best_move = m;
             }
 
             if alpha >= beta {
@@ -106,12 +451,19 @@ impl EndgameSearcher {
             }
         }
 
+        self.save_node(board, alpha, alpha_original, beta, best_move, wld);
+
         (alpha, total_nodes)
     }
 
-    fn endgame_negamax_ffo(&self, board: &mut Board, mut alpha: i32, beta: i32, wld: bool) -> (i32, u64) {
+    fn endgame_negamax_ffo(&self, board: &CompactBoard, mut alpha: i32, beta: i32, wld: bool, split: Option<&Split>) -> (i32, u64) {
+        let empties = board.empties();
+        if empties <= LAST_EMPTIES {
+            return last_empties::solve(board, alpha, beta, wld);
+        }
+
         if board.is_game_over() {
-            let score = if board.black_move { board.get_score() } else { -board.get_score() };
+            let score = board.disk_difference();
             if wld {
                 return (score.signum(), 1);
             } else {
@@ -119,26 +471,35 @@ impl EndgameSearcher {
             }
         }
 
+        if stability_cutoff(board, alpha, wld) {
+            return (alpha, 1);
+        }
+
+        let (score, hash_move) = self.probe_node(board, alpha, beta, wld);
+        if let Some(score) = score {
+            return (score, 1);
+        }
+
         let mut moves = board.get_moves();
-        let empties = board.all_disks().count_zeros();
-        moves.sort_by(|&m| board.move_count_after(m) as i32);
+        moves.sort_by(|&m| if Some(m) == hash_move { i32::MIN } else { board.move_count_after(m) as i32 });
 
+        let alpha_original = alpha;
+        let mut best_move = moves[0];
         let mut total_nodes = 0;
 
         for m in &moves {
-            let undo = board.make_move(m);
-            let (mut result, nodes) = if empties > 0 {
-                self.endgame_negamax_ffo(board, -beta, -alpha, wld)
-            } else {
-                self.endgame_negamax_nb(board, -beta, -alpha, wld)
-            };
-            board.undo_move(undo, m);
+            let (mut result, nodes) = self.endgame_negamax_ffo(&board.play(m), -beta, -alpha, wld, split);
+
+            if split.is_some_and(|split| split.stopped()) {
+                return (alpha, total_nodes + nodes);
+            }
 
             result = -result;
             total_nodes += nodes;
 
             if result > alpha {
                 alpha = result;
+                best_move = m;
             }
 
             if alpha >= beta {
@@ -146,55 +507,24 @@ impl EndgameSearcher {
             }
         }
 
-        (alpha, total_nodes)
-    }
-
-    fn endgame_negamax_nb(&self, board: &mut Board, mut alpha: i32, beta: i32, wld: bool) -> (i32, u64) {
-        let moves = board.get_moves();
-        let mut total_nodes = 0;
-
-        for m in &moves {
-            let undo = board.make_move(m);
-            let (mut result, nodes) = self.endgame_negamax_nb_2(board, -beta, -alpha, wld);
-            board.undo_move(undo, m);
-
-            result = -result;
-            total_nodes += nodes;
-
-            if result > alpha {
-                alpha = result;
-            }
-        }
+        self.save_node(board, alpha, alpha_original, beta, best_move, wld);
 
         (alpha, total_nodes)
     }
+}
 
-    fn endgame_negamax_nb_2(&self, board: &mut Board, mut alpha: i32, _beta: i32, wld: bool) -> (i32, u64) {
-        let moves = board.get_moves();
-
-        for m in &moves {
-            let undo = board.make_move(m);
-
-            let mut score = if board.black_move { -board.get_score() } else { board.get_score() };
-            if wld {
-                score = score.signum()
-            }
-
-            board.undo_move(undo, m);
-
-            if score > alpha {
-                alpha = score;
-            }
-        }
-
-        (alpha, moves.len() as u64)
+/// Always solves the position completely, so the search limits are ignored.
+impl Searcher for EndgameSearcher {
+    fn search(&mut self, board: &mut Board, _limits: SearchLimits) -> SearchResult {
+        self.endgame_solve(board, self.wld)
     }
 }
 
-pub fn endgame_solve(board: &mut Board, wld: bool, print: bool) -> (i32, Move, SearchData) {
+pub fn endgame_solve(board: &mut Board, wld: bool, print: bool) -> SearchResult {
     let start_time = Instant::now();
     let mut total_nodes = 0;
 
+    let board = CompactBoard::from(&*board);
     let mut moves = board.get_moves();
     moves.sort_by(|&m| board.move_count_after(m) as i32);
 
@@ -203,9 +533,7 @@ pub fn endgame_solve(board: &mut Board, wld: bool, print: bool) -> (i32, Move, S
     let mut best_move = moves[0];
 
     for m in &moves {
-        let undo = board.make_move(m);
-        let (mut result, nodes) = endgame_negamax(board, -beta, -best_score, wld);
-        board.undo_move(undo, m);
+        let (mut result, nodes) = endgame_negamax(&board.play(m), -beta, -best_score, wld);
 
         total_nodes += nodes;
 
@@ -227,16 +555,93 @@ pub fn endgame_solve(board: &mut Board, wld: bool, print: bool) -> (i32, Move, S
     let duration = end_time - start_time;
     let time_taken = duration.as_secs() as u32 * 1000 + duration.subsec_millis();
 
+    // The line is found by re-solving, which is left out of the statistics of the solve.
+    let (pv, pv_nodes) = solved_line(&board, best_move, best_score, |b, alpha, beta| {
+        endgame_negamax(b, alpha, beta, wld)
+    });
+
     if print {
         println!("[{}] Searched {} nodes in {} ms.", if wld { "WLD" } else { "FULL" }, total_nodes, time_taken);
+        println!("PV: {} ({} nodes to extract)", format_pv(&pv), pv_nodes);
     }
 
-    (best_score, best_move, SearchData { nodes: total_nodes, time: time_taken, depth: board.all_disks().count_zeros() as u8 })
+    SearchResult {
+        score: best_score,
+        best_move,
+        pv,
+        data: SearchData { nodes: total_nodes, time: time_taken, depth: board.empties() as u8 }
+    }
 }
 
-fn endgame_negamax(board: &mut Board, mut alpha: i32, beta: i32, wld: bool) -> (i32, u64) {
+/// Checks whether the opponent's stable disks cap the score at or below alpha. The player to move
+/// can at most end up with every disk that is not stable for the opponent.
+/// # Arguments:
+/// * `board`: The position to check.
+/// * `alpha`: Alpha-cutoff.
+/// * `wld`: Whether the search is only for win/loss/draw.
+/// # Returns:
+/// * true if the node fails low without being searched.
+fn stability_cutoff(board: &CompactBoard, alpha: i32, wld: bool) -> bool {
+    let upper_bound = |stable: u64| {
+        let score = 64 - 2 * stable.count_ones() as i32;
+        if wld { score.signum() } else { score }
+    };
+
+    // Skip computing stability when even a fully stable opponent could not cause a cutoff.
+    if upper_bound(board.opponent) > alpha {
+        return false;
+    }
+
+    upper_bound(board.opponent_stable()) <= alpha
+}
+
+/// Reconstructs an optimal line to the end of the game for a solved position. After the given
+/// move, each move is found by re-solving the children with a window around the known score, and
+/// taking the first one which achieves it.
+/// # Arguments:
+/// * `board`: The solved position.
+/// * `best_move`: The best move in the position.
+/// * `score`: The exact score of the position, relative to the player to move.
+/// * `solve`: A function which solves a position within the given window.
+/// # Returns:
+/// * A tuple containing the line, starting with `best_move`, and the number of nodes searched.
+fn solved_line<F>(board: &CompactBoard, best_move: Move, mut score: i32, mut solve: F) -> (Vec<Move>, u64)
+    where F: FnMut(&CompactBoard, i32, i32) -> (i32, u64)
+{
+    let mut total_nodes = 0;
+    let mut line = vec![best_move];
+    let mut board = board.play(best_move);
+    score = -score;
+
+    while !board.is_game_over() {
+        let moves = board.get_moves();
+        let mut next = moves[0];
+
+        for m in &moves {
+            let (result, nodes) = solve(&board.play(m), -score - 1, -score + 1);
+            total_nodes += nodes;
+
+            if -result == score {
+                next = m;
+                break;
+            }
+        }
+
+        line.push(next);
+        board = board.play(next);
+        score = -score;
+    }
+
+    (line, total_nodes)
+}
+
+fn endgame_negamax(board: &CompactBoard, mut alpha: i32, beta: i32, wld: bool) -> (i32, u64) {
+    if board.empties() <= LAST_EMPTIES {
+        return last_empties::solve(board, alpha, beta, wld);
+    }
+
     if board.is_game_over() {
-        let score = if board.black_move { board.get_score() } else { -board.get_score() };
+        let score = board.disk_difference();
         if wld {
             return (score.signum(), 1);
         } else {
@@ -246,16 +651,14 @@ fn endgame_negamax(board: &mut Board, mut alpha: i32, beta: i32, wld: bool) -> (
 
     let mut moves = board.get_moves();
     let move_count = board.move_count();
-    if move_count > 4 || move_count > 1 && board.all_disks().count_zeros() > 3 {
+    if move_count > 4 || move_count > 1 && board.empties() > 3 {
         moves.sort_by(|&m| board.move_count_after(m) as i32);
     }
 
     let mut total_nodes = 0;
 
     for m in &moves {
-        let undo = board.make_move(m);
-        let (mut result, nodes) = endgame_negamax(board, -beta, -alpha, wld);
-        board.undo_move(undo, m);
+        let (mut result, nodes) = endgame_negamax(&board.play(m), -beta, -alpha, wld);
 
         result = -result;
         total_nodes += nodes;
@@ -274,7 +677,11 @@ fn endgame_negamax(board: &mut Board, mut alpha: i32, beta: i32, wld: bool) -> (
 
 #[cfg(test)]
 mod test {
+    use rand::Rng;
+
     use crate::board::{ Board, Move };
+    use crate::search::eval::{ PatternEvaluator, StagedPatternEvaluator };
+    use super::EndgameSearcher;
 
     #[test]
     fn ffo_simplified_40() {
@@ -283,10 +690,89 @@ mod test {
         board.make_move(Move::Play(8));
         board.make_move(Move::Play(1));
 
-        assert_eq!(super::endgame_solve(&mut board, true, true).0, 1);
+        assert_eq!(super::endgame_solve(&mut board, true, true).score, 1);
+
+        let result = super::endgame_solve(&mut board, false, true);
+        assert_eq!(result.score, 38);
+        assert_eq!(result.best_move, Move::Play(2));
+
+        // Playing out the principal variation should finish the game with the solved score.
+        let mut end = board.clone();
+        for &m in &result.pv {
+            assert!(end.get_moves().contains(m));
+            end.make_move(m);
+        }
+        assert!(end.is_game_over());
+        assert_eq!(if board.black_move { end.get_score() } else { -end.get_score() }, 38);
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        let mut rng = rand::thread_rng();
+        let eval = StagedPatternEvaluator::from(vec![], vec![PatternEvaluator::new()]);
+        let mut serial = EndgameSearcher::with_eval(eval.clone(), false);
+        let mut parallel = EndgameSearcher::with_eval(eval, false);
+        parallel.set_threads(4).unwrap();
+
+        for _ in 0..2 {
+            let mut board = Board::new();
+            while board.all_disks().count_zeros() > 14 && !board.is_game_over() {
+                let moves = board.get_moves();
+                board.make_move(moves[rng.gen_range(0, moves.len())]);
+            }
+            if board.is_game_over() {
+                continue;
+            }
+
+            for &wld in &[true, false] {
+                let expected = serial.endgame_solve(&mut board, wld);
+                let result = parallel.endgame_solve(&mut board, wld);
+                assert_eq!(result.score, expected.score);
+
+                // The parallel solver may pick a different move, but it must be one of the best.
+                let mut child = board.clone();
+                child.make_move(result.best_move);
+                assert_eq!(-serial.endgame_solve(&mut child, wld).score, expected.score);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_lost_root() {
+        // White's top five rows are stable, so every cutoff in the tree would fire at the root.
+        let mut board = Board::from_pos(0x0000000000800000, 0xFFFFFFFFFF400000, true);
+        let eval = StagedPatternEvaluator::from(vec![], vec![PatternEvaluator::new()]);
+        let mut parallel = EndgameSearcher::with_eval(eval, false);
+        parallel.set_threads(2).unwrap();
+
+        let result = parallel.endgame_solve(&mut board, true);
+        assert_eq!(result.score, -1);
+        assert_eq!(result.best_move, Move::Play(42));
+        assert_eq!(result.pv[0], Move::Play(42));
+    }
 
-        let (score, m, _) = super::endgame_solve(&mut board, false, true);
-        assert_eq!(score, 38);
-        assert_eq!(m, Move::Play(2));
+    #[test]
+    fn table_matches_no_table() {
+        let mut rng = rand::thread_rng();
+        let eval = StagedPatternEvaluator::from(vec![], vec![PatternEvaluator::new()]);
+        let mut hashed = EndgameSearcher::with_eval(eval.clone(), false);
+        let mut plain = EndgameSearcher::with_eval(eval, false);
+        plain.set_hash_empties(64);
+
+        for _ in 0..5 {
+            let mut board = Board::new();
+            while board.all_disks().count_zeros() > 14 && !board.is_game_over() {
+                let moves = board.get_moves();
+                board.make_move(moves[rng.gen_range(0, moves.len())]);
+            }
+            if board.is_game_over() {
+                continue;
+            }
+
+            // WLD and exact results share the table, so alternate between them.
+            for &wld in &[false, true, false] {
+                assert_eq!(hashed.endgame_solve(&mut board, wld).score, plain.endgame_solve(&mut board, wld).score);
+            }
+        }
     }
 }
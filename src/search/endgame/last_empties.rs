@@ -0,0 +1,265 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Specialized solvers for the last four empty squares. These work directly on the player and
+//! opponent bitboards of a `CompactBoard` and keep the empty squares in small fixed-size arrays, so no move lists are
+//! generated. With three or four empties, squares in quadrants with an odd number of empties are
+//! tried first, since the last move in a region is usually worth the most.
+
+use crate::board::{ bitboard, compact::CompactBoard };
+
+/// Solves a position with at most four empty squares.
+/// # Arguments:
+/// * `board`: The position to solve.
+/// * `alpha`: Alpha-cutoff.
+/// * `beta`: Beta-cutoff.
+/// * `wld`: Whether to only solve for win/loss/draw.
+/// # Returns:
+/// * A tuple containing the score, relative to the player to move, and the number of nodes searched.
+pub fn solve(board: &CompactBoard, alpha: i32, beta: i32, wld: bool) -> (i32, u64) {
+    let (player, opponent) = (board.player, board.opponent);
+
+    let mut squares = [0; 4];
+    let mut count = 0;
+    let mut empty = !(player | opponent);
+    while empty != 0 {
+        squares[count] = 63 - empty.trailing_zeros() as u8;
+        empty &= empty - 1;
+        count += 1;
+    }
+
+    match count {
+        0 => (final_score(player, opponent, wld), 1),
+        1 => solve_1(player, opponent, squares[0], wld),
+        2 => solve_2(player, opponent, alpha, beta, [squares[0], squares[1]], wld),
+        3 => solve_3(player, opponent, alpha, beta, [squares[0], squares[1], squares[2]], wld),
+        _ => solve_4(player, opponent, alpha, beta, squares, wld)
+    }
+}
+
+/// The score of a finished game, relative to the player.
+#[inline]
+fn final_score(player: u64, opponent: u64, wld: bool) -> i32 {
+    let score = player.count_ones() as i32 - opponent.count_ones() as i32;
+    if wld { score.signum() } else { score }
+}
+
+/// The disks flipped by playing on the given square, not including the square itself.
+#[inline]
+fn flips(square: u8, player: u64, opponent: u64) -> u64 {
+    bitboard::get_flip(square as usize, player, opponent) ^ (0x80_00_00_00_00_00_00_00 >> square)
+}
+
+/// The quadrant of a square, numbered 0 to 3.
+#[inline]
+fn quadrant(square: u8) -> u8 {
+    ((square >> 5) & 1) << 1 | ((square >> 2) & 1)
+}
+
+/// Reorders the squares so that those in quadrants with an odd number of empties come first.
+fn parity_order(squares: &mut [u8]) {
+    let mut parity = 0u8;
+    for &square in squares.iter() {
+        parity ^= 1 << quadrant(square);
+    }
+    squares.sort_by_key(|&square| parity & (1 << quadrant(square)) == 0);
+}
+
+/// Solves with one empty square. Neither side can do better than the single move, so the
+/// window is not needed.
+fn solve_1(player: u64, opponent: u64, square: u8, wld: bool) -> (i32, u64) {
+    let disk = 0x80_00_00_00_00_00_00_00 >> square;
+
+    let flipped = flips(square, player, opponent);
+    if flipped != 0 {
+        return (final_score(player | flipped | disk, opponent ^ flipped, wld), 1);
+    }
+
+    let flipped = flips(square, opponent, player);
+    if flipped != 0 {
+        return (final_score(player ^ flipped, opponent | flipped | disk, wld), 1);
+    }
+
+    (final_score(player, opponent, wld), 1)
+}
+
+fn solve_2(player: u64, opponent: u64, mut alpha: i32, beta: i32, squares: [u8; 2], wld: bool) -> (i32, u64) {
+    let mut total_nodes = 0;
+    let mut moved = false;
+
+    for i in 0..2 {
+        let flipped = flips(squares[i], player, opponent);
+        if flipped == 0 {
+            continue;
+        }
+        moved = true;
+
+        let disk = 0x80_00_00_00_00_00_00_00 >> squares[i];
+        let (result, nodes) = solve_1(opponent ^ flipped, player | flipped | disk, squares[1 - i], wld);
+        total_nodes += nodes;
+
+        if -result > alpha {
+            alpha = -result;
+            if alpha >= beta {
+                return (alpha, total_nodes);
+            }
+        }
+    }
+
+    if moved {
+        return (alpha, total_nodes);
+    }
+
+    if game_over(player, opponent, &squares) {
+        return (final_score(player, opponent, wld), 1);
+    }
+
+    let (result, nodes) = solve_2(opponent, player, -beta, -alpha, squares, wld);
+    (-result, total_nodes + nodes)
+}
+
+fn solve_3(player: u64, opponent: u64, mut alpha: i32, beta: i32, mut squares: [u8; 3], wld: bool) -> (i32, u64) {
+    let mut total_nodes = 0;
+    let mut moved = false;
+
+    parity_order(&mut squares);
+
+    for i in 0..3 {
+        let flipped = flips(squares[i], player, opponent);
+        if flipped == 0 {
+            continue;
+        }
+        moved = true;
+
+        let disk = 0x80_00_00_00_00_00_00_00 >> squares[i];
+        let rest = match i {
+            0 => [squares[1], squares[2]],
+            1 => [squares[0], squares[2]],
+            _ => [squares[0], squares[1]]
+        };
+        let (result, nodes) = solve_2(opponent ^ flipped, player | flipped | disk, -beta, -alpha, rest, wld);
+        total_nodes += nodes;
+
+        if -result > alpha {
+            alpha = -result;
+            if alpha >= beta {
+                return (alpha, total_nodes);
+            }
+        }
+    }
+
+    if moved {
+        return (alpha, total_nodes);
+    }
+
+    if game_over(player, opponent, &squares) {
+        return (final_score(player, opponent, wld), 1);
+    }
+
+    let (result, nodes) = solve_3(opponent, player, -beta, -alpha, squares, wld);
+    (-result, total_nodes + nodes)
+}
+
+fn solve_4(player: u64, opponent: u64, mut alpha: i32, beta: i32, mut squares: [u8; 4], wld: bool) -> (i32, u64) {
+    let mut total_nodes = 0;
+    let mut moved = false;
+
+    parity_order(&mut squares);
+
+    for i in 0..4 {
+        let flipped = flips(squares[i], player, opponent);
+        if flipped == 0 {
+            continue;
+        }
+        moved = true;
+
+        let disk = 0x80_00_00_00_00_00_00_00 >> squares[i];
+        let mut rest = [0; 3];
+        let mut n = 0;
+        for (j, &square) in squares.iter().enumerate() {
+            if j != i {
+                rest[n] = square;
+                n += 1;
+            }
+        }
+        let (result, nodes) = solve_3(opponent ^ flipped, player | flipped | disk, -beta, -alpha, rest, wld);
+        total_nodes += nodes;
+
+        if -result > alpha {
+            alpha = -result;
+            if alpha >= beta {
+                return (alpha, total_nodes);
+            }
+        }
+    }
+
+    if moved {
+        return (alpha, total_nodes);
+    }
+
+    if game_over(player, opponent, &squares) {
+        return (final_score(player, opponent, wld), 1);
+    }
+
+    let (result, nodes) = solve_4(opponent, player, -beta, -alpha, squares, wld);
+    (-result, total_nodes + nodes)
+}
+
+/// Checks whether the opponent also has no move on any of the squares, given that the player has
+/// none, in which case the game is over.
+#[inline]
+fn game_over(player: u64, opponent: u64, squares: &[u8]) -> bool {
+    squares.iter().all(|&square| flips(square, opponent, player) == 0)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+
+    use crate::board::{ Board, compact::CompactBoard };
+
+    /// A plain negamax over the full move lists, to check the specialized solvers against.
+    fn reference(board: &mut Board) -> i32 {
+        if board.is_game_over() {
+            return if board.black_move { board.get_score() } else { -board.get_score() };
+        }
+
+        let mut best = -64;
+        for m in &board.get_moves() {
+            let undo = board.make_move(m);
+            best = best.max(-reference(board));
+            board.undo_move(undo, m);
+        }
+        best
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        let mut rng = rand::thread_rng();
+
+        for empties in 0..=4 {
+            for _ in 0..200 {
+                let mut board = Board::new();
+                while board.all_disks().count_zeros() > empties && !board.is_game_over() {
+                    let moves = board.get_moves();
+                    board.make_move(moves[rng.gen_range(0, moves.len())]);
+                }
+                if board.all_disks().count_zeros() > empties {
+                    continue;
+                }
+
+                let expected = reference(&mut board);
+                let compact = CompactBoard::from(&board);
+                assert_eq!(super::solve(&compact, -64, 64, false).0, expected);
+                assert_eq!(super::solve(&compact, -1, 1, true).0, expected.signum());
+
+                // Null windows must still give the right side of the bound.
+                let (score, _) = super::solve(&compact, expected - 1, expected, false);
+                assert!(score >= expected);
+                let (score, _) = super::solve(&compact, expected, expected + 1, false);
+                assert!(score <= expected);
+            }
+        }
+    }
+}
@@ -2,25 +2,36 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::board::Board;
 use crate::board::Move;
+use crate::board::compact::CompactBoard;
 
 mod piecesquare;
 pub use self::piecesquare::PieceSquareEvaluator;
 mod pattern;
-pub use self::pattern::PatternEvaluator;
+pub use self::pattern::{ PatternEvaluator, Perspective };
 mod staged;
-pub use self::staged::StagedPatternEvaluator;
+pub use self::staged::{ StagedPatternEvaluator, EVAL_ENV_VAR };
+mod stability;
+pub use self::stability::{ StabilityEvaluator, StabilityWeights };
 
 pub mod pattern_util;
 
+/// Scores positions for the searchers. Scores are relative to the player to move, and must not
+/// depend on which color that is, so that positions can be shared between colors in the
+/// transposition tables.
 pub trait Evaluator {
-    fn get_score(&self, _: &Board) -> i32;
-    fn move_order_score(&self, board: &mut Board, mv: Move) -> i32 {
-        let undo = board.make_move(mv);
-        let score = -self.get_score(board);
-        board.undo_move(undo, mv);
-        
-        score
+    fn get_score(&self, _: &CompactBoard) -> i32;
+    fn move_order_score(&self, board: &CompactBoard, mv: Move) -> i32 {
+        -self.get_score(&board.play(mv))
+    }
+}
+
+impl<E: Evaluator + ?Sized> Evaluator for &E {
+    fn get_score(&self, board: &CompactBoard) -> i32 {
+        (**self).get_score(board)
+    }
+
+    fn move_order_score(&self, board: &CompactBoard, mv: Move) -> i32 {
+        (**self).move_order_score(board, mv)
     }
 }
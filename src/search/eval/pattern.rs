@@ -2,29 +2,60 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::board::Board;
+use crate::board::compact::CompactBoard;
 use super::pattern_util::*;
 
-use std::error::Error;
+use crate::{ Error, Result };
+
 use std::fs::File;
 use std::io::BufReader;
 
-use serde::Deserialize;
+use serde::{ Deserialize, Serialize };
 use serde_json::{ from_reader };
 
+/// Whose point of view the weights in a file score positions from. Files written before
+/// evaluators scored for the player to move have no perspective, and are relative to black.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Perspective {
+    /// Black is the 2 digits in the pattern indices, and the score is negated when white is to
+    /// move.
+    #[default]
+    Black,
+    /// The player to move is the 2 digits in the pattern indices.
+    Mover
+}
+
+impl Perspective {
+    /// Checks whether the weights see the position with the colors swapped, so that the opponent
+    /// is the 2 digits in the pattern indices and the score must be negated.
+    /// # Arguments:
+    /// * `black_move`: Whether black is to move in the position being scored.
+    #[inline]
+    pub fn swaps(self, black_move: bool) -> bool {
+        self == Perspective::Black && !black_move
+    }
+}
+
 #[derive(Deserialize)]
 pub struct PatternFile {
     masks: Vec<u64>,
     weights: Vec<Vec<f32>>,
     parity_e: f32,
-    parity_o: f32
+    parity_o: f32,
+    #[serde(default)]
+    perspective: Perspective
 }
 
-#[derive(Default)]
+/// Scores positions by looking up the disks in each pattern. By default the weights are written
+/// from the point of view of the player to move, which takes the place of black in the pattern
+/// indices.
+#[derive(Clone, Default)]
 pub struct PatternEvaluator {
     patterns: Vec<(u64, Vec<f32>)>,
     parity_e: f32,
-    parity_o: f32
+    parity_o: f32,
+    perspective: Perspective
 }
 
 impl PatternEvaluator {
@@ -32,7 +63,8 @@ impl PatternEvaluator {
         PatternEvaluator {
             patterns: Vec::new(),
             parity_e: 0f32,
-            parity_o: 0f32
+            parity_o: 0f32,
+            perspective: Perspective::Mover
         }
     }
 
@@ -41,17 +73,8 @@ impl PatternEvaluator {
             let mut new_masks = vec![mask];
             let mut new_weights = vec![weight.clone()];
 
-            let max_idx = 3usize.pow(mask.count_ones() as u32);
-            let max_val = 2usize.pow(mask.count_ones() as u32);
-
-            let mut board = [-1; 64];
-            let mut idx = 0;
-            for i in 0..64 {
-                if mask & (0x80_00_00_00_00_00_00_00 << i) == 1 {
-                    board[i] = idx;
-                    idx += 1;
-                } 
-            }
+            let max_idx = 3usize.pow(mask.count_ones());
+            let max_val = 2usize.pow(mask.count_ones());
 
             let rotate_board = | a: u64, i: u8 | {
                 let mut temp = a;
@@ -103,51 +126,111 @@ impl PatternEvaluator {
 
         PatternEvaluator {
             patterns: all_masks.iter().zip(all_weights).map(| (&m, w) | (m, w)).collect(),
-            parity_e, parity_o
+            parity_e, parity_o,
+            perspective: Perspective::Mover
         }
     }
 
-    pub fn from_file(path: &str) -> Result<PatternEvaluator, Box<dyn Error>> {
+    /// Sets whose point of view the weights score positions from.
+    pub fn set_perspective(&mut self, perspective: Perspective) {
+        self.perspective = perspective;
+    }
+
+    pub fn from_file(path: &str) -> Result<PatternEvaluator> {
         let file = File::open(path)?;
         let reader = BufReader::new(file);
         let pat_file: PatternFile = from_reader(reader)?;
     
-        Ok(pat_file.to_eval())
+        pat_file.into_eval()
     }
 }
 
 impl super::Evaluator for PatternEvaluator {
-    fn get_score(&self, board: &Board) -> i32 {
+    fn get_score(&self, board: &CompactBoard) -> i32 {
         let mut score: f32 = if board.all_disks().count_zeros() & 1 == 1 {
             self.parity_o
         } else {
             self.parity_e
         };
 
-        let blacks = board.black_disks;
-        let whites = board.white_disks;
+        let swap = self.perspective.swaps(board.black_move);
+        let (twos, ones) = if swap { (board.opponent, board.player) } else { (board.player, board.opponent) };
 
         for (mask, weights) in self.patterns.iter() {
             // Extract the pattern from both bitboards
-            let black_pat = pext64(blacks, *mask) as usize;
-            let white_pat = pext64(whites, *mask) as usize;
+            let twos_pat = pext64(twos, *mask) as usize;
+            let ones_pat = pext64(ones, *mask) as usize;
             // Get the index of the given pattern
-            let index = ONES_TERNARY[white_pat] + TWOS_TERNARY[black_pat];
+            let index = ONES_TERNARY[ones_pat] + TWOS_TERNARY[twos_pat];
 
             // Add the pattern's weight to the score
             score += weights[index];
         }
 
-        if board.black_move {
-            (score * 100.0) as i32
-        } else {
+        if swap {
             (-score * 100.0) as i32
+        } else {
+            (score * 100.0) as i32
         }
     }
 }
 
 impl PatternFile {
-    pub fn to_eval(self) -> PatternEvaluator {
-        PatternEvaluator::from(self.masks, self.weights, self.parity_e, self.parity_o)
+    /// Builds the evaluator, after checking that there is a full table of weights for each mask.
+    pub fn into_eval(self) -> Result<PatternEvaluator> {
+        if self.masks.len() != self.weights.len() {
+            return Err(Error::InvalidWeights(format!("{} masks but {} weight tables", self.masks.len(), self.weights.len())));
+        }
+
+        for (mask, weights) in self.masks.iter().zip(&self.weights) {
+            // The ternary index tables only cover patterns of up to 12 squares.
+            if mask.count_ones() > 12 {
+                return Err(Error::InvalidWeights(format!("mask {:#018x} has more than 12 squares", mask)));
+            }
+
+            let expected = 3usize.pow(mask.count_ones());
+            if weights.len() != expected {
+                return Err(Error::InvalidWeights(format!("mask {:#018x} needs {} weights, got {}", mask, expected, weights.len())));
+            }
+        }
+
+        let mut eval = PatternEvaluator::from(self.masks, self.weights, self.parity_e, self.parity_o);
+        eval.set_perspective(self.perspective);
+        Ok(eval)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::from_str;
+
+    use crate::board::{ Board, Move, compact::CompactBoard };
+    use crate::search::eval::Evaluator;
+    use super::PatternFile;
+
+    #[test]
+    fn test_perspective() {
+        // A single square in the corner, with weights that are not symmetric between the colors.
+        let file = |perspective: &str| {
+            let json = format!(r#"{{"masks": [9223372036854775808], "weights": [[0.0, -1.0, 3.0]], "parity_e": 0.5, "parity_o": 0.0{}}}"#, perspective);
+            from_str::<PatternFile>(&json).unwrap().into_eval().unwrap()
+        };
+        let mover = file(r#", "perspective": "mover""#);
+        let black = file(r#", "perspective": "black""#);
+        let old = file("");
+
+        // Black owns the corner, and either side can be to move.
+        let mut board = Board::from_pos(0x80_00_00_00_00_00_00_00, 0x00_00_00_10_00_00_00_00, true);
+        let black_to_move = CompactBoard::from(&board);
+        board.make_move(Move::Pass);
+        let white_to_move = CompactBoard::from(&board);
+
+        assert_eq!(mover.get_score(&black_to_move), 350);
+        assert_eq!(mover.get_score(&white_to_move), -50);
+
+        // Black-relative weights score the corner for black, and are negated for white to move.
+        assert_eq!(black.get_score(&black_to_move), 350);
+        assert_eq!(black.get_score(&white_to_move), -350);
+        assert_eq!(old.get_score(&white_to_move), -350);
+    }
+}
@@ -1,13 +1,8 @@
-#[cfg(all(target_arch="x86", target_feature="bmi2"))]
-use std::arch::x86::{ _pext_u32, _pdep_u32 };
+use crate::board::cpu;
 
-#[cfg(all(target_arch="x86_64", target_feature="bmi2"))]
-use std::arch::x86_64::{ _pext_u64, _pdep_u64 };
+pub use crate::board::bitboard::{ flip_vertical, flip_diag };
 
-#[cfg(target_arch="x86_64")]
-use std::arch::x86_64::{ _bswap64 };
-
-pub const ONES_TERNARY: [usize; 4096] = [
+pub static ONES_TERNARY: [usize; 4096] = [
          0,      1,      3,      4,      9,     10,     12,     13,     27,     28,     30,     31,
         36,     37,     39,     40,     81,     82,     84,     85,     90,     91,     93,     94,
        108,    109,    111,    112,    117,    118,    120,    121,    243,    244,    246,    247,
@@ -352,7 +347,7 @@ pub const ONES_TERNARY: [usize; 4096] = [
     265716, 265717, 265719, 265720,
 ];
 
-pub const TWOS_TERNARY: [usize; 4096] = [
+pub static TWOS_TERNARY: [usize; 4096] = [
          0,      2,      6,      8,     18,     20,     24,     26,     54,     56,     60,     62,
         72,     74,     78,     80,    162,    164,    168,    170,    180,    182,    186,    188,
        216,    218,    222,    224,    234,    236,    240,    242,    486,    488,    492,    494,
@@ -697,29 +692,43 @@ pub const TWOS_TERNARY: [usize; 4096] = [
     531432, 531434, 531438, 531440
 ];
 
-#[cfg(all(target_arch="x86_64", target_feature="bmi2"))]
+/// Deposits the low bits of `a` into the set bits of `mask`, using PDEP if the CPU supports it.
+#[inline]
 pub fn pdep64(a: u64, mask: u64) -> u64 {
-    unsafe {
-        _pdep_u64(a, mask)
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if cpu::has_bmi2() {
+            // Only reached when the CPU supports BMI2.
+            return unsafe { pdep64_bmi2(a, mask) };
+        }
     }
+
+    pdep64_generic(a, mask)
 }
 
-#[cfg(all(target_arch="x86", target_feature="bmi2"))]
-pub fn pdep64(a: u64, mask: u64) -> u64 {
-    let mask_low = mask & 0xFF_FF_FF_FF;
-    let low = unsafe {
-        _pdep_u32((a & 0xFF_FF_FF_FF) as u32, mask_low as u32)
-    };
+/// # Safety
+/// * The CPU must support BMI2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+pub unsafe fn pdep64_bmi2(a: u64, mask: u64) -> u64 {
+    std::arch::x86_64::_pdep_u64(a, mask)
+}
 
-    let high = unsafe {
-        _pdep_u32(a >> 32, mask >> mask_low.count_ones())
-    };
+/// # Safety
+/// * The CPU must support BMI2.
+#[cfg(target_arch = "x86")]
+#[target_feature(enable = "bmi2")]
+pub unsafe fn pdep64_bmi2(a: u64, mask: u64) -> u64 {
+    use std::arch::x86::_pdep_u32;
+
+    let mask_low = mask & 0xFF_FF_FF_FF;
+    let low = _pdep_u32((a & 0xFF_FF_FF_FF) as u32, mask_low as u32) as u64;
+    let high = _pdep_u32((a >> mask_low.count_ones()) as u32, (mask >> 32) as u32) as u64;
 
     low | (high << 32)
 }
 
-#[cfg(not(target_feature="bmi2"))]
-pub fn pdep64(a: u64, mask: u64) -> u64 {
+pub fn pdep64_generic(a: u64, mask: u64) -> u64 {
     let mut temp = mask;
     let mut dst = 0;
     let mut idx = 1u64;
@@ -735,29 +744,44 @@ pub fn pdep64(a: u64, mask: u64) -> u64 {
     dst
 }
 
-#[cfg(all(target_arch="x86_64", target_feature="bmi2"))]
+/// Extracts the bits of `a` under the set bits of `mask` into the low bits of the result, using
+/// PEXT if the CPU supports it.
+#[inline]
 pub fn pext64(a: u64, mask: u64) -> u64 {
-    unsafe {
-        _pext_u64(a, mask)
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if cpu::has_bmi2() {
+            // Only reached when the CPU supports BMI2.
+            return unsafe { pext64_bmi2(a, mask) };
+        }
     }
+
+    pext64_generic(a, mask)
 }
 
-#[cfg(all(target_arch="x86", target_feature="bmi2"))]
-pub fn pext64(a: u64, mask: u64) -> u64 {
-    let mask_low = mask & 0xFF_FF_FF_FF;
-    let low = unsafe {
-        _pext_u32((a & 0xFF_FF_FF_FF) as u32, mask_low as u32)
-    };
+/// # Safety
+/// * The CPU must support BMI2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+pub unsafe fn pext64_bmi2(a: u64, mask: u64) -> u64 {
+    std::arch::x86_64::_pext_u64(a, mask)
+}
 
-    let high = unsafe {
-        _pext_u32(a >> 32, mask >> 32)
-    };
+/// # Safety
+/// * The CPU must support BMI2.
+#[cfg(target_arch = "x86")]
+#[target_feature(enable = "bmi2")]
+pub unsafe fn pext64_bmi2(a: u64, mask: u64) -> u64 {
+    use std::arch::x86::_pext_u32;
+
+    let mask_low = mask & 0xFF_FF_FF_FF;
+    let low = _pext_u32((a & 0xFF_FF_FF_FF) as u32, mask_low as u32) as u64;
+    let high = _pext_u32((a >> 32) as u32, (mask >> 32) as u32) as u64;
 
     low | (high << mask_low.count_ones())
 }
 
-#[cfg(not(target_feature="bmi2"))]
-pub fn pext64(a: u64, mask: u64) -> u64 {
+pub fn pext64_generic(a: u64, mask: u64) -> u64 {
     let mut temp = mask;
     let mut dst = 0;
     let mut idx = 1u64;
@@ -773,40 +797,31 @@ pub fn pext64(a: u64, mask: u64) -> u64 {
     dst
 }
 
-#[cfg(target_arch="x86_64")]
-pub fn flip_vertical(a: u64) -> u64 {
-    unsafe {
-        _bswap64(a as i64) as u64
-    }
-}
+#[cfg(test)]
+mod test {
+    use rand::Rng;
 
-#[cfg(not(target_arch="x86_64"))]
-pub fn flip_vertical(a: u64) -> u64 {
-    const K1: u64 = 0x00FF00FF00FF00FF;
-    const K2: u64 = 0x0000FFFF0000FFFF;
-    
-    let mut x = 0;
-    x = ((a >>  8) & K1) | ((a & K1) <<  8);
-    x = ((a >> 16) & K2) | ((a & K2) << 16);
-    x = ( a >> 32)       | ( a       << 32);
-    
-    x
-}
+    use crate::board::cpu;
 
-pub fn flip_diag(a: u64) -> u64 {
-    const K1: u64 = 0x5500550055005500;
-    const K2: u64 = 0x3333000033330000;
-    const K4: u64 = 0x0f0f0f0f00000000;
-    
-    let mut t;
-    let mut x = a;
-    
-    t  = K4 & (x ^ (x << 28));
-    x ^=       t ^ (t >> 28) ;
-    t  = K2 & (x ^ (x << 14));
-    x ^=       t ^ (t >> 14) ;
-    t  = K1 & (x ^ (x <<  7));
-    x ^=       t ^ (t >>  7) ;
+    #[test]
+    fn test_pext_pdep() {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..10000 {
+            let (a, mask) = (rng.gen::<u64>(), rng.gen::<u64>() & rng.gen::<u64>());
+
+            let extracted = super::pext64_generic(a, mask);
+            assert_eq!(extracted.count_ones(), (a & mask).count_ones());
+            assert_eq!(super::pdep64_generic(extracted, mask), a & mask);
+            assert_eq!(super::pext64(a, mask), extracted);
+            assert_eq!(super::pdep64(a, mask), super::pdep64_generic(a, mask));
 
-    x
-}
\ No newline at end of file
+            if cpu::has_bmi2() {
+                unsafe {
+                    assert_eq!(super::pext64_bmi2(a, mask), extracted);
+                    assert_eq!(super::pdep64_bmi2(a, mask), super::pdep64_generic(a, mask));
+                }
+            }
+        }
+    }
+}
@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::board::Board;
+use crate::board::compact::CompactBoard;
 
 const PIECE_SQUARE_MASKS: [u64; 10] = [
     0x81_00_00_00_00_00_00_81,
@@ -37,26 +37,21 @@ impl PieceSquareEvaluator {
 }
 
 impl super::Evaluator for PieceSquareEvaluator {
-    fn get_score(&self, board: &Board) -> i32 {
-        let mut black_score = 0;
-        let mut white_score = 0;
+    fn get_score(&self, board: &CompactBoard) -> i32 {
+        let mut score = 0;
 
         for (index, mask) in PIECE_SQUARE_MASKS.iter().enumerate() {
-            black_score += (board.black_disks & mask).count_ones() as i32 * self.square_table[index];
-            white_score += (board.white_disks & mask).count_ones() as i32 * self.square_table[index];
+            score += (board.player & mask).count_ones() as i32 * self.square_table[index];
+            score -= (board.opponent & mask).count_ones() as i32 * self.square_table[index];
         }
 
-        if board.black_move {
-            black_score - white_score
-        } else {
-            white_score - black_score
-        }
+        score
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::board::Board;
+    use crate::board::compact::CompactBoard;
     use crate::search::eval::Evaluator;
     use super::PieceSquareEvaluator;
 
@@ -65,7 +60,7 @@ mod test {
         let eval_1 = PieceSquareEvaluator::new();
         let eval_2 = PieceSquareEvaluator::from([1; 10]);
 
-        let board = Board::from_pos(0xFF_FF_FF_FF_00_00_00_00, 0x00_00_00_00_FF_FF_FF_FF, true);
+        let board = CompactBoard::new(0xFF_FF_FF_FF_00_00_00_00, 0x00_00_00_00_FF_FF_FF_FF, true);
         assert_eq!(eval_1.get_score(&board), 0);
         assert_eq!(eval_2.get_score(&board), 0);
     }
@@ -0,0 +1,194 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A hand-crafted evaluator built from mobility, frontier, corner and stability features. It needs
+//! no trained weights, and the default weights make a reasonable baseline. Each feature is the
+//! difference between the player to move and the opponent, and is weighted by interpolating
+//! between an opening and an endgame set of weights based on the number of disks on the board.
+
+use crate::Result;
+use crate::board::{ bitboard, compact::CompactBoard };
+
+use std::fs::File;
+use std::io::BufReader;
+
+use serde::{ Deserialize, Serialize };
+use serde_json::{ from_reader };
+
+/// The X-squares, diagonally adjacent to each corner, in the same order as `CORNER_LIST`.
+const X_SQUARES: [u64; 4] = [
+    0x00_40_00_00_00_00_00_00,
+    0x00_02_00_00_00_00_00_00,
+    0x00_00_00_00_00_00_40_00,
+    0x00_00_00_00_00_00_02_00
+];
+
+/// The C-squares, orthogonally adjacent to each corner, in the same order as `CORNER_LIST`.
+const C_SQUARES: [u64; 4] = [
+    0x40_80_00_00_00_00_00_00,
+    0x02_01_00_00_00_00_00_00,
+    0x00_00_00_00_00_00_80_40,
+    0x00_00_00_00_00_00_01_02
+];
+
+const CORNER_LIST: [u64; 4] = [
+    0x80_00_00_00_00_00_00_00,
+    0x01_00_00_00_00_00_00_00,
+    0x00_00_00_00_00_00_00_80,
+    0x00_00_00_00_00_00_00_01
+];
+
+/// The weight of each feature, in centi-disks per unit of difference.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StabilityWeights {
+    /// The number of legal moves.
+    pub mobility: i32,
+    /// The number of empty squares next to an opponent's disk.
+    pub potential_mobility: i32,
+    /// The number of disks next to an empty square.
+    pub frontier: i32,
+    /// The number of corners occupied.
+    pub corners: i32,
+    /// The number of X-squares occupied next to an empty corner.
+    pub x_squares: i32,
+    /// The number of C-squares occupied next to an empty corner.
+    pub c_squares: i32,
+    /// The number of stable disks.
+    pub stable: i32
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StabilityEvaluator {
+    opening: StabilityWeights,
+    endgame: StabilityWeights
+}
+
+impl StabilityEvaluator {
+    pub fn new() -> StabilityEvaluator {
+        StabilityEvaluator {
+            opening: StabilityWeights {
+                mobility: 40,
+                potential_mobility: 15,
+                frontier: -10,
+                corners: 300,
+                x_squares: -150,
+                c_squares: -50,
+                stable: 60
+            },
+            endgame: StabilityWeights {
+                mobility: 30,
+                potential_mobility: 5,
+                frontier: -5,
+                corners: 150,
+                x_squares: -50,
+                c_squares: -20,
+                stable: 100
+            }
+        }
+    }
+
+    pub fn from(opening: StabilityWeights, endgame: StabilityWeights) -> StabilityEvaluator {
+        StabilityEvaluator {
+            opening,
+            endgame
+        }
+    }
+
+    /// Loads the weights from a json file with `opening` and `endgame` weight sets.
+    pub fn from_file(path: &str) -> Result<StabilityEvaluator> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let eval = from_reader(reader)?;
+
+        Ok(eval)
+    }
+
+    /// Calculates the features for a single player.
+    /// # Returns:
+    /// * The features, in the same order as the fields of `StabilityWeights`.
+    fn features(player: u64, opponent: u64) -> [i32; 7] {
+        let empty = !(player | opponent);
+
+        let mut x_squares = 0;
+        let mut c_squares = 0;
+        for i in 0..CORNER_LIST.len() {
+            if empty & CORNER_LIST[i] != 0 {
+                x_squares += (player & X_SQUARES[i]).count_ones();
+                c_squares += (player & C_SQUARES[i]).count_ones();
+            }
+        }
+
+        [
+            bitboard::all_moves(player, opponent).count_ones() as i32,
+            (bitboard::neighbours(opponent) & empty).count_ones() as i32,
+            (bitboard::neighbours(empty) & player).count_ones() as i32,
+            (player & bitboard::CORNERS).count_ones() as i32,
+            x_squares as i32,
+            c_squares as i32,
+            bitboard::stable_disks(player, opponent).count_ones() as i32
+        ]
+    }
+}
+
+impl Default for StabilityEvaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StabilityWeights {
+    fn as_array(&self) -> [i32; 7] {
+        [
+            self.mobility,
+            self.potential_mobility,
+            self.frontier,
+            self.corners,
+            self.x_squares,
+            self.c_squares,
+            self.stable
+        ]
+    }
+}
+
+impl super::Evaluator for StabilityEvaluator {
+    fn get_score(&self, board: &CompactBoard) -> i32 {
+        let (player, opponent) = (board.player, board.opponent);
+
+        let player_features = StabilityEvaluator::features(player, opponent);
+        let opponent_features = StabilityEvaluator::features(opponent, player);
+
+        let opening = self.opening.as_array();
+        let endgame = self.endgame.as_array();
+        let phase = board.all_disks().count_ones() as i32;
+
+        let mut score = 0;
+        for i in 0..player_features.len() {
+            let weight = (opening[i] * (64 - phase) + endgame[i] * phase) / 64;
+            score += weight * (player_features[i] - opponent_features[i]);
+        }
+
+        score
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::board::{ Board, compact::CompactBoard };
+    use crate::search::eval::Evaluator;
+    use super::StabilityEvaluator;
+
+    #[test]
+    fn test_stability_eval() {
+        let eval = StabilityEvaluator::new();
+
+        // The start position is symmetric.
+        assert_eq!(eval.get_score(&CompactBoard::from(&Board::new())), 0);
+
+        // Black has a corner with an anchored edge, and white has an X-square next to an empty one.
+        let board = CompactBoard::new(0xE0_00_00_08_10_00_00_00, 0x00_00_00_10_08_00_02_00, true);
+        let score = eval.get_score(&board);
+        assert!(score > 0);
+        assert_eq!(eval.get_score(&board.pass()), -score);
+    }
+}
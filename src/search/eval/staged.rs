@@ -1,13 +1,22 @@
-use crate::board::Board;
+use crate::board::compact::CompactBoard;
 use super::{ PatternEvaluator, pattern::PatternFile };
 
 use std::collections::HashMap;
-use std::error::Error;
+use crate::{ Error, Result };
+
+use std::env;
 use std::fs::File;
 use std::io::BufReader;
 
 use serde::Deserialize;
-use serde_json::{ from_reader };
+use serde_json::{ from_reader, from_str };
+
+/// The environment variable which gives the weight file to use when none is given explicitly.
+pub const EVAL_ENV_VAR: &str = "RUTHLESS_EVAL";
+
+/// The weights built into the binary. These are the piece-square table written as single-square
+/// patterns, so they evaluate the same as `PieceSquareEvaluator` for every stage.
+const DEFAULT_WEIGHTS: &str = include_str!("default_weights.json");
 
 #[derive(Deserialize)]
 pub struct StagedPatternFile {
@@ -15,6 +24,7 @@ pub struct StagedPatternFile {
     evaluators: Vec<PatternFile>
 }
 
+#[derive(Clone)]
 pub struct StagedPatternEvaluator {
     stage_map: HashMap<u32, usize>,
     evaluators: Vec<PatternEvaluator>
@@ -50,31 +60,103 @@ impl StagedPatternEvaluator {
         }
     }
 
-    pub fn from_file(path: &str) -> Result<StagedPatternEvaluator, Box<dyn Error>> {
+    pub fn from_file(path: &str) -> Result<StagedPatternEvaluator> {
         let file = File::open(path)?;
         let reader = BufReader::new(file);
         let st_file: StagedPatternFile = from_reader(reader)?;
     
-        Ok(st_file.to_eval())
+        st_file.into_eval()
+    }
+
+    /// Loads the weights built into the binary.
+    pub fn embedded() -> Result<StagedPatternEvaluator> {
+        let st_file: StagedPatternFile = from_str(DEFAULT_WEIGHTS)?;
+
+        st_file.into_eval()
+    }
+
+    /// Loads the weights from the given file if there is one, then from the file named by the
+    /// `RUTHLESS_EVAL` environment variable if it is set, and otherwise uses the built-in weights.
+    /// # Arguments:
+    /// * `path`: The weight file to load, if one was given explicitly.
+    /// # Returns:
+    /// * The evaluator, or an error if the chosen file could not be read or parsed.
+    pub fn load(path: Option<&str>) -> Result<StagedPatternEvaluator> {
+        match path.map(String::from).or_else(|| env::var(EVAL_ENV_VAR).ok()) {
+            Some(path) => StagedPatternEvaluator::from_file(&path),
+            None => StagedPatternEvaluator::embedded()
+        }
+    }
+}
+
+impl Default for StagedPatternEvaluator {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl super::Evaluator for StagedPatternEvaluator {
-    fn get_score(&self, board: &Board) -> i32 {
+    fn get_score(&self, board: &CompactBoard) -> i32 {
         let disks = board.all_disks().count_ones();
-        let stage = self.stage_map.get(&disks).unwrap();
+        // Disk counts missing from the stage map use the last stage.
+        let stage = self.stage_map.get(&disks).copied().unwrap_or_else(|| self.evaluators.len().saturating_sub(1));
 
-        self.evaluators[*stage].get_score(board)
+        self.evaluators.get(stage).map_or(0, |eval| eval.get_score(board))
     }
 }
 
 impl StagedPatternFile {
-    pub fn to_eval(mut self) -> StagedPatternEvaluator {
-        let evals = self.evaluators.drain(0..).map(|e| e.to_eval()).collect();
+    /// Builds the evaluator, after checking that every stage in the map has an evaluator.
+    pub fn into_eval(mut self) -> Result<StagedPatternEvaluator> {
+        if let Some(&stage) = self.stage_map.values().find(|&&stage| stage >= self.evaluators.len()) {
+            return Err(Error::InvalidWeights(format!("stage {} has no evaluator", stage)));
+        }
 
-        StagedPatternEvaluator {
+        let evals = self.evaluators.drain(0..).map(|e| e.into_eval()).collect::<Result<_>>()?;
+
+        Ok(StagedPatternEvaluator {
             stage_map: self.stage_map,
             evaluators: evals
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+
+    use serde_json::from_str;
+
+    use crate::Error;
+    use crate::board::{ Board, compact::CompactBoard };
+    use crate::search::eval::{ Evaluator, PieceSquareEvaluator };
+    use super::{ DEFAULT_WEIGHTS, StagedPatternEvaluator, StagedPatternFile };
+
+    #[test]
+    fn test_embedded() {
+        let embedded = StagedPatternEvaluator::embedded().unwrap();
+        let piece_square = PieceSquareEvaluator::new();
+        let mut rng = rand::thread_rng();
+
+        // The weights are stored as floats, so allow for rounding.
+        for _ in 0..20 {
+            let mut board = Board::new();
+            while !board.is_game_over() {
+                let compact = CompactBoard::from(&board);
+                assert!((embedded.get_score(&compact) - piece_square.get_score(&compact)).abs() <= 1);
+                let moves = board.get_moves();
+                board.make_move(moves[rng.gen_range(0, moves.len())]);
+            }
+            embedded.get_score(&CompactBoard::from(&board));
         }
+
+        assert!(matches!(StagedPatternEvaluator::load(Some("does_not_exist.json")), Err(Error::Io(_))));
+
+        // Malformed files are rejected when loaded, rather than panicking when evaluating.
+        let bad_stage = DEFAULT_WEIGHTS.replace("\"64\": 0", "\"64\": 1");
+        assert!(matches!(from_str::<StagedPatternFile>(&bad_stage).unwrap().into_eval(), Err(Error::InvalidWeights(_))));
+        let bad_weights = DEFAULT_WEIGHTS.replacen("[0.0, -0.64, 0.64]", "[0.0, -0.64]", 1);
+        assert!(matches!(from_str::<StagedPatternFile>(&bad_weights).unwrap().into_eval(), Err(Error::InvalidWeights(_))));
+        assert!(matches!(from_str::<StagedPatternFile>("{}").map_err(Error::from), Err(Error::InvalidWeights(_))));
     }
-}
\ No newline at end of file
+}
@@ -2,6 +2,8 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use std::thread;
+
 use crate::board::{ Board, Move };
 use crate::search::endgame::endgame_solve;
 use crate::search::endgame::EndgameSearcher;
@@ -15,7 +17,7 @@ fn move_in_list(moves: Vec<u8>, m: Move) -> bool {
 fn ffo_pos_40_wld() {
     let mut board = Board::from_pos(0x0101312303010100, 0x9E7ECEDCFC1E0800, true);
 
-    assert_eq!(endgame_solve(&mut board, true, true).0, 1);
+    assert_eq!(endgame_solve(&mut board, true, true).score, 1);
 }
 
 #[test]
@@ -23,9 +25,11 @@ fn ffo_pos_40_wld() {
 fn ffo_pos_40_exact() {
     let mut board = Board::from_pos(0x0101312303010100, 0x9E7ECEDCFC1E0800, true);
 
-    let searcher: EndgameSearcher = EndgameSearcher::new(true);
+    let mut searcher: EndgameSearcher = EndgameSearcher::new(true).unwrap();
+    searcher.set_threads(thread::available_parallelism().map_or(1, |n| n.get())).unwrap();
 
-    let (score, m, _) = searcher.endgame_solve(&mut board, false);
+    let result = searcher.endgame_solve(&mut board, false);
+    let (score, m) = (result.score, result.best_move);
     assert_eq!(score, 38);
     assert!(move_in_list(vec![ 8 ], m));
 }
@@ -35,7 +39,7 @@ fn ffo_pos_40_exact() {
 fn ffo_pos_41_wld() {
     let mut board = Board::from_pos(0x000200F8642C1800, 0x7C3C7E0618D02472, true);
 
-    assert_eq!(endgame_solve(&mut board, true, true).0, 0);
+    assert_eq!(endgame_solve(&mut board, true, true).score, 0);
 }
 
 #[test]
@@ -43,9 +47,11 @@ fn ffo_pos_41_wld() {
 fn ffo_pos_41_exact() {
     let mut board = Board::from_pos(0x000200F8642C1800, 0x7C3C7E0618D02472, true);
 
-    let searcher: EndgameSearcher = EndgameSearcher::new(true);
+    let mut searcher: EndgameSearcher = EndgameSearcher::new(true).unwrap();
+    searcher.set_threads(thread::available_parallelism().map_or(1, |n| n.get())).unwrap();
 
-    let (score, m, _) = searcher.endgame_solve(&mut board, false);
+    let result = searcher.endgame_solve(&mut board, false);
+    let (score, m) = (result.score, result.best_move);
     assert_eq!(score, 0);
     assert!(move_in_list(vec![ 31 ], m));
 }
@@ -55,7 +61,7 @@ fn ffo_pos_41_exact() {
 fn ffo_pos_42_wld() {
     let mut board = Board::from_pos(0x000C040486040200, 0x3801FB7B391B1D3C, true);
 
-    assert_eq!(endgame_solve(&mut board, true, true).0, 1);
+    assert_eq!(endgame_solve(&mut board, true, true).score, 1);
 }
 
 #[test]
@@ -63,9 +69,11 @@ fn ffo_pos_42_wld() {
 fn ffo_pos_42_exact() {
     let mut board = Board::from_pos(0x000C040486040200, 0x3801FB7B391B1D3C, true);
 
-    let searcher: EndgameSearcher = EndgameSearcher::new(true);
+    let mut searcher: EndgameSearcher = EndgameSearcher::new(true).unwrap();
+    searcher.set_threads(thread::available_parallelism().map_or(1, |n| n.get())).unwrap();
 
-    let (score, m, _) = searcher.endgame_solve(&mut board, false);
+    let result = searcher.endgame_solve(&mut board, false);
+    let (score, m) = (result.score, result.best_move);
     assert_eq!(score, 6);
     assert!(move_in_list(vec![ 14 ], m));
 }
@@ -75,7 +83,7 @@ fn ffo_pos_42_exact() {
 fn ffo_pos_43_wld() {
     let mut board = Board::from_pos(0x3E3C0C1E1C08143E, 0x0000706062F60800, false);
 
-    assert_eq!(endgame_solve(&mut board, true, true).0, -1);
+    assert_eq!(endgame_solve(&mut board, true, true).score, -1);
 }
 
 #[test]
@@ -83,9 +91,11 @@ fn ffo_pos_43_wld() {
 fn ffo_pos_43_exact() {
     let mut board = Board::from_pos(0x3E3C0C1E1C08143E, 0x0000706062F60800, false);
 
-    let searcher: EndgameSearcher = EndgameSearcher::new(true);
+    let mut searcher: EndgameSearcher = EndgameSearcher::new(true).unwrap();
+    searcher.set_threads(thread::available_parallelism().map_or(1, |n| n.get())).unwrap();
 
-    let (score, m, _) = searcher.endgame_solve(&mut board, false);
+    let result = searcher.endgame_solve(&mut board, false);
+    let (score, m) = (result.score, result.best_move);
     assert_eq!(score, -12);
     assert!(move_in_list(vec![ 50, 22 ], m));
 }
@@ -95,7 +105,7 @@ fn ffo_pos_43_exact() {
 fn ffo_pos_44_wld() {
     let mut board = Board::from_pos(0x08081C0E0CC83C1C, 0x222563F1F0340000, false);
 
-    assert_eq!(endgame_solve(&mut board, true, true).0, -1);
+    assert_eq!(endgame_solve(&mut board, true, true).score, -1);
 }
 
 #[test]
@@ -103,19 +113,43 @@ fn ffo_pos_44_wld() {
 fn ffo_pos_44_exact() {
     let mut board = Board::from_pos(0x08081C0E0CC83C1C, 0x222563F1F0340000, false);
 
-    let searcher: EndgameSearcher = EndgameSearcher::new(true);
+    let mut searcher: EndgameSearcher = EndgameSearcher::new(true).unwrap();
+    searcher.set_threads(thread::available_parallelism().map_or(1, |n| n.get())).unwrap();
 
-    let (score, m, _) = searcher.endgame_solve(&mut board, false);
+    let result = searcher.endgame_solve(&mut board, false);
+    let (score, m) = (result.score, result.best_move);
     assert_eq!(score, -14);
     assert!(move_in_list(vec![ 57, 11 ], m));
 }
 
+#[test]
+#[ignore]
+fn ffo_pos_45_wld() {
+    let mut board = Board::from_pos(0x1EB8D0E8D8380000, 0x00042C142446BC0C, true);
+
+    assert_eq!(endgame_solve(&mut board, true, true).score, 1);
+}
+
+#[test]
+#[ignore]
+fn ffo_pos_45_exact() {
+    let mut board = Board::from_pos(0x1EB8D0E8D8380000, 0x00042C142446BC0C, true);
+
+    let mut searcher: EndgameSearcher = EndgameSearcher::new(true).unwrap();
+    searcher.set_threads(thread::available_parallelism().map_or(1, |n| n.get())).unwrap();
+
+    let result = searcher.endgame_solve(&mut board, false);
+    let (score, m) = (result.score, result.best_move);
+    assert_eq!(score, 6);
+    assert!(move_in_list(vec![ 9 ], m));
+}
+
 #[test]
 #[ignore]
 fn ffo_pos_46_wld() {
     let mut board = Board::from_pos(0x1C04060703173078, 0x003838783C280C02, true);
 
-    assert_eq!(endgame_solve(&mut board, true, true).0, -1);
+    assert_eq!(endgame_solve(&mut board, true, true).score, -1);
 }
 
 #[test]
@@ -123,9 +157,11 @@ fn ffo_pos_46_wld() {
 fn ffo_pos_46_exact() {
     let mut board = Board::from_pos(0x1C04060703173078, 0x003838783C280C02, true);
 
-    let searcher: EndgameSearcher = EndgameSearcher::new(true);
+    let mut searcher: EndgameSearcher = EndgameSearcher::new(true).unwrap();
+    searcher.set_threads(thread::available_parallelism().map_or(1, |n| n.get())).unwrap();
 
-    let (score, m, _) = searcher.endgame_solve(&mut board, false);
+    let result = searcher.endgame_solve(&mut board, false);
+    let (score, m) = (result.score, result.best_move);
     assert_eq!(score, -8);
     assert!(move_in_list(vec![ 17 ], m));
 }
@@ -133,13 +169,13 @@ fn ffo_pos_46_exact() {
 #[test]
 #[ignore]
 fn ffo_pos_47_exact() {
-    let mut board = Board::from_pos(0x000004FC24140C3C, 0x7C3C780058E83000, true);
-    board.make_move(Move::Play(14));
-    board.make_move(Move::Play(57));
+    let mut board = Board::from_pos(0x000004FC24140C3C, 0x7C3C780058E83000, false);
 
-    let searcher: EndgameSearcher = EndgameSearcher::new(true);
+    let mut searcher: EndgameSearcher = EndgameSearcher::new(true).unwrap();
+    searcher.set_threads(thread::available_parallelism().map_or(1, |n| n.get())).unwrap();
 
-    let (score, m, _) = searcher.endgame_solve(&mut board, false);
+    let result = searcher.endgame_solve(&mut board, false);
+    let (score, m) = (result.score, result.best_move);
     assert_eq!(score, 4);
-    assert!(move_in_list(vec![ 49 ], m));
+    assert!(move_in_list(vec![ 14 ], m));
 }
@@ -1,83 +1,285 @@
-use crate::board::{ Board, Move };
+//! A transposition table keyed on the Zobrist key of a position, from `CompactBoard::hash_key`.
+//! Entries are packed into 16 bytes and grouped into cache-line sized buckets. Each entry records
+//! the search generation it was written in, so that entries from earlier searches are preferred
+//! for replacement instead of the whole table being cleared between moves.
+//!
+//! The table can be shared between threads without locking. Each entry is stored as two atomic
+//! words, with the key XORed with the data, so an entry torn by a concurrent write fails the key
+//! check and is treated as a miss.
 
-#[derive(Clone, Copy, Debug)]
+use std::sync::atomic::{ AtomicU64, AtomicU8, Ordering };
+
+use crate::board::Move;
+
+/// The number of entries in each bucket.
+const BUCKET_SIZE: usize = 4;
+
+/// How many plies of depth one generation of age is worth when picking an entry to replace.
+const AGE_WEIGHT: i32 = 4;
+
+const BOUND_EXACT: u64 = 1;
+const BOUND_LOWER: u64 = 2;
+const BOUND_UPPER: u64 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Score {
     Exact(i32),
     Lower(i32),
     Upper(i32)
 }
 
-#[derive(Clone, Copy, Debug)]
+/// A single table entry. `data` packs the score into bits 0-31, the depth into bits 32-39, the
+/// bound type into bits 40-41, the best move into bits 48-55, and the generation into bits 56-63.
+/// An entry with a bound of zero is empty.
+#[derive(Clone, Copy, Debug, Default)]
 struct Entry {
-    depth: u8,
-    score: Score,
-    state: u128,
-    replace: bool
+    key: u64,
+    data: u64
+}
+
+impl Entry {
+    fn new(key: u64, score: Score, depth: u8, best_move: Move, generation: u8) -> Entry {
+        let (bound, value) = match score {
+            Score::Exact(value) => (BOUND_EXACT, value),
+            Score::Lower(value) => (BOUND_LOWER, value),
+            Score::Upper(value) => (BOUND_UPPER, value)
+        };
+
+        let data = u64::from(value as u32)
+            | u64::from(depth) << 32
+            | bound << 40
+            | u64::from(encode_move(best_move)) << 48
+            | u64::from(generation) << 56;
+
+        Entry { key, data }
+    }
+
+    fn is_empty(self) -> bool {
+        self.bound() == 0
+    }
+
+    fn score(self) -> Score {
+        let value = self.data as u32 as i32;
+        match self.bound() {
+            BOUND_EXACT => Score::Exact(value),
+            BOUND_LOWER => Score::Lower(value),
+            _ => Score::Upper(value)
+        }
+    }
+
+    fn best_move(self) -> Move {
+        decode_move((self.data >> 48) as u8)
+    }
+
+    fn depth(self) -> u8 {
+        (self.data >> 32) as u8
+    }
+
+    fn bound(self) -> u64 {
+        (self.data >> 40) & 0x3
+    }
+
+    fn generation(self) -> u8 {
+        (self.data >> 56) as u8
+    }
+}
+
+/// Packs a move into a byte, with squares stored as-is and a pass stored as 64.
+fn encode_move(m: Move) -> u8 {
+    match m {
+        Move::Play(m) => m,
+        Move::Pass => 64
+    }
+}
+
+fn decode_move(m: u8) -> Move {
+    if m < 64 {
+        Move::Play(m)
+    } else {
+        Move::Pass
+    }
+}
+
+/// The storage for a single entry, which can be read and written concurrently.
+#[derive(Debug, Default)]
+struct Slot {
+    check: AtomicU64,
+    data: AtomicU64
+}
+
+impl Slot {
+    fn load(&self) -> Entry {
+        let data = self.data.load(Ordering::Relaxed);
+        let key = self.check.load(Ordering::Relaxed) ^ data;
+        Entry { key, data }
+    }
+
+    fn store(&self, entry: Entry) {
+        self.data.store(entry.data, Ordering::Relaxed);
+        self.check.store(entry.key ^ entry.data, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct Bucket {
+    entries: [Slot; BUCKET_SIZE]
 }
 
 pub struct HashTable {
-    size: usize,
-    table: Vec<Option<Entry>>
+    table: Vec<Bucket>,
+    generation: AtomicU8
 }
 
 impl HashTable {
-    pub fn empty(size: usize) -> Self {
-        let mut table = Vec::new();
-        table.resize_with(size, || None);
+    /// Creates an empty table which uses approximately the given amount of memory.
+    /// # Arguments:
+    /// * `mb`: The size of the table in megabytes. The table always has at least one bucket.
+    /// # Returns:
+    /// * An empty `HashTable`.
+    pub fn with_size_mb(mb: usize) -> Self {
+        let buckets = (mb << 20) / std::mem::size_of::<Bucket>();
 
         HashTable {
-            size,
-            table
+            table: (0..buckets.max(1)).map(|_| Bucket::default()).collect(),
+            generation: AtomicU8::new(0)
         }
     }
 
-    pub fn probe(&self, board: &Board, depth: u8, alpha: i32, beta: i32) -> Option<Score> {
-        let state = ((board.black_disks as u128) << 64) + board.white_disks as u128;
-        let index = (state % self.size as u128) as usize;
-        let value = self.table[index];
+    /// Returns the index of the bucket for the given key. Uses the high bits of the key, so that
+    /// the table size does not need to be a power of two.
+    fn index(&self, key: u64) -> usize {
+        ((u128::from(key) * self.table.len() as u128) >> 64) as usize
+    }
+
+    /// Looks up the given position in the table.
+    /// # Arguments:
+    /// * `key`: The hash key of the position to look up.
+    /// * `depth`: The minimum depth for a stored score to be usable.
+    /// * `alpha`: The lower bound of the search window.
+    /// * `beta`: The upper bound of the search window.
+    /// # Returns:
+    /// * The stored score, if it was searched deep enough and is useful for the given window.
+    /// * The stored best move, if the position is in the table at all.
+    pub fn probe(&self, key: u64, depth: u8, alpha: i32, beta: i32) -> (Option<Score>, Option<Move>) {
+        let bucket = &self.table[self.index(key)];
 
-        if let Some(entry) = value {
-            if entry.state == state && entry.depth >= depth {
-                return match entry.score {
-                    Score::Exact(_) => Some(entry.score),
-                    Score::Lower(score) if score >= beta => Some(entry.score),
-                    Score::Upper(score) if score <= alpha => Some(entry.score),
-                    _ => None
-                }
+        for slot in &bucket.entries {
+            let entry = slot.load();
+            if entry.key == key && !entry.is_empty() {
+                let score = entry.score();
+                let usable = entry.depth() >= depth && match score {
+                    Score::Exact(_) => true,
+                    Score::Lower(value) => value >= beta,
+                    Score::Upper(value) => value <= alpha
+                };
+
+                return (if usable { Some(score) } else { None }, Some(entry.best_move()));
             }
         }
 
-        None
+        (None, None)
     }
 
-    pub fn save(&mut self, board: &Board, score: Score, depth: u8) {
-        let state = ((board.black_disks as u128) << 64) + board.white_disks as u128;
-        let index = (state % self.size as u128) as usize;
-        let value = self.table[index];
+    /// Saves a score and the best (or refutation) move for the position with the given key. An
+    /// existing entry for the same position is always overwritten. Otherwise, an empty entry is
+    /// used if there is one, or else the entry with the lowest depth after accounting for how many
+    /// searches ago it was written.
+    pub fn save(&self, key: u64, score: Score, depth: u8, best_move: Move) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let bucket = &self.table[self.index(key)];
 
-        match value {
-            Some(entry) if entry.depth > depth && !entry.replace => {},
-            _ => {
-                let new_entry = Entry {
-                    depth, score, state, replace: false
-                };
+        let mut victim = 0;
+        let mut victim_value = i32::MAX;
+        for (i, slot) in bucket.entries.iter().enumerate() {
+            let entry = slot.load();
+            if entry.key == key || entry.is_empty() {
+                victim = i;
+                break;
+            }
 
-                self.table[index] = Some(new_entry);
+            let age = i32::from(generation.wrapping_sub(entry.generation()));
+            let value = i32::from(entry.depth()) - AGE_WEIGHT * age;
+            if value < victim_value {
+                victim = i;
+                victim_value = value;
             }
         }
+
+        bucket.entries[victim].store(Entry::new(key, score, depth, best_move, generation));
     }
 
-    pub fn set_replace(&mut self) {
-        for value in self.table.iter_mut() {
-            if let Some(entry) = value {
-                entry.replace = true;
-            }
-        }
+    /// Marks the start of a new search. Entries from earlier searches remain usable, but are
+    /// replaced in preference to entries from the current search.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn clear(&mut self) {
-        for i in 0..self.table.len() {
-            self.table[i] = None;
+        for bucket in self.table.iter_mut() {
+            *bucket = Bucket::default();
+        }
+        self.generation = AtomicU8::new(0);
+    }
+}
+
+impl Clone for HashTable {
+    fn clone(&self) -> HashTable {
+        let table = self.table.iter().map(|bucket| {
+            let copy = Bucket::default();
+            for (slot, entry) in copy.entries.iter().zip(bucket.entries.iter()) {
+                slot.store(entry.load());
+            }
+            copy
+        }).collect();
+
+        HashTable {
+            table,
+            generation: AtomicU8::new(self.generation.load(Ordering::Relaxed))
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use crate::board::{ Board, Move, compact::CompactBoard };
+    use super::{ HashTable, Score };
+
+    #[test]
+    fn test_probe_bounds() {
+        let table = HashTable::with_size_mb(1);
+        let key = CompactBoard::from(&Board::new()).hash_key();
+
+        assert_eq!(table.probe(key, 1, -100, 100), (None, None));
+
+        table.save(key, Score::Lower(50), 6, Move::Play(19));
+        assert_eq!(table.probe(key, 6, -100, 40), (Some(Score::Lower(50)), Some(Move::Play(19))));
+        assert_eq!(table.probe(key, 6, -100, 100), (None, Some(Move::Play(19))));
+        assert_eq!(table.probe(key, 7, -100, 40), (None, Some(Move::Play(19))));
+
+        table.save(key, Score::Exact(-i32::MAX), 8, Move::Pass);
+        assert_eq!(table.probe(key, 8, -100, 100), (Some(Score::Exact(-i32::MAX)), Some(Move::Pass)));
+    }
+
+    #[test]
+    fn test_side_to_move() {
+        let table = HashTable::with_size_mb(1);
+        let board = CompactBoard::from(&Board::new());
+
+        table.save(board.hash_key(), Score::Exact(10), 4, Move::Play(19));
+        assert_eq!(table.probe(board.pass().hash_key(), 1, -100, 100), (None, None));
+    }
+
+    #[test]
+    fn test_persists_across_searches() {
+        let mut table = HashTable::with_size_mb(1);
+        let key = CompactBoard::from(&Board::new()).hash_key();
+
+        table.save(key, Score::Exact(10), 4, Move::Play(19));
+        table.new_search();
+        assert_eq!(table.probe(key, 4, -100, 100), (Some(Score::Exact(10)), Some(Move::Play(19))));
+
+        table.clear();
+        assert_eq!(table.probe(key, 4, -100, 100), (None, None));
+    }
+}
@@ -5,29 +5,81 @@
 //! Contains an implementation of iterative deepening which allows you to select the algorithm
 //! which you want to use to perform the search. Currently implemented are Best Node Search and
 //! Negamax. All functions take time in millis as the parameter.
+//!
+//! `iter_deep` is a generic driver used by the `Searcher` implementations, which deepens any
+//! fixed-depth search until one of the `SearchLimits` is reached.
 
-use crate::board::{ Board, Move };
-use crate::search::{ SearchData, bns, negamax, eval::Evaluator };
+use crate::board::Board;
+use crate::search::{ SearchData, SearchLimits, SearchResult, bns, negamax, eval::Evaluator };
 
 const MIN_SEARCH_DEPTH: u8 = 8;
 
-pub fn bns_iter_deep<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T) -> (i32, Move, SearchData) {
+/// Runs an iterative deepening search using the given fixed-depth search until one of the limits
+/// is reached. If only a depth limit is given, a single search is run at that depth. Otherwise the
+/// search starts at `MIN_SEARCH_DEPTH` (or the depth limit, if lower) and deepens one ply at a
+/// time, stopping when the next iteration is predicted to exceed the time or node limit.
+/// # Arguments:
+/// * `board`: Board to search.
+/// * `limits`: The limits placed on the search.
+/// * `search`: A function which searches the board to the given depth.
+/// # Returns:
+/// * The result of the deepest completed search, with the statistics totalled over all iterations.
+pub fn iter_deep<F>(board: &mut Board, limits: SearchLimits, mut search: F) -> SearchResult
+    where F: FnMut(&mut Board, u8) -> SearchResult
+{
+    let empties = board.all_disks().count_zeros().max(1) as u8;
+    let iterative = limits.time.is_some() || limits.nodes.is_some();
+
+    let max_depth = match limits.depth {
+        Some(depth) => depth.max(1),
+        None if iterative => empties,
+        None => MIN_SEARCH_DEPTH
+    };
+
+    let mut depth = if iterative { MIN_SEARCH_DEPTH.min(max_depth) } else { max_depth };
+    let mut total_nodes = 0;
+    let mut total_time = 0;
+
+    loop {
+        let mut result = search(board, depth);
+
+        total_nodes += result.data.nodes;
+        total_time += result.data.time;
+
+        let branching_factor = (result.data.nodes as f32).powf(1.0 / depth as f32);
+        let predicted_nodes = (result.data.nodes as f32 * branching_factor) as u64;
+        let predicted_time = (result.data.time as f32 * branching_factor) as u32;
+
+        let out_of_time = limits.time.is_some_and(|time| total_time + predicted_time >= time);
+        let out_of_nodes = limits.nodes.is_some_and(|nodes| total_nodes + predicted_nodes >= nodes);
+
+        if depth >= max_depth || depth >= empties || out_of_time || out_of_nodes {
+            result.data = SearchData { nodes: total_nodes, time: total_time, depth };
+            return result;
+        }
+
+        depth += 1;
+    }
+}
+
+pub fn bns_iter_deep<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T, print: bool) -> SearchResult {
     let mut depth = MIN_SEARCH_DEPTH;
     let mut time_prediction = 0;
     let mut time_spent = 0;
 
     let mut searched_total = 0;
     
-    let mut best_move = board.get_moves()[0];
+    let mut best_pv = vec![board.get_moves()[0]];
     let mut best_score = 0;
 
     let mut branching_factor;
 
     while time_prediction < time {
-        let (score, m, data) = bns::best_node_search(board, depth, evaluator);
+        let result = bns::best_node_search(board, depth, evaluator, print);
+        let data = result.data;
 
-        best_move = m;
-        best_score = score;
+        best_pv = result.pv;
+        best_score = result.score;
         searched_total += data.nodes;
         
         branching_factor = (data.nodes as f32).powf(1.0 / depth as f32);
@@ -39,28 +91,36 @@ pub fn bns_iter_deep<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T)
 
     depth -= 1;
 
-    println!("Final search was depth {}. Total time was {:.2} s", depth - 1, time_spent as f32 / 1000.0);
+    if print {
+        println!("Final search was depth {}. Total time was {:.2} s", depth, time_spent as f32 / 1000.0);
+    }
 
-    (best_score, best_move, SearchData { nodes: searched_total, time: time_spent, depth })
+    SearchResult {
+        score: best_score,
+        best_move: best_pv[0],
+        pv: best_pv,
+        data: SearchData { nodes: searched_total, time: time_spent, depth }
+    }
 }
 
-pub fn nm_iter_deep<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T) -> (i32, Move, SearchData) {
+pub fn nm_iter_deep<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T) -> SearchResult {
     let mut depth = MIN_SEARCH_DEPTH;
     let mut time_prediction = 0;
     let mut time_spent = 0;
 
     let mut searched_total = 0;
     
-    let mut best_move = board.get_moves()[0];
+    let mut best_pv = vec![board.get_moves()[0]];
     let mut best_score = 0;
 
     let mut branching_factor;
 
     while time_prediction < time {
-        let (score, m, data) = negamax::negamax(board, depth, evaluator, false);
+        let result = negamax::negamax(board, depth, evaluator, false);
+        let data = result.data;
 
-        best_move = m;
-        best_score = score;
+        best_pv = result.pv;
+        best_score = result.score;
         searched_total += data.nodes;
         
         branching_factor = (data.nodes as f32).powf(1.0 / depth as f32);
@@ -74,5 +134,10 @@ pub fn nm_iter_deep<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T) -
 
     eprintln!("Final search was depth {}. Total time was {:.2} s", depth, time_spent as f32 / 1000.0);
 
-    (best_score, best_move, SearchData { nodes: searched_total, time: time_spent, depth })
+    SearchResult {
+        score: best_score,
+        best_move: best_pv[0],
+        pv: best_pv,
+        data: SearchData { nodes: searched_total, time: time_spent, depth }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,423 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Monte Carlo Tree Search, using UCT to select nodes and a configurable policy to play out games
+//! from the leaves. Each iteration expands a single node and plays a single game out to the end,
+//! so the number of playouts is also the number of nodes in the tree.
+//!
+//! The tree is kept between searches. When the next search starts from a position reached by one
+//! or two moves from the last root, that part of the tree becomes the new root.
+
+use std::time::Instant;
+
+use rand::prelude::*;
+use rand::rngs::ThreadRng;
+
+use crate::board::{ Board, Move, compact::CompactBoard, movelist::MoveList };
+use crate::search::{ SearchData, SearchLimits, SearchResult, Searcher, format_pv, eval::Evaluator };
+
+/// The exploration constant used in the UCT formula.
+const DEFAULT_EXPLORATION: f64 = 1.4;
+
+/// The number of playouts to run when neither a playout nor a time limit is given.
+const DEFAULT_PLAYOUTS: u64 = 10_000;
+
+/// How many playouts to run between checks of the time limit.
+const TIME_CHECK_INTERVAL: u64 = 64;
+
+/// The probability that the evaluator-guided policy plays a random move instead of the best one.
+const DEFAULT_EPSILON: f64 = 0.1;
+
+/// A policy for choosing moves while playing out a game from a leaf of the tree.
+pub trait Playout {
+    /// Chooses the next move of the playout.
+    /// # Arguments:
+    /// * `board`: The current position.
+    /// * `moves`: The legal moves in the position. It is never empty.
+    /// * `rng`: The random number generator to use.
+    /// # Returns:
+    /// * The move to play.
+    fn choose_move(&self, board: &CompactBoard, moves: &MoveList, rng: &mut ThreadRng) -> Move;
+}
+
+/// Plays uniformly random moves.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomPlayout;
+
+impl Playout for RandomPlayout {
+    fn choose_move(&self, _board: &CompactBoard, moves: &MoveList, rng: &mut ThreadRng) -> Move {
+        moves[rng.gen_range(0, moves.len())]
+    }
+}
+
+/// Plays the move with the best `move_order_score` under the given evaluator, or a random move
+/// with probability `epsilon`, so that playouts from the same leaf do not all play the same game.
+#[derive(Clone, Debug)]
+pub struct EvalPlayout<E: Evaluator> {
+    eval: E,
+    epsilon: f64
+}
+
+impl<E: Evaluator> EvalPlayout<E> {
+    pub fn new(eval: E) -> EvalPlayout<E> {
+        EvalPlayout {
+            eval,
+            epsilon: DEFAULT_EPSILON
+        }
+    }
+
+    /// Sets the probability of playing a random move instead of the best one.
+    pub fn set_epsilon(&mut self, epsilon: f64) {
+        self.epsilon = epsilon;
+    }
+}
+
+impl<E: Evaluator> Playout for EvalPlayout<E> {
+    fn choose_move(&self, board: &CompactBoard, moves: &MoveList, rng: &mut ThreadRng) -> Move {
+        if moves.len() == 1 || rng.gen::<f64>() < self.epsilon {
+            return moves[rng.gen_range(0, moves.len())];
+        }
+
+        let mut best_move = moves[0];
+        let mut best_score = i32::MIN;
+        for m in moves {
+            let score = self.eval.move_order_score(board, m);
+            if score > best_score {
+                best_move = m;
+                best_score = score;
+            }
+        }
+
+        best_move
+    }
+}
+
+/// The statistics for one of the moves at the root of the tree.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MoveStats {
+    pub m: Move,
+    /// The number of playouts which started with this move.
+    pub visits: u32,
+    /// The fraction of those playouts won by the player to move at the root, counting draws as
+    /// half a win.
+    pub win_rate: f64
+}
+
+/// A node of the search tree. The wins are counted for the player who played `m`, which is the
+/// player choosing between this node and its siblings.
+struct Node {
+    m: Move,
+    visits: u32,
+    wins: f64,
+    untried: Option<Vec<Move>>,
+    children: Vec<Node>
+}
+
+impl Node {
+    fn new(m: Move) -> Node {
+        Node {
+            m,
+            visits: 0,
+            wins: 0.0,
+            untried: None,
+            children: Vec::new()
+        }
+    }
+
+    fn win_rate(&self) -> f64 {
+        if self.visits == 0 {
+            0.0
+        } else {
+            self.wins / f64::from(self.visits)
+        }
+    }
+
+    fn most_visited(&self) -> Option<&Node> {
+        self.children.iter().max_by_key(|child| child.visits)
+    }
+
+    /// Selects the child with the highest UCT value. All children must have been visited.
+    fn select(&mut self, exploration: f64) -> &mut Node {
+        let log_visits = f64::from(self.visits).ln();
+        let uct = |child: &Node| child.win_rate() + exploration * (log_visits / f64::from(child.visits)).sqrt();
+
+        let mut best = 0;
+        let mut best_value = f64::MIN;
+        for (i, child) in self.children.iter().enumerate() {
+            let value = uct(child);
+            if value > best_value {
+                best = i;
+                best_value = value;
+            }
+        }
+
+        &mut self.children[best]
+    }
+}
+
+/// A `Searcher` which uses Monte Carlo Tree Search.
+///
+/// The playout limit is taken from the node limit of `SearchLimits`, and the depth limit is
+/// ignored. The reported score is the win rate of the best move, scaled from -100 to 100.
+pub struct MonteCarloSearcher<P: Playout> {
+    policy: P,
+    exploration: f64,
+    print: bool,
+    root: Node,
+    root_board: Option<Board>
+}
+
+impl MonteCarloSearcher<RandomPlayout> {
+    /// Creates a searcher which plays out games with random moves.
+    pub fn random(print: bool) -> MonteCarloSearcher<RandomPlayout> {
+        MonteCarloSearcher::new(RandomPlayout, print)
+    }
+}
+
+impl<P: Playout> MonteCarloSearcher<P> {
+    pub fn new(policy: P, print: bool) -> MonteCarloSearcher<P> {
+        MonteCarloSearcher {
+            policy,
+            exploration: DEFAULT_EXPLORATION,
+            print,
+            root: Node::new(Move::Pass),
+            root_board: None
+        }
+    }
+
+    /// Sets the exploration constant used when selecting nodes with UCT.
+    pub fn set_exploration(&mut self, exploration: f64) {
+        self.exploration = exploration;
+    }
+
+    /// Returns the statistics for each move at the root, as of the last search.
+    /// # Returns:
+    /// * The `MoveStats` for each root move that has been played out, most visited first.
+    pub fn root_stats(&self) -> Vec<MoveStats> {
+        let mut stats: Vec<MoveStats> = self.root.children.iter().map(|child| {
+            MoveStats { m: child.m, visits: child.visits, win_rate: child.win_rate() }
+        }).collect();
+        stats.sort_by_key(|s| std::cmp::Reverse(s.visits));
+        stats
+    }
+
+    /// Moves the root of the tree to the given board. If the board is reached within two moves
+    /// of the current root, that subtree is kept, otherwise the tree is discarded.
+    fn set_root(&mut self, board: &Board) {
+        let same = |a: &Board, b: &Board| {
+            a.black_disks == b.black_disks && a.white_disks == b.white_disks && a.black_move == b.black_move
+        };
+
+        let mut new_root = None;
+        if let Some(root_board) = self.root_board.as_mut() {
+            if same(root_board, board) {
+                return;
+            }
+
+            let root = std::mem::replace(&mut self.root, Node::new(Move::Pass));
+            'find: for child in root.children {
+                let undo = root_board.make_move(child.m);
+                if same(root_board, board) {
+                    new_root = Some(child);
+                    break;
+                }
+
+                for grandchild in child.children {
+                    let grand_undo = root_board.make_move(grandchild.m);
+                    let found = same(root_board, board);
+                    root_board.undo_move(grand_undo, grandchild.m);
+
+                    if found {
+                        new_root = Some(grandchild);
+                        break 'find;
+                    }
+                }
+                root_board.undo_move(undo, child.m);
+            }
+        }
+
+        self.root = new_root.unwrap_or_else(|| Node::new(Move::Pass));
+        self.root_board = Some(board.clone());
+    }
+
+    /// Runs one iteration of the search: selects a leaf with UCT, expands it, plays a game out
+    /// from it, and updates every node on the way back up.
+    /// # Returns:
+    /// * The result of the playout for the player to move at `board`.
+    fn iterate(&self, node: &mut Node, board: &CompactBoard, rng: &mut ThreadRng) -> f64 {
+        let result = if board.is_game_over() {
+            game_result(board)
+        } else {
+            let untried = node.untried.get_or_insert_with(|| {
+                let mut moves: Vec<Move> = board.get_moves().into_iter().collect();
+                moves.shuffle(rng);
+                moves
+            });
+
+            if let Some(m) = untried.pop() {
+                let mut child = Node::new(m);
+                let child_result = self.playout(&board.play(m), rng);
+                child.visits = 1;
+                child.wins = 1.0 - child_result;
+
+                node.children.push(child);
+                1.0 - child_result
+            } else {
+                let child = node.select(self.exploration);
+                let child_result = self.iterate(child, &board.play(child.m), rng);
+
+                1.0 - child_result
+            }
+        };
+
+        node.visits += 1;
+        node.wins += 1.0 - result;
+
+        result
+    }
+
+    /// Plays a game out to the end using the playout policy.
+    /// # Returns:
+    /// * The result for the player to move at `board`.
+    fn playout(&self, board: &CompactBoard, rng: &mut ThreadRng) -> f64 {
+        let mut game = *board;
+        let mut plies = 0;
+
+        while !game.is_game_over() {
+            let moves = game.get_moves();
+            let m = self.policy.choose_move(&game, &moves, rng);
+            game = game.play(m);
+            plies += 1;
+        }
+
+        if plies % 2 == 0 {
+            game_result(&game)
+        } else {
+            1.0 - game_result(&game)
+        }
+    }
+}
+
+/// Returns the result of a finished game for the player to move: 1 for a win, 0.5 for a draw and
+/// 0 for a loss.
+fn game_result(board: &CompactBoard) -> f64 {
+    match board.disk_difference().signum() {
+        1 => 1.0,
+        0 => 0.5,
+        _ => 0.0
+    }
+}
+
+impl<P: Playout> Searcher for MonteCarloSearcher<P> {
+    fn search(&mut self, board: &mut Board, limits: SearchLimits) -> SearchResult {
+        let start_time = Instant::now();
+        let mut rng = thread_rng();
+
+        self.set_root(board);
+
+        let playouts = match limits {
+            SearchLimits { nodes: None, time: None, .. } => DEFAULT_PLAYOUTS,
+            SearchLimits { nodes, .. } => nodes.unwrap_or(u64::MAX)
+        };
+
+        let position = CompactBoard::from(&*board);
+        let mut root = std::mem::replace(&mut self.root, Node::new(Move::Pass));
+        let mut total_playouts = 0;
+        while total_playouts < playouts && !position.is_game_over() {
+            self.iterate(&mut root, &position, &mut rng);
+            total_playouts += 1;
+
+            if let Some(time) = limits.time {
+                if total_playouts % TIME_CHECK_INTERVAL == 0 && start_time.elapsed().as_millis() >= u128::from(time) {
+                    break;
+                }
+            }
+        }
+        self.root = root;
+
+        let mut pv = Vec::new();
+        let mut node = &self.root;
+        while let Some(child) = node.most_visited() {
+            pv.push(child.m);
+            node = child;
+        }
+
+        let (best_move, win_rate) = match self.root.most_visited() {
+            Some(child) => (child.m, child.win_rate()),
+            None => (Move::Pass, 0.5)
+        };
+
+        let duration = start_time.elapsed();
+        let time_taken = duration.as_secs() as u32 * 1000 + duration.subsec_millis();
+
+        if self.print {
+            println!("[MCTS] Ran {} playouts in {} ms.", total_playouts, time_taken);
+            for stats in self.root_stats() {
+                println!("\t{}: {} visits, {:.1}% wins", stats.m, stats.visits, stats.win_rate * 100.0);
+            }
+            println!("PV: {}", format_pv(&pv));
+        }
+
+        SearchResult {
+            score: ((win_rate - 0.5) * 200.0).round() as i32,
+            best_move,
+            data: SearchData { nodes: total_playouts, time: time_taken, depth: pv.len() as u8 },
+            pv
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+
+    use crate::board::Board;
+    use crate::search::{ SearchLimits, Searcher, endgame::endgame_solve, eval::PieceSquareEvaluator };
+    use super::{ EvalPlayout, MonteCarloSearcher };
+
+    #[test]
+    fn test_mcts_reuses_tree() {
+        let mut board = Board::new();
+        let mut searcher = MonteCarloSearcher::random(false);
+
+        let result = searcher.search(&mut board, SearchLimits::new().with_nodes(2000));
+        assert_eq!(result.data.nodes, 2000);
+        assert!(board.get_moves().contains(result.best_move));
+
+        let stats = searcher.root_stats();
+        assert_eq!(stats.len(), 4);
+        assert_eq!(stats.iter().map(|s| s.visits).sum::<u32>(), 2000);
+        assert!(stats.iter().any(|s| s.m == result.best_move && s.visits == stats[0].visits));
+
+        // After playing the best move and a reply, the subtree below them should be kept.
+        board.make_move(result.pv[0]);
+        board.make_move(result.pv[1]);
+        let kept = searcher.search(&mut board, SearchLimits::new().with_nodes(1)).data.nodes;
+        assert_eq!(kept, 1);
+        assert!(searcher.root_stats().iter().map(|s| s.visits).sum::<u32>() > 1);
+    }
+
+    #[test]
+    fn test_mcts_finds_win() {
+        let mut rng = rand::thread_rng();
+        let mut searcher = MonteCarloSearcher::new(EvalPlayout::new(PieceSquareEvaluator::new()), false);
+
+        // Near the end of the game the whole tree is explored, so UCT should find a winning move
+        // whenever there is one.
+        for _ in 0..10 {
+            let mut board = Board::new();
+            while board.all_disks().count_zeros() > 5 && !board.is_game_over() {
+                let moves = board.get_moves();
+                board.make_move(moves[rng.gen_range(0, moves.len())]);
+            }
+            if board.is_game_over() || endgame_solve(&mut board, true, false).score != 1 {
+                continue;
+            }
+
+            let result = searcher.search(&mut board, SearchLimits::new().with_nodes(2000));
+            board.make_move(result.best_move);
+            assert_eq!(endgame_solve(&mut board, true, false).score, -1);
+        }
+    }
+}
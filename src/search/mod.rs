@@ -9,13 +9,85 @@ pub mod negamax;
 pub mod bns;
 pub mod nm_new;
 pub mod iterative;
+pub mod mcts;
+pub mod probcut;
 pub mod hashtable;
 
 #[cfg(test)]
 mod ffo_test;
 
+use crate::board::{ Board, Move };
+
+pub use self::iterative::iter_deep;
+
+#[derive(Clone, Copy, Debug, Default)]
 pub struct SearchData {
     pub nodes: u64,
     pub time: u32,
     pub depth: u8
 }
+
+/// The limits placed on a search. Any combination of limits may be set, and the search stops once
+/// any of them is reached. Time and node limits are checked between iterations of iterative
+/// deepening, using the previous iteration to predict whether the next one will fit. If no limits
+/// are set, searchers use their own default depth.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// The maximum depth to search to.
+    pub depth: Option<u8>,
+    /// The time to spend searching, in millis.
+    pub time: Option<u32>,
+    /// The maximum number of nodes to search.
+    pub nodes: Option<u64>
+}
+
+impl SearchLimits {
+    pub fn new() -> SearchLimits {
+        SearchLimits::default()
+    }
+
+    pub fn with_depth(mut self, depth: u8) -> SearchLimits {
+        self.depth = Some(depth);
+        self
+    }
+
+    pub fn with_time(mut self, time: u32) -> SearchLimits {
+        self.time = Some(time);
+        self
+    }
+
+    pub fn with_nodes(mut self, nodes: u64) -> SearchLimits {
+        self.nodes = Some(nodes);
+        self
+    }
+}
+
+/// The result of a search, with the score relative to the player to move.
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub score: i32,
+    pub best_move: Move,
+    /// The principal variation, starting with the best move.
+    pub pv: Vec<Move>,
+    pub data: SearchData
+}
+
+/// Formats a principal variation as a space-separated list of moves.
+/// # Arguments:
+/// * `pv`: The moves to format.
+/// # Returns:
+/// * A `String` containing the moves, e.g. `"f5 d6 c3"`.
+pub fn format_pv(pv: &[Move]) -> String {
+    pv.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+/// A common interface over all of the search algorithms, so that they can be swapped out freely.
+pub trait Searcher {
+    /// Searches the given position within the given limits. The board is left unchanged.
+    /// # Arguments:
+    /// * `board`: Board to search.
+    /// * `limits`: The limits placed on the search.
+    /// # Returns:
+    /// * The result of the search.
+    fn search(&mut self, board: &mut Board, limits: SearchLimits) -> SearchResult;
+}
@@ -18,12 +18,11 @@
 //! `beta`], so alpha-cutoffs will return alpha, while beta-cutoffs will return beta.
 
 use std::collections::HashMap;
-use std::i32;
 use std::io::{ self, Write };
 use std::time::Instant;
 
-use crate::board::{ Board, Move };
-use crate::search::{ SearchData, eval::Evaluator };
+use crate::board::{ Board, Move, compact::CompactBoard };
+use crate::search::{ SearchData, SearchLimits, SearchResult, Searcher, format_pv, iter_deep, eval::Evaluator };
 
 const MIN_SEARCH_DEPTH: u8 = 8;
 
@@ -35,18 +34,23 @@ const MIN_SEARCH_DEPTH: u8 = 8;
 /// * `depth`: Depth to search to.
 /// * `evaluator`: Evaluator to use for position evaluation at a leaf.
 /// # Returns:
-/// * A tuple containing the score of the best move and the best move.
-pub fn negamax<T: Evaluator>(board: &mut Board, depth: u8, evaluator: &T, print: bool) -> (i32, Move, SearchData) {
+/// * A `SearchResult` containing the score of the best move, the best move and the principal
+///   variation.
+pub fn negamax<T: Evaluator>(board: &mut Board, depth: u8, evaluator: &T, print: bool) -> SearchResult {
+    let board = CompactBoard::from(&*board);
     let mut moves = board.get_moves();
-    moves.sort_by(|&m| -evaluator.move_order_score(board, m));
+    moves.sort_by(|&m| -evaluator.move_order_score(&board, m));
 
     let beta = i32::MAX;
     let mut best_score = -beta;
     let mut best_move = moves[0];
+    let mut best_pv = vec![best_move];
 
     let mut total_nodes = 0;
     let mut total_millis = 0;
 
+    let mut line = Vec::new();
+
     for m in &moves {
         if print {
             print!("Evaluating: {}", m);
@@ -55,9 +59,7 @@ pub fn negamax<T: Evaluator>(board: &mut Board, depth: u8, evaluator: &T, print:
 
         let start_time = Instant::now();
 
-        let undo = board.make_move(m);
-        let (mut result, nodes) = negamax_impl(board, -beta, -best_score, depth - 1, evaluator);
-        board.undo_move(undo, m);
+        let (mut result, nodes) = negamax_impl(&board.play(m), -beta, -best_score, depth - 1, evaluator, &mut line);
 
         result = -result;
 
@@ -71,6 +73,9 @@ pub fn negamax<T: Evaluator>(board: &mut Board, depth: u8, evaluator: &T, print:
             }
             best_move = m;
             best_score = result;
+            best_pv.clear();
+            best_pv.push(m);
+            best_pv.extend_from_slice(&line);
         } else if print {
             println!(" --             Nodes: {}, Time {} ms", nodes, time_taken);
         }
@@ -81,9 +86,15 @@ pub fn negamax<T: Evaluator>(board: &mut Board, depth: u8, evaluator: &T, print:
     
     if print {
         println!("Searched {} nodes in {} ms ({:.2} kn/s)", total_nodes, total_millis, total_nodes as f32 / total_millis as f32);
+        println!("PV: {}", format_pv(&best_pv));
     }
 
-    (best_score, best_move, SearchData { nodes: total_nodes, time: total_millis, depth })
+    SearchResult {
+        score: best_score,
+        best_move,
+        pv: best_pv,
+        data: SearchData { nodes: total_nodes, time: total_millis, depth }
+    }
 }
 
 /// A Negamax implementation which returns the best move for a curent position, along with score.
@@ -94,10 +105,12 @@ pub fn negamax<T: Evaluator>(board: &mut Board, depth: u8, evaluator: &T, print:
 /// * `depth`: Depth to search to.
 /// * `evaluator`: Evaluator to use for position evaluation at a leaf.
 /// # Returns:
-/// * A tuple containing the score of the best move and the best move.
-pub fn negamax_id<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T, print: bool) -> (i32, Move, SearchData) {
+/// * A `SearchResult` containing the score of the best move, the best move and the principal
+///   variation.
+pub fn negamax_id<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T, print: bool) -> SearchResult {
+    let board = CompactBoard::from(&*board);
     let mut moves = board.get_moves();
-    moves.sort_by(|&m| -evaluator.move_order_score(board, m));
+    moves.sort_by(|&m| -evaluator.move_order_score(&board, m));
 
     let mut scores: HashMap<Move, i32> = HashMap::new();
 
@@ -109,6 +122,9 @@ pub fn negamax_id<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T, pri
 
     let mut branching_factor;
 
+    let mut line = Vec::new();
+    let mut best_pv = vec![moves[0]];
+
     while time_prediction + total_millis < time {
         let beta = i32::MAX;
 
@@ -117,8 +133,6 @@ pub fn negamax_id<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T, pri
         let mut iter_nodes = 0;
         let mut iter_time = 0;
 
-        eprintln!("{:?}", moves);
-
         for m in &moves {
             if print {
                 print!("Evaluating: {}", m);
@@ -127,9 +141,7 @@ pub fn negamax_id<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T, pri
 
             let start_time = Instant::now();
 
-            let undo = board.make_move(m);
-            let (mut result, nodes) = negamax_impl(board, -beta, -best_score, depth - 1, evaluator);
-            board.undo_move(undo, m);
+            let (mut result, nodes) = negamax_impl(&board.play(m), -beta, -best_score, depth - 1, evaluator, &mut line);
 
             result = -result;
 
@@ -144,6 +156,9 @@ pub fn negamax_id<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T, pri
                     println!(" -- Score: {:3}, Nodes: {}, Time {} ms", result, nodes, time_taken);
                 }
                 best_score = result;
+                best_pv.clear();
+                best_pv.push(m);
+                best_pv.extend_from_slice(&line);
             } else if print {
                 println!(" --             Nodes: {}, Time {} ms", nodes, time_taken);
             }
@@ -161,8 +176,10 @@ pub fn negamax_id<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T, pri
         depth += 2;
         time_prediction = (iter_time as f32 * (branching_factor.powi(2) + 1.0)) as u32;
 
-        eprintln!("Took {} ms", iter_time);
-        eprintln!("Predicted next time is {} ms", time_prediction);
+        if print {
+            println!("Took {} ms", iter_time);
+            println!("Predicted next time is {} ms", time_prediction);
+        }
     }
 
     depth -= 2;
@@ -170,12 +187,21 @@ pub fn negamax_id<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T, pri
     if print {
         println!("Searched {} nodes in {} ms ({:.2} kn/s)", total_nodes, total_millis, total_nodes as f32 / total_millis as f32);
         println!("Final search was depth {}", depth);
+        println!("PV: {}", format_pv(&best_pv));
     }
 
-    eprintln!("{:?}", moves);
-    eprintln!("{:?}", scores);
-
-    (*scores.get(&moves[0]).unwrap(), moves[0], SearchData { nodes: total_nodes, time: total_millis, depth })
+    // If no iteration fit in the time budget, fall back to the statically best ordered move.
+    let (score, depth) = match scores.get(&best_pv[0]) {
+        Some(&score) => (score, depth),
+        None => (-evaluator.get_score(&board.play(best_pv[0])), 1)
+    };
+
+    SearchResult {
+        score,
+        best_move: best_pv[0],
+        pv: best_pv,
+        data: SearchData { nodes: total_nodes, time: total_millis, depth }
+    }
 }
 
 /// A Negamax implementation which returns the score of the best move in current position for the
@@ -187,20 +213,24 @@ pub fn negamax_id<T: Evaluator>(board: &mut Board, time: u32, evaluator: &T, pri
 /// * `alpha`: Alpha-cutoff.
 /// * `beta`: Beta-cutoff.
 /// * `evaluator`: Evaluator to use for position evaluation at a leaf.
+/// * `pv`: Filled with the principal variation if the score is strictly between `alpha` and
+///   `beta`, and left empty otherwise.
 /// # Returns:
 /// * A tuple containing the score of the best move and the number of nodes searched.
-pub fn negamax_impl<T: Evaluator>(board: &mut Board, mut alpha: i32, beta: i32, depth: u8, evaluator: &T) -> (i32, u64) {
+pub fn negamax_impl<T: Evaluator>(board: &CompactBoard, mut alpha: i32, beta: i32, depth: u8, evaluator: &T, pv: &mut Vec<Move>) -> (i32, u64) {
+    pv.clear();
+
     if board.is_game_over() || depth == 0 {
         return (evaluator.get_score(board), 1);
     }
 
+    let mut line = Vec::new();
+
     let mut moves = board.get_moves();
     if depth > 4 {
         moves.sort_by(|&m| {
             let half_depth = ((depth / 2) & !0x1) | (depth & 0x1);
-            let undo = board.make_move(m);
-            let (result, nodes) = negamax_impl(board, -beta, -alpha, half_depth, evaluator);
-            board.undo_move(undo, m);
+            let (result, _) = negamax_impl(&board.play(m), -beta, -alpha, half_depth, evaluator, &mut line);
     
             -result
         });
@@ -209,37 +239,103 @@ pub fn negamax_impl<T: Evaluator>(board: &mut Board, mut alpha: i32, beta: i32,
     let mut total_nodes = 1;
 
     for m in &moves {
-        let undo = board.make_move(m);
-        let (mut result, nodes) = negamax_impl(board, -beta, -alpha, depth - 1, evaluator);
-        board.undo_move(undo, m);
+        let (mut result, nodes) = negamax_impl(&board.play(m), -beta, -alpha, depth - 1, evaluator, &mut line);
 
         result = -result;
         total_nodes += nodes;
 
         if result >= beta {
+            pv.clear();
             return (beta, total_nodes);
         }
 
         if result > alpha {
             alpha = result;
+            pv.clear();
+            pv.push(m);
+            pv.extend_from_slice(&line);
         }
     }
 
     (alpha, total_nodes)
 }
 
+/// A `Searcher` which uses plain Negamax. Depth-limited searches use `negamax`, time-limited ones
+/// use `negamax_id`, and any other combination of limits uses `iter_deep`.
+pub struct Negamax<E: Evaluator> {
+    eval: E,
+    print: bool
+}
+
+impl<E: Evaluator> Negamax<E> {
+    pub fn new(eval: E, print: bool) -> Negamax<E> {
+        Negamax {
+            eval,
+            print
+        }
+    }
+}
+
+impl<E: Evaluator> Searcher for Negamax<E> {
+    fn search(&mut self, board: &mut Board, limits: SearchLimits) -> SearchResult {
+        let eval = &self.eval;
+        let print = self.print;
+
+        match limits {
+            SearchLimits { depth: Some(depth), time: None, nodes: None } => {
+                negamax(board, depth.max(1), eval, print)
+            }
+            SearchLimits { depth: None, time: Some(time), nodes: None } => {
+                negamax_id(board, time, eval, print)
+            }
+            _ => iter_deep(board, limits, |b, depth| negamax(b, depth, eval, print))
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::board::{ Board, Move };
-    use crate::search::{ negamax, eval::PieceSquareEvaluator };
+    use crate::search::{ negamax, SearchLimits, Searcher, eval::PieceSquareEvaluator };
 
     #[test]
     fn test_negamax() {
         let mut board = Board::from_pos(0x000040BC00000000, 0x0000004000000000, false);
         let eval = PieceSquareEvaluator::from([1; 10]);
 
-        let (_score, m, _) = negamax::negamax(&mut board, 2, &eval, true);
+        let result = negamax::negamax(&mut board, 2, &eval, true);
+
+        assert_eq!(result.best_move, Move::Play(9));
+        assert_eq!(result.pv.len(), 2);
+        assert_eq!(result.pv[0], Move::Play(9));
+    }
+
+    #[test]
+    fn test_negamax_searcher() {
+        let mut board = Board::from_pos(0x000040BC00000000, 0x0000004000000000, false);
+        let eval = PieceSquareEvaluator::from([1; 10]);
+
+        let mut searcher = negamax::Negamax::new(&eval, false);
+        let result = searcher.search(&mut board, SearchLimits::new().with_depth(2));
+
+        assert_eq!(result.best_move, Move::Play(9));
+        assert_eq!(result.pv[0], Move::Play(9));
+        assert_eq!(result.data.depth, 2);
+
+        // A node limit should still complete at least one iteration.
+        let result = searcher.search(&mut board, SearchLimits::new().with_depth(4).with_nodes(1));
+        assert_eq!(result.data.depth, 4);
+    }
+
+    #[test]
+    fn test_negamax_id_no_time() {
+        let mut board = Board::from_pos(0x000040BC00000000, 0x0000004000000000, false);
+        let eval = PieceSquareEvaluator::from([1; 10]);
+
+        // With no time for a full iteration, the first ordered move should be returned.
+        let result = negamax::negamax_id(&mut board, 0, &eval, false);
 
-        assert_eq!(m, Move::Play(9));
+        assert_eq!(result.pv, vec![result.best_move]);
+        assert_eq!(result.data.depth, 1);
     }
 }
@@ -1,56 +1,45 @@
-use crate::board::{ Board, Move };
-use crate::search::{ SearchData, eval::{ Evaluator, PieceSquareEvaluator }, hashtable:: { Score, HashTable } };
+use crate::board::{ Board, Move, compact::CompactBoard };
+use crate::search::{ SearchData, SearchLimits, SearchResult, Searcher, format_pv, iter_deep };
+use crate::search::{ eval::{ Evaluator, PieceSquareEvaluator }, hashtable:: { Score, HashTable }, probcut::ProbCutParams };
 
+use std::cell::Cell;
 use std::collections::HashMap;
-use std::i32;
 use std::io::{ Write, stdout };
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread;
 use std::time::Instant;
 
 const MIN_SEARCH_DEPTH: u8 = 8;
 
 // Hashtable Params
-const DEFAULT_TABLE_SIZE: usize = 8276803;
-
-// Probcut Params
-const PROBCUT_DEPTH: u8 = 7;
-const PROBCUT_SHALLOW: u8 = 1;
-const PROBCUT_SIGMA: f32 = 642.0;
-const PROBCUT_THRESHOLD: f32 = 1.645;
-const PROBCUT_BIAS_MAX: f32 = PROBCUT_SIGMA * PROBCUT_THRESHOLD;
-const PROBCUT_A: f32 = 1.12749;
-const PROBCUT_B: f32 = -3.6805;
+const DEFAULT_TABLE_MB: usize = 64;
 
+/// A principal variation searcher with a transposition table. It can optionally run in parallel
+/// using Lazy SMP, where helper threads search the same position and share their results only
+/// through the transposition table.
 pub struct NegamaxSearcher<E: Evaluator> {
-    eval: E,
+    eval: Arc<E>,
     verbose: u8,
     output: Box<dyn Write>,
-    hashtable: HashTable,
-    cut_attempt: usize,
-    cut_success: usize
+    hashtable: Arc<HashTable>,
+    threads: usize,
+    probcut: Option<Arc<ProbCutParams>>
 }
 
-impl<E: Evaluator> NegamaxSearcher<E> {
+impl<E: Evaluator + Send + Sync> NegamaxSearcher<E> {
     pub fn new() -> NegamaxSearcher<PieceSquareEvaluator> {
-        let eval = PieceSquareEvaluator::new();
-
-        NegamaxSearcher {
-            eval: eval,
-            verbose: 1,
-            output: Box::new(stdout()),
-            hashtable: HashTable::empty(DEFAULT_TABLE_SIZE),
-            cut_attempt: 0,
-            cut_success: 0
-        }
+        NegamaxSearcher::with_eval(PieceSquareEvaluator::new())
     }
 
     pub fn with_eval(eval: E) -> Self {
         NegamaxSearcher {
-            eval: eval,
+            eval: Arc::new(eval),
             verbose: 1,
             output: Box::new(stdout()),
-            hashtable: HashTable::empty(DEFAULT_TABLE_SIZE),
-            cut_attempt: 0,
-            cut_success: 0
+            hashtable: Arc::new(HashTable::with_size_mb(DEFAULT_TABLE_MB)),
+            threads: 1,
+            probcut: None
         }
     }
 
@@ -62,12 +51,87 @@ impl<E: Evaluator> NegamaxSearcher<E> {
         self.output = output;
     }
 
-    pub fn search(&mut self, board: &mut Board, time: u32) -> (i32, Move, SearchData) {
-        self.cut_attempt = 0;
-        self.cut_success = 0;
+    /// Replaces the transposition table with an empty one of the given size.
+    /// # Arguments:
+    /// * `mb`: The size of the new table in megabytes.
+    pub fn set_table_size(&mut self, mb: usize) {
+        self.hashtable = Arc::new(HashTable::with_size_mb(mb));
+    }
+
+    /// Empties the transposition table, so that the next search does not use the results of
+    /// earlier ones.
+    pub fn clear_table(&mut self) {
+        Arc::get_mut(&mut self.hashtable).expect("The table is in use by a search.").clear();
+    }
 
-        let mut moves = board.get_moves();
-        moves.sort_by(|&m| -self.eval.move_order_score(board, m));
+    /// Sets the number of threads used to search. With more than one thread, the extra threads
+    /// run as Lazy SMP helpers, which fill the shared transposition table for the main thread.
+    /// # Arguments:
+    /// * `threads`: The total number of threads, including the main search thread.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Enables or disables selective search with Multi-ProbCut.
+    /// # Arguments:
+    /// * `probcut`: The fitted parameters to prune with, or `None` to search the full tree.
+    pub fn set_probcut(&mut self, probcut: Option<ProbCutParams>) {
+        self.probcut = probcut.map(Arc::new);
+    }
+
+    /// Creates a worker which searches using this searcher's evaluator and table.
+    fn worker(&self, stop: Option<Arc<AtomicBool>>) -> Worker<E> {
+        Worker {
+            eval: Arc::clone(&self.eval),
+            hashtable: Arc::clone(&self.hashtable),
+            probcut: self.probcut.clone(),
+            stop,
+            cut_attempt: Cell::new(0),
+            cut_success: Cell::new(0)
+        }
+    }
+
+    /// Runs the given search on the current thread, while the helper threads search the same
+    /// position until it finishes. The nodes searched by the helpers are added to the result.
+    fn with_helpers<F>(&mut self, board: &mut Board, search: F) -> SearchResult
+        where F: FnOnce(&mut Self, &mut Board) -> SearchResult
+    {
+        self.hashtable.new_search();
+
+        if self.threads == 1 {
+            return search(self, board);
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let root = CompactBoard::from(&*board);
+
+        thread::scope(|scope| {
+            let helpers: Vec<_> = (1..self.threads).map(|id| {
+                let worker = self.worker(Some(Arc::clone(&stop)));
+                scope.spawn(move || worker.helper_search(&root, id))
+            }).collect();
+
+            let mut result = search(self, board);
+
+            stop.store(true, Ordering::Relaxed);
+            for helper in helpers {
+                result.data.nodes += helper.join().expect("Helper search thread panicked.");
+            }
+
+            result
+        })
+    }
+
+    pub fn search_to_time(&mut self, board: &mut Board, time: u32) -> SearchResult {
+        self.with_helpers(board, |searcher, board| searcher.search_to_time_impl(board, time))
+    }
+
+    fn search_to_time_impl(&mut self, board: &mut Board, time: u32) -> SearchResult {
+        let worker = self.worker(None);
+        let root = CompactBoard::from(&*board);
+
+        let mut moves = root.get_moves();
+        moves.sort_by(|&m| -self.eval.move_order_score(&root, m));
 
         let mut scores: HashMap<Move, i32> = HashMap::new();
 
@@ -81,6 +145,9 @@ impl<E: Evaluator> NegamaxSearcher<E> {
 
         let mut best_move = moves[0];
         let mut best_move_score = 0;
+        let mut best_pv = vec![best_move];
+
+        let mut line = Vec::new();
 
         while time_prediction + total_millis < time {
             let beta = i32::MAX;
@@ -103,25 +170,24 @@ impl<E: Evaluator> NegamaxSearcher<E> {
                 let mut score;
                 let mut nodes = 0;
 
-                let undo = board.make_move(m);
+                let child = root.play(m);
                 if first {
-                    let (result, s_nodes) = self.pvs_impl(board, -beta, -best_score, depth - 1);
+                    let (result, s_nodes) = worker.pvs_impl(&child, -beta, -best_score, depth - 1, &mut line);
     
                     score = -result;
                     nodes += s_nodes;
                 } else {
-                    let (result, s_nodes) = self.pvs_impl(board, -best_score - 1, -best_score, depth - 1);
+                    let (result, s_nodes) = worker.pvs_impl(&child, -best_score - 1, -best_score, depth - 1, &mut line);
     
                     score = -result;
                     nodes += s_nodes;
     
                     if score > best_score && score < beta {
-                        let (result, s_nodes) = self.pvs_impl(board, -beta, -score, depth - 1);
+                        let (result, s_nodes) = worker.pvs_impl(&child, -beta, -best_score, depth - 1, &mut line);
                         score = -result;
                         nodes += s_nodes;
                     }
                 }
-                board.undo_move(undo, m);
 
                 scores.insert(m, score);
 
@@ -132,6 +198,9 @@ impl<E: Evaluator> NegamaxSearcher<E> {
                 if score > best_score {
                     best_move = m;
                     best_move_score = score;
+                    best_pv.clear();
+                    best_pv.push(m);
+                    best_pv.extend_from_slice(&line);
                     if self.verbose > 0 {
                         writeln!(
                             self.output,
@@ -160,10 +229,8 @@ impl<E: Evaluator> NegamaxSearcher<E> {
             }
 
             moves.sort_by(|&m| {
-                let undo = board.make_move(m);
-
-                let entry = self.hashtable.probe(board, depth, -i32::MAX, i32::MAX);
-                let value = if let Some(score) = entry {
+                let (entry, _) = self.hashtable.probe(root.play(m).hash_key(), depth, -i32::MAX, i32::MAX);
+                if let Some(score) = entry {
                     match score {
                         Score::Exact(score) => score,
                         Score::Lower(score) => score,
@@ -171,11 +238,7 @@ impl<E: Evaluator> NegamaxSearcher<E> {
                     }
                 } else {
                     i32::MAX
-                };
-
-                board.undo_move(undo, m);
-        
-                value
+                }
             });
             
             branching_factor = (iter_nodes as f32).powf(1.0 / depth as f32);
@@ -198,22 +261,32 @@ impl<E: Evaluator> NegamaxSearcher<E> {
                 total_nodes as f32 / total_millis as f32
             ).expect("Unable to write to output stream.");
             
-            let pc_frac = self.cut_success as f32 / self.cut_attempt as f32;
-
             writeln!(self.output, "Final search was depth {}", depth).expect("Unable to write to output stream.");
-            writeln!(self.output, "ProbCut pruned {:.1}% of the time", pc_frac * 100.0).expect("Unable to write to output stream.");
+            if worker.cut_attempt.get() > 0 {
+                let pc_frac = worker.cut_success.get() as f32 / worker.cut_attempt.get() as f32;
+                writeln!(self.output, "ProbCut pruned {:.1}% of the time", pc_frac * 100.0).expect("Unable to write to output stream.");
+            }
+            writeln!(self.output, "PV: {}", format_pv(&best_pv)).expect("Unable to write to output stream.");
         }
 
-        self.hashtable.set_replace();
-
-        self.hashtable.clear(); // TODO: Remove once better time management is implemented.
+        SearchResult {
+            score: best_move_score,
+            best_move,
+            pv: best_pv,
+            data: SearchData { nodes: total_nodes, time: total_millis, depth }
+        }
+    }
 
-        (best_move_score, best_move, SearchData { nodes: total_nodes, time: total_millis, depth })
+    pub fn search_to_depth(&mut self, board: &mut Board, depth: u8) -> SearchResult {
+        self.with_helpers(board, |searcher, board| searcher.search_to_depth_impl(board, depth))
     }
 
-    pub fn search_to_depth(&mut self, board: &mut Board, depth: u8) -> (i32, Move, SearchData) {
-        let mut moves = board.get_moves();
-        moves.sort_by(|&m| -self.eval.move_order_score(board, m));
+    fn search_to_depth_impl(&mut self, board: &mut Board, depth: u8) -> SearchResult {
+        let worker = self.worker(None);
+        let root = CompactBoard::from(&*board);
+
+        let mut moves = root.get_moves();
+        moves.sort_by(|&m| -self.eval.move_order_score(&root, m));
 
         let mut total_nodes = 0;
         let mut total_millis = 0;
@@ -222,6 +295,9 @@ impl<E: Evaluator> NegamaxSearcher<E> {
         
         let mut best_move = moves[0];
         let mut best_score = -beta;
+        let mut best_pv = vec![best_move];
+
+        let mut line = Vec::new();
 
         let mut first = true;
 
@@ -236,25 +312,24 @@ impl<E: Evaluator> NegamaxSearcher<E> {
             let mut score;
             let mut nodes = 0;
 
-            let undo = board.make_move(m);
+            let child = root.play(m);
             if first {
-                let (result, s_nodes) = self.pvs_impl(board, -beta, -best_score, depth - 1);
+                let (result, s_nodes) = worker.pvs_impl(&child, -beta, -best_score, depth - 1, &mut line);
 
                 score = -result;
                 nodes += s_nodes;
             } else {
-                let (result, s_nodes) = self.pvs_impl(board, -best_score - 1, -best_score, depth - 1);
+                let (result, s_nodes) = worker.pvs_impl(&child, -best_score - 1, -best_score, depth - 1, &mut line);
 
                 score = -result;
                 nodes += s_nodes;
 
                 if score > best_score && score < beta {
-                    let (result, s_nodes) = self.pvs_impl(board, -beta, -score, depth - 1);
+                    let (result, s_nodes) = worker.pvs_impl(&child, -beta, -best_score, depth - 1, &mut line);
                     score = -result;
                     nodes += s_nodes;
                 }
             }
-            board.undo_move(undo, m);
 
             let end_time = Instant::now();
             let duration = end_time - start_time;
@@ -263,6 +338,9 @@ impl<E: Evaluator> NegamaxSearcher<E> {
             if score > best_score {
                 best_move = m;
                 best_score = score;
+                best_pv.clear();
+                best_pv.push(m);
+                best_pv.extend_from_slice(&line);
                 if self.verbose > 0 {
                     writeln!(
                         self.output,
@@ -296,33 +374,137 @@ impl<E: Evaluator> NegamaxSearcher<E> {
                 total_nodes as f32 / total_millis as f32
             ).expect("Unable to write to output stream.");
             writeln!(self.output, "Final search was depth {}", depth).expect("Unable to write to output stream.");
+            writeln!(self.output, "PV: {}", format_pv(&best_pv)).expect("Unable to write to output stream.");
+        }
+
+        SearchResult {
+            score: best_score,
+            best_move,
+            pv: best_pv,
+            data: SearchData { nodes: total_nodes, time: total_millis, depth }
         }
+    }
+
+}
+
+/// The state needed by a single thread to search. Each thread has its own worker, but they all
+/// share the evaluator and transposition table of the searcher which created them.
+struct Worker<E: Evaluator> {
+    eval: Arc<E>,
+    hashtable: Arc<HashTable>,
+    probcut: Option<Arc<ProbCutParams>>,
+    /// Set when a helper thread should stop. The main thread's worker has none.
+    stop: Option<Arc<AtomicBool>>,
+    cut_attempt: Cell<usize>,
+    cut_success: Cell<usize>
+}
 
-        self.hashtable.set_replace();
+impl<E: Evaluator> Worker<E> {
+    fn stopped(&self) -> bool {
+        self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
 
-        self.hashtable.clear(); // TODO: Remove once better time management is implemented.
+    /// Searches the position with iterative deepening until told to stop. Odd-numbered helpers
+    /// start one ply deeper, so that the helpers are spread across different depths.
+    /// # Returns:
+    /// * The number of nodes searched.
+    fn helper_search(&self, board: &CompactBoard, id: usize) -> u64 {
+        let empties = board.empties() as u8;
+        let mut total_nodes = 0;
+        let mut line = Vec::new();
 
-        (best_score, best_move, SearchData { nodes: total_nodes, time: total_millis, depth })
+        let mut depth = 1 + (id % 2) as u8;
+        while depth <= empties && !self.stopped() {
+            total_nodes += self.pvs_impl(board, -i32::MAX, i32::MAX, depth, &mut line).1;
+            depth += 1;
+        }
+
+        total_nodes
     }
 
-    fn pvs_impl(&mut self, board: &mut Board, mut alpha: i32, mut beta: i32, depth: u8) -> (i32, u64) {
+    /// Follows the best moves stored in the transposition table from the given position, appending
+    /// them to `pv`. Used to fill in the rest of a line cut short by a table hit.
+    fn extend_pv(&self, board: &CompactBoard, pv: &mut Vec<Move>, depth: u8) {
+        let mut board = *board;
+
+        for _ in 0..depth {
+            match self.hashtable.probe(board.hash_key(), 0, 0, 0).1 {
+                Some(m) if board.get_moves().contains(m) => {
+                    pv.push(m);
+                    board = board.play(m);
+                }
+                _ => break
+            }
+        }
+    }
+
+    /// Tries to prune the node with Multi-ProbCut. For each depth pair fitted for this depth and
+    /// stage, a null-window shallow search checks whether the deep score is likely to be outside
+    /// the window.
+    /// # Returns:
+    /// * The bound to return if the node was cut, and the nodes searched in either case.
+    fn probcut(&self, board: &CompactBoard, alpha: i32, beta: i32, depth: u8, line: &mut Vec<Move>) -> (Option<i32>, u64) {
+        let mut total_nodes = 0;
+
+        // Scores past a wipeout cannot be predicted.
+        let params = match &self.probcut {
+            Some(params) if alpha > -i32::MAX && beta < i32::MAX => params,
+            _ => return (None, 0)
+        };
+
+        for (shallow, fit) in params.cuts(depth, board.all_disks().count_ones()) {
+            self.cut_attempt.set(self.cut_attempt.get() + 1);
+            let margin = params.threshold * fit.sigma;
+
+            let beta_bound = ((beta as f32 + margin - fit.b) / fit.a).ceil() as i32;
+            let beta_bound = beta_bound.clamp(-i32::MAX + 1, i32::MAX - 1);
+            let (score, nodes) = self.pvs_impl(board, beta_bound - 1, beta_bound, shallow, line);
+            total_nodes += nodes;
+            if score >= beta_bound {
+                self.cut_success.set(self.cut_success.get() + 1);
+                return (Some(beta), total_nodes);
+            }
+
+            let alpha_bound = ((alpha as f32 - margin - fit.b) / fit.a).floor() as i32;
+            let alpha_bound = alpha_bound.clamp(-i32::MAX + 1, i32::MAX - 1);
+            let (score, nodes) = self.pvs_impl(board, alpha_bound, alpha_bound + 1, shallow, line);
+            total_nodes += nodes;
+            if score <= alpha_bound {
+                self.cut_success.set(self.cut_success.get() + 1);
+                return (Some(alpha), total_nodes);
+            }
+        }
+
+        (None, total_nodes)
+    }
+
+    fn pvs_impl(&self, board: &CompactBoard, mut alpha: i32, mut beta: i32, depth: u8, pv: &mut Vec<Move>) -> (i32, u64) {
+        pv.clear();
+
+        if self.stopped() {
+            return (alpha, 0);
+        }
+
         if board.is_game_over() || depth == 0 {
-            if board.black_move && board.black_disks.count_ones() == 0 {
-                return (-i32::MAX, 1);
-            } else if !board.black_move && board.white_disks.count_ones() == 0 {
+            if board.player == 0 {
                 return (-i32::MAX, 1);
             }
 
             return (self.eval.get_score(board), 1);
         }
 
+        let mut hash_move = None;
         if depth > 3 {
-            let entry = self.hashtable.probe(board, depth, alpha, beta);
+            let (entry, entry_move) = self.hashtable.probe(board.hash_key(), depth, alpha, beta);
+            hash_move = entry_move;
             if let Some(score) = entry {
                 let node_value;
 
                 match score {
-                    Score::Exact(score) => return (score, 1),
+                    Score::Exact(score) => {
+                        self.extend_pv(board, pv, depth);
+                        return (score, 1);
+                    },
                     Score::Lower(score) => {
                         alpha = score;
                         node_value = score;
@@ -340,14 +522,22 @@ impl<E: Evaluator> NegamaxSearcher<E> {
         }
         
         let mut total_nodes = 1;
+        let mut line = Vec::new();
+
+        let (cut, nodes) = self.probcut(board, alpha, beta, depth, &mut line);
+        total_nodes += nodes;
+        if let Some(score) = cut {
+            return (score, total_nodes);
+        }
 
         let mut moves = board.get_moves();
-        if depth > 3 {
+        if let Some(hash_move) = hash_move {
+            // The stored move is usually best, so try it first and skip the shallow search.
+            moves.sort_by(|&m| if m == hash_move { i32::MIN } else { -self.eval.move_order_score(board, m) });
+        } else if depth > 3 {
             moves.sort_by(|&m| {
                 let half_depth = ((depth / 2) & !0x1) | (depth & 0x1);
-                let undo = board.make_move(m);
-                let (result, nodes) = self.pvs_impl(board, -beta, -alpha, half_depth);
-                board.undo_move(undo, m);
+                let (result, nodes) = self.pvs_impl(&board.play(m), -beta, -alpha, half_depth, &mut line);
 
                 total_nodes += nodes;
         
@@ -355,60 +545,48 @@ impl<E: Evaluator> NegamaxSearcher<E> {
             });
         }
 
-        // // ProbCut
-        // if depth == PROBCUT_DEPTH {
-        //     self.cut_attempt += 1;
-
-        //     let beta_bound = ((PROBCUT_BIAS_MAX + (beta as f32 - PROBCUT_B)) / PROBCUT_A) as i32;
-        //     let alpha_bound = ((-PROBCUT_BIAS_MAX + (alpha as f32 - PROBCUT_B)) / PROBCUT_A) as i32;
-
-        //     let (beta_score, beta_nodes) = self.pvs_impl(board, beta_bound - 1, beta_bound, PROBCUT_SHALLOW);
-        //     total_nodes += beta_nodes;
-        //     if beta_score >= beta_bound {
-        //         self.cut_success += 1;
-        //         return (beta, total_nodes);
-        //     }
-
-        //     let (alpha_score, alpha_nodes) = self.pvs_impl(board, alpha_bound, alpha_bound + 1, PROBCUT_SHALLOW);
-        //     total_nodes += alpha_nodes;
-        //     if alpha_score <= alpha_bound {
-        //         self.cut_success += 1;
-        //         return (alpha, total_nodes);
-        //     }
-        // }
-
         let alpha_original = alpha;
         let mut best_score = -i32::MAX;
+        let mut best_move = moves[0];
 
         let mut first = true;
     
         for m in &moves {
-            let undo = board.make_move(m);
+            let child = board.play(m);
             let mut score;
             if first {
-                let (result, nodes) = self.pvs_impl(board, -beta, -alpha, depth - 1);
+                let (result, nodes) = self.pvs_impl(&child, -beta, -alpha, depth - 1, &mut line);
 
                 score = -result;
                 total_nodes += nodes;
             } else {
-                let (result, nodes) = self.pvs_impl(board, -alpha - 1, -alpha, depth - 1);
+                let (result, nodes) = self.pvs_impl(&child, -alpha - 1, -alpha, depth - 1, &mut line);
 
                 score = -result;
                 total_nodes += nodes;
 
+                // Re-search with the full window, so that the principal variation is exact.
                 if score > alpha && score < beta {
-                    let (result, nodes) = self.pvs_impl(board, -beta, -score, depth - 1);
+                    let (result, nodes) = self.pvs_impl(&child, -beta, -alpha, depth - 1, &mut line);
                     score = -result;
                     total_nodes += nodes;
                 }
             }
-            board.undo_move(undo, m);
-    
+
+            // The scores of a stopped search are meaningless, so must not reach the table.
+            if self.stopped() {
+                pv.clear();
+                return (alpha, total_nodes);
+            }
     
             if score > best_score {
                 best_score = score;
+                best_move = m;
                 if score > alpha {
                     alpha = score;
+                    pv.clear();
+                    pv.push(m);
+                    pv.extend_from_slice(&line);
                 }
             }
     
@@ -423,16 +601,79 @@ impl<E: Evaluator> NegamaxSearcher<E> {
             let node_score;
             if best_score < alpha_original {
                 node_score = Score::Upper(best_score);
-                self.hashtable.save(board, node_score, depth);
+                self.hashtable.save(board.hash_key(), node_score, depth, best_move);
             } else if best_score >= beta {
                 node_score = Score::Lower(best_score);
-                self.hashtable.save(board, node_score, depth);
+                self.hashtable.save(board.hash_key(), node_score, depth, best_move);
             } else if best_score > alpha_original && alpha < beta {
                 node_score = Score::Exact(best_score);
-                self.hashtable.save(board, node_score, depth);
+                self.hashtable.save(board.hash_key(), node_score, depth, best_move);
             }
         }
     
         (alpha, total_nodes)
     }
-}
\ No newline at end of file
+}
+
+impl<E: Evaluator + Send + Sync> Searcher for NegamaxSearcher<E> {
+    fn search(&mut self, board: &mut Board, limits: SearchLimits) -> SearchResult {
+        match limits {
+            SearchLimits { depth: Some(depth), time: None, nodes: None } => {
+                self.search_to_depth(board, depth.max(1))
+            }
+            SearchLimits { depth: None, time: Some(time), nodes: None } => {
+                self.search_to_time(board, time)
+            }
+            _ => iter_deep(board, limits, |b, depth| self.search_to_depth(b, depth))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::board::Board;
+    use crate::search::eval::PieceSquareEvaluator;
+    use crate::search::probcut::{ DepthPair, Fit, ProbCutParams };
+    use super::NegamaxSearcher;
+
+    #[test]
+    fn test_lazy_smp() {
+        let mut board = Board::new();
+
+        let mut searcher = NegamaxSearcher::with_eval(PieceSquareEvaluator::new());
+        searcher.set_verbose(0);
+        searcher.set_threads(4);
+
+        let result = searcher.search_to_depth(&mut board, 6);
+
+        assert!(board.get_moves().contains(result.best_move));
+        assert_eq!(result.pv[0], result.best_move);
+        assert_eq!(result.data.depth, 6);
+
+        // The helpers must leave the board untouched.
+        assert_eq!(board.hash_key(), Board::new().hash_key());
+    }
+
+    #[test]
+    fn test_probcut() {
+        let mut board = Board::new();
+        for _ in 0..10 {
+            let moves = board.get_moves();
+            board.make_move(moves[0]);
+        }
+
+        let mut searcher = NegamaxSearcher::with_eval(PieceSquareEvaluator::new());
+        searcher.set_verbose(0);
+        let full = searcher.search_to_depth(&mut board, 8);
+
+        // Assume the depth 2 search predicts the depth 6 one perfectly, in every stage.
+        let fit = Some(Fit { a: 1.0, b: 0.0, sigma: 0.0 });
+        let pair = DepthPair { deep: 6, shallow: 2, stages: vec![fit; 9] };
+        searcher.set_probcut(Some(ProbCutParams { threshold: 1.5, stage_size: 8, pairs: vec![pair] }));
+        searcher.clear_table();
+        let selective = searcher.search_to_depth(&mut board, 8);
+
+        assert!(board.get_moves().contains(selective.best_move));
+        assert!(selective.data.nodes < full.data.nodes);
+    }
+}
@@ -0,0 +1,254 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Parameters for Multi-ProbCut. For each pair of depths, the score of a deep search is modelled
+//! as a linear function of the score of a shallow search, `deep = a * shallow + b`, with normally
+//! distributed error `sigma`. The model is fitted separately for each stage of the game, since the
+//! evaluation gets more reliable as the board fills up.
+//!
+//! Samples are generated by the `pc-tune` subcommand, which prints one line per position with the
+//! deep depth, the shallow depth, the number of disks, the deep score and the shallow score.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+
+use crate::{ Error, Result };
+
+use serde::{ Deserialize, Serialize };
+use serde_json::{ from_reader };
+
+/// How many standard deviations a shallow score must be past a bound before a cut is made.
+pub const DEFAULT_THRESHOLD: f32 = 1.5;
+
+/// The number of disks in each stage of the game.
+pub const DEFAULT_STAGE_SIZE: u32 = 8;
+
+/// The minimum number of samples needed to fit a stage.
+const MIN_SAMPLES: usize = 30;
+
+/// Scores past this are wipeouts, which are not on the evaluation's scale and are left out of fits.
+const MAX_FIT_SCORE: i32 = 64 * 100;
+
+/// The fitted linear model for one stage of one depth pair.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fit {
+    pub a: f32,
+    pub b: f32,
+    pub sigma: f32
+}
+
+/// The fitted models for a single pair of depths, indexed by stage. Stages without enough
+/// samples have no model, and are never cut.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DepthPair {
+    pub deep: u8,
+    pub shallow: u8,
+    pub stages: Vec<Option<Fit>>
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProbCutParams {
+    pub threshold: f32,
+    pub stage_size: u32,
+    pub pairs: Vec<DepthPair>
+}
+
+/// A single sample from `pc-tune`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub deep: u8,
+    pub shallow: u8,
+    pub disks: u32,
+    pub deep_score: i32,
+    pub shallow_score: i32
+}
+
+impl Sample {
+    /// Parses a line of `pc-tune` output.
+    /// # Arguments:
+    /// * `line`: A line of the form `deep, shallow, disks, deep_score, shallow_score`.
+    /// # Returns:
+    /// * The parsed `Sample`, or `None` if the line is malformed.
+    pub fn from_line(line: &str) -> Option<Sample> {
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        if fields.len() != 5 {
+            return None;
+        }
+
+        Some(Sample {
+            deep: fields[0].parse().ok()?,
+            shallow: fields[1].parse().ok()?,
+            disks: fields[2].parse().ok()?,
+            deep_score: fields[3].parse().ok()?,
+            shallow_score: fields[4].parse().ok()?
+        })
+    }
+}
+
+impl ProbCutParams {
+    pub fn from_file(path: &str) -> Result<ProbCutParams> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let params: ProbCutParams = from_reader(reader)?;
+
+        params.validate()?;
+
+        Ok(params)
+    }
+
+    /// Checks that the parameters can be used to search, so that a bad file is reported when it
+    /// is loaded rather than failing during a search.
+    fn validate(&self) -> Result<()> {
+        if self.stage_size == 0 {
+            return Err(Error::InvalidWeights("stage_size must be positive".to_string()));
+        }
+
+        let num_stages = (64 / self.stage_size + 1) as usize;
+        for pair in &self.pairs {
+            if pair.stages.len() > num_stages {
+                return Err(Error::InvalidWeights(format!(
+                    "depth pair {}/{} has {} stages, but a stage size of {} allows at most {}",
+                    pair.deep, pair.shallow, pair.stages.len(), self.stage_size, num_stages
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fits a model for every depth pair and stage in the given samples, using least squares.
+    /// # Arguments:
+    /// * `samples`: The samples to fit.
+    /// * `threshold`: The cut threshold to store with the parameters.
+    /// * `stage_size`: The number of disks in each stage.
+    /// # Returns:
+    /// * The fitted `ProbCutParams`, with the depth pairs sorted by deep depth then shallow depth.
+    pub fn fit(samples: &[Sample], threshold: f32, stage_size: u32) -> ProbCutParams {
+        let num_stages = (64 / stage_size + 1) as usize;
+
+        let mut groups: BTreeMap<(u8, u8), Vec<Vec<Sample>>> = BTreeMap::new();
+        for sample in samples {
+            let stages = groups.entry((sample.deep, sample.shallow)).or_insert_with(|| vec![Vec::new(); num_stages]);
+            stages[(sample.disks / stage_size) as usize].push(*sample);
+        }
+
+        let pairs = groups.into_iter().map(|((deep, shallow), stages)| {
+            DepthPair {
+                deep,
+                shallow,
+                stages: stages.iter().map(|stage| fit_stage(stage)).collect()
+            }
+        }).collect();
+
+        ProbCutParams {
+            threshold,
+            stage_size,
+            pairs
+        }
+    }
+
+    /// Returns the depth pairs which apply at the given depth, along with the model for the stage.
+    /// # Arguments:
+    /// * `depth`: The depth of the node, which is matched against the deep depth of each pair.
+    /// * `disks`: The number of disks on the board.
+    pub fn cuts(&self, depth: u8, disks: u32) -> impl Iterator<Item = (u8, Fit)> + '_ {
+        let stage = (disks / self.stage_size) as usize;
+
+        self.pairs.iter()
+            .filter(move |pair| pair.deep == depth)
+            .filter_map(move |pair| pair.stages.get(stage).cloned().flatten().map(|fit| (pair.shallow, fit)))
+    }
+}
+
+/// Fits `deep = a * shallow + b` to the samples, or returns `None` if there are too few of them.
+/// Samples where either score is a wipeout are ignored.
+fn fit_stage(samples: &[Sample]) -> Option<Fit> {
+    let samples: Vec<Sample> = samples.iter()
+        .filter(|s| s.deep_score.abs() <= MAX_FIT_SCORE && s.shallow_score.abs() <= MAX_FIT_SCORE)
+        .cloned()
+        .collect();
+
+    if samples.len() < MIN_SAMPLES {
+        return None;
+    }
+
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|s| f64::from(s.shallow_score)).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|s| f64::from(s.deep_score)).sum::<f64>() / n;
+
+    let mut cov = 0.0;
+    let mut var = 0.0;
+    for s in &samples {
+        let dx = f64::from(s.shallow_score) - mean_x;
+        cov += dx * (f64::from(s.deep_score) - mean_y);
+        var += dx * dx;
+    }
+
+    if var == 0.0 {
+        return None;
+    }
+
+    let a = cov / var;
+    let b = mean_y - a * mean_x;
+    let residuals = samples.iter().map(|s| {
+        let error = f64::from(s.deep_score) - (a * f64::from(s.shallow_score) + b);
+        error * error
+    }).sum::<f64>();
+
+    Some(Fit {
+        a: a as f32,
+        b: b as f32,
+        sigma: (residuals / n).sqrt() as f32
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ DepthPair, ProbCutParams, Sample };
+    use crate::Error;
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(Sample::from_line("7, 3, 20, 150, -40"), Some(Sample { deep: 7, shallow: 3, disks: 20, deep_score: 150, shallow_score: -40 }));
+        assert_eq!(Sample::from_line("Searched 100 nodes"), None);
+
+        // deep = 2 * shallow + 10, plus or minus 5.
+        let mut samples: Vec<Sample> = (0..100).map(|i| {
+            let shallow_score = i * 10 - 500;
+            let noise = if i % 2 == 0 { 5 } else { -5 };
+            Sample { deep: 7, shallow: 3, disks: 20, deep_score: 2 * shallow_score + 10 + noise, shallow_score }
+        }).collect();
+
+        // Wipeouts should not affect the fit.
+        samples.push(Sample { deep: 7, shallow: 3, disks: 20, deep_score: i32::MAX, shallow_score: 300 });
+        samples.push(Sample { deep: 7, shallow: 3, disks: 20, deep_score: -i32::MAX, shallow_score: -i32::MAX });
+
+        let params = ProbCutParams::fit(&samples, 1.5, 8);
+        assert_eq!(params.pairs.len(), 1);
+        assert_eq!(params.pairs[0].stages.len(), 9);
+
+        let cuts: Vec<_> = params.cuts(7, 20).collect();
+        assert_eq!(cuts.len(), 1);
+        let (shallow, fit) = cuts[0];
+        assert_eq!(shallow, 3);
+        assert!((fit.a - 2.0).abs() < 0.01);
+        assert!((fit.b - 10.0).abs() < 1.0);
+        assert!((fit.sigma - 5.0).abs() < 0.1);
+
+        // Other stages and depths have no samples, so they are never cut.
+        assert_eq!(params.cuts(7, 40).count(), 0);
+        assert_eq!(params.cuts(6, 20).count(), 0);
+        assert!(params.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate() {
+        let params = ProbCutParams::fit(&[], 1.5, 8);
+        assert!(matches!(ProbCutParams { stage_size: 0, ..params.clone() }.validate(), Err(Error::InvalidWeights(_))));
+
+        let pair = DepthPair { deep: 7, shallow: 3, stages: vec![None; 10] };
+        assert!(matches!(ProbCutParams { pairs: vec![pair], ..params }.validate(), Err(Error::InvalidWeights(_))));
+    }
+}